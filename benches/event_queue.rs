@@ -0,0 +1,147 @@
+//! Compare the future event list of the simulation, an indexed heap, with a
+//! plain `BinaryHeap`, that needs to be rebuilt to remove an event given its
+//! id.
+//!
+//! Both queues hold the same entries, ordered by time and then by id, and
+//! are driven directly, without a simulation around them.
+#![feature(test)]
+extern crate desim;
+extern crate test;
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use test::Bencher;
+
+use desim::{Event, EventId, ProcessId, RawEventQueue};
+
+/// Number of events removed, and scheduled again, in the removal benchmarks.
+const REMOVED: usize = 100;
+
+fn events(n: usize) -> Vec<Event> {
+    (0..n)
+        .map(|i| Event {
+            time: ((i * 7919) % n) as f64,
+            process: ProcessId(0),
+            label: None,
+        })
+        .collect()
+}
+
+fn heap_push_pop(b: &mut Bencher, n: usize) {
+    let events = events(n);
+    b.iter(|| {
+        let mut heap = BinaryHeap::new();
+        for (id, e) in events.iter().enumerate() {
+            heap.push(Reverse((*e, id)));
+        }
+        while let Some(e) = heap.pop() {
+            test::black_box(e);
+        }
+    });
+}
+
+fn indexed_push_pop(b: &mut Bencher, n: usize) {
+    let events = events(n);
+    b.iter(|| {
+        let mut queue = RawEventQueue::new();
+        for e in events.iter() {
+            queue.push(*e);
+        }
+        while let Some(e) = queue.pop() {
+            test::black_box(e);
+        }
+    });
+}
+
+fn heap_remove(b: &mut Bencher, n: usize) {
+    let events = events(n);
+    let mut heap: BinaryHeap<_> = events.iter().enumerate().map(|(id, e)| Reverse((*e, id))).collect();
+    let mut ids: Vec<EventId> = (0..n).collect();
+    let mut next_id = n;
+    b.iter(|| {
+        for i in 0..REMOVED {
+            let k = i * (n / REMOVED);
+            let id = ids[k];
+            let old = std::mem::take(&mut heap);
+            heap = old.into_iter().filter(|o| (o.0).1 != id).collect();
+            // put it back for the next iteration
+            heap.push(Reverse((events[k], next_id)));
+            ids[k] = next_id;
+            next_id += 1;
+        }
+    });
+}
+
+fn indexed_remove(b: &mut Bencher, n: usize) {
+    let events = events(n);
+    let mut queue = RawEventQueue::new();
+    let mut ids: Vec<_> = events.iter().map(|e| queue.push(*e)).collect();
+    b.iter(|| {
+        for i in 0..REMOVED {
+            let k = i * (n / REMOVED);
+            let e = queue.remove(ids[k]).unwrap();
+            // put it back for the next iteration
+            ids[k] = queue.push(e);
+        }
+    });
+}
+
+#[bench]
+fn heap_push_pop_10k(b: &mut Bencher) {
+    heap_push_pop(b, 10_000);
+}
+
+#[bench]
+fn heap_push_pop_100k(b: &mut Bencher) {
+    heap_push_pop(b, 100_000);
+}
+
+#[bench]
+fn heap_push_pop_1m(b: &mut Bencher) {
+    heap_push_pop(b, 1_000_000);
+}
+
+#[bench]
+fn indexed_push_pop_10k(b: &mut Bencher) {
+    indexed_push_pop(b, 10_000);
+}
+
+#[bench]
+fn indexed_push_pop_100k(b: &mut Bencher) {
+    indexed_push_pop(b, 100_000);
+}
+
+#[bench]
+fn indexed_push_pop_1m(b: &mut Bencher) {
+    indexed_push_pop(b, 1_000_000);
+}
+
+#[bench]
+fn heap_remove_10k(b: &mut Bencher) {
+    heap_remove(b, 10_000);
+}
+
+#[bench]
+fn heap_remove_100k(b: &mut Bencher) {
+    heap_remove(b, 100_000);
+}
+
+#[bench]
+fn heap_remove_1m(b: &mut Bencher) {
+    heap_remove(b, 1_000_000);
+}
+
+#[bench]
+fn indexed_remove_10k(b: &mut Bencher) {
+    indexed_remove(b, 10_000);
+}
+
+#[bench]
+fn indexed_remove_100k(b: &mut Bencher) {
+    indexed_remove(b, 100_000);
+}
+
+#[bench]
+fn indexed_remove_1m(b: &mut Bencher) {
+    indexed_remove(b, 1_000_000);
+}
@@ -5,7 +5,7 @@ extern crate desim;
 
 use rand::{Rng as RngT, XorShiftRng as Rng};
 
-use desim::{Simulation, Effect, Event, Context};
+use desim::{Simulation, Effect, Event, Context, ProcessId, QueueDiscipline};
 use std::rc::Rc;
 
 enum Message {
@@ -16,8 +16,8 @@ enum Message {
 fn main(){
     let ctx = Rc::new(Context::<Message>::new());
     let mut s = Simulation::new(ctx);
-    let cpu = s.create_resource(1);
-    s.create_process(1, Box::new(move || {
+    let cpu = s.create_resource(1, QueueDiscipline::Fifo);
+    s.create_process(ProcessId(1), Box::new(move || {
         for _ in 0..10 {
             // wait for the cpu to be available
             yield Effect::Request(cpu);
@@ -27,7 +27,7 @@ fn main(){
             yield Effect::Release(cpu);
         }
     }));
-    s.create_process(2, Box::new(move || {
+    s.create_process(ProcessId(2), Box::new(move || {
         let mut rng = Rng::new_unseeded();
         loop{
             // wait for the CPU
@@ -39,7 +39,7 @@ fn main(){
         }
     }));
     // let p1 to start immediately...
-    s.schedule_event(Event{time: 0.0, process: 1});
+    s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
     // ...and p2 after 17 time units
-    s.schedule_event(Event{time: 17.0, process: 2});
+    s.schedule_event(Event{time: 17.0, process: ProcessId(2), label: None}).unwrap();
 }
@@ -25,11 +25,33 @@ along with this program.  If not, see <http://www.gnu.org/licenses/>. */
 //! consider some kind of finite resource that must be shared among
 //! the processes, e.g. a bunch of servers in a simulation on queues.
 //!
+//! The simulation can be set up by hand or with chained calls on a
+//! `SimulationBuilder`.
+//!
 //! After setting up the simulation, it can be run step-by-step, using
 //! the `step()` method, or all at once, with `run()`, until and ending
 //! condition is met.
 //!
-//! The simulation will generate a log of all the events.
+//! The simulation will generate a log of all the events. Events scheduled
+//! for the same time are processed in the order they were scheduled, unless
+//! the `RoundRobin` scheduling policy is selected to resume in turn the
+//! processes that have events at the same time.
+//!
+//! The simulation time is represented by default with `f64`, but any type
+//! implementing the `SimTime` trait can be used instead, e.g. `u64` for
+//! models based on discrete ticks.
+//!
+//! The state of a simulation, except the processes, can be saved with
+//! `checkpoint` and used to create a new simulation with `from_checkpoint`.
+//! With the `serde` feature enabled, checkpoints can be serialized.
+//!
+//! A simulation, with its `Context` and processes, lives in a single thread,
+//! so it can not be moved to another one. Independent simulations, e.g. for
+//! a parameter sweep, can still run in parallel by building each of them
+//! inside the thread that runs it, and sending back only the results.
+//! With the `sync` feature enabled, a `SyncContext` can be shared between
+//! threads and attached to a simulation, to read its clock and the series
+//! recorded by its processes from other threads.
 //!
 /*
 //! `nonblocking_run` lets you run the simulation in another thread
@@ -44,504 +66,10049 @@ along with this program.  If not, see <http://www.gnu.org/licenses/>. */
 //!
 //! When a new process is created in the simulation, an identifier, of type
 //! `ProcessId` is assigned to it. That id can be used to schedule an event that
-//! resume the process.
+//! resume the process. Process and resource identifiers wrap a number in
+//! distinct types, e.g. `ProcessId(1)`, so that one can not be passed where
+//! the other is expected.
 //!
 //! A process can be stopped and resumed later on. To stop the process, the
 //! generator yields an `Effect` that specify what the simulator should do.
 //! For example, a generator can set a timeout after witch it is executed again.
 //! The process may also return. In that case it can not be resumed anymore.
+//! The value it returns, of the type chosen with the last type parameter of
+//! `Context` and `Simulation` (`()` by default), is kept by the simulation
+//! and can be retrieved with `take_result`.
+//!
+//! New processes can also be created while the simulation is running, from
+//! inside another process, using the `spawn` method of the `Context`. A
+//! spawned process is scheduled to start at the current simulation time.
+//!
+//! # Signals
+//! A signal, created with `Context::create_signal`, lets any number of
+//! processes wait, yielding `WaitForSignal`, until another process or the
+//! owner of the simulation fires it with `Context::fire_signal`. Firing the
+//! signal resumes all the processes waiting for it at that moment, e.g. to
+//! implement a barrier.
+//!
+//! # Channels
+//! The messages sent with `SendMessage` all have the type of the `Context`.
+//! A `Channel`, created with `Channel::new`, carries messages of its own type
+//! between processes: a `Sender` returns the effect that delivers a message
+//! to the receiving process and resumes it, and the process reads it with
+//! its `Receiver`.
+//!
+//! # Random numbers
+//! The `Context` contains a seedable pseudo-random number generator that
+//! processes can use to draw, e.g., random service or interarrival times, so
+//! that a simulation can be reproduced by using the same seed.
 //!
 //!
 //! # Resource
 //! A resource is a finite amount of entities that can be used by one process
 //! a time. When all the instances of the resource of interest are being used by
-//! a process, the requiring one is enqueued in a FIFO and is resumed when the
+//! a process, the requiring one is enqueued and is resumed when the
 //! resource become available again. When the process does not need the resource
 //! anymore, it must release it.
 //!
 //! A resource can be created in the simulation using the `create_resource`
-//! method, which requires the amount of resource and returns an identifier
-//! for that resource that can be used to require and release it.
+//! method, which requires the amount of resource and the `QueueDiscipline`
+//! used to order the waiting processes (FIFO, LIFO or by priority) and
+//! returns an identifier for that resource that can be used to require and
+//! release it.
 //!
 //! A resource can be required and reelased by a process yielding
-//! the corresponding `Effect`. There is no check on the fact that a process
-//! yielding `Release` was holding a resource with that ID, but if a resource
-//! gets more release then requests, the simulation will panic.
+//! the corresponding `Effect`. By default there is no check on the fact that
+//! a process yielding `Release` was holding a resource with that ID, but if a
+//! resource gets more release then requests, the simulation will panic.
+//! With `enable_hold_tracking`, the units held by each process are tracked
+//! and a release without a hold is reported as an error by `step`.
+//!
+//! A resource can also be required in shared mode, yielding `RequestShared`,
+//! to implement a readers-writer lock: any number of shared holders can use
+//! the resource at the same time as long as no process holds it exclusively,
+//! while an exclusive `Request` is granted only when there are no shared
+//! holders. A resource obtained in shared mode must be released with
+//! `ReleaseShared`.
+//!
+//! # Pool
+//! A pool models a divisible capacity, like bandwidth or a share of a CPU,
+//! that processes reserve in arbitrary amounts. It is created with the
+//! `create_pool` method and a process can reserve and give back part of it
+//! yielding `Reserve` and `Unreserve`. When the requested amount is not
+//! available, the process is enqueued in a FIFO and is resumed as soon as
+//! enough capacity is given back by other processes. A rate limiter can be
+//! modeled with `create_token_bucket`, a pool refilled at a fixed rate.
+//!
+//! # Store
+//! A store holds distinct items, of the type of the messages of the
+//! `Context`, e.g. the parts of an inventory. It is created with the
+//! `create_store` method and a process adds an item yielding `Put` and takes
+//! the first item that matches a filter yielding `Get`, waiting until one is
+//! put if there is none.
 //!
 
 #![feature(generators, generator_trait)]
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 use std::ops::{Generator, GeneratorState};
-use std::collections::{BinaryHeap, VecDeque, HashMap, HashSet};
-use std::cmp::{Ordering, Reverse};
+use std::collections::{VecDeque, HashMap, HashSet};
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display};
+use std::error::Error;
+use std::ops::{Add, Sub};
 use std::thread;
 use std::pin::Pin;
+use std::panic::{self, AssertUnwindSafe};
+use std::any::Any;
+#[cfg(feature = "sync")]
+use std::sync::{Arc, Mutex};
 use std::rc::Rc;
 use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
 
 /// The effect is yelded by a process generator to
 /// interact with the simulation environment.
-#[derive(Debug, Copy, Clone)]
-pub enum Effect<T> {
+#[derive(Debug, Clone)]
+pub enum Effect<T, Time = f64, R = ()> {
     /// The process that yields this effect will be resumed
-    /// after the speified time
-    TimeOut(f64),
-    /// Yielding this effect it is possible to schedule the specified event
-    Event(Event),
+    /// after the speified time.
+    ///
+    /// A `TimeOut(0.0)` lets the other processes scheduled at the current
+    /// time run and resumes the process without advancing the time, but
+    /// `Delay` states that intent more clearly.
+    /// The simulation panics if the time is negative or NaN.
+    TimeOut(Time),
+    /// Give way to the other processes scheduled at the current time: the
+    /// process is resumed after them, without advancing the time.
+    ///
+    /// Events for the same time are processed in the order they were
+    /// scheduled, so the process runs again after every event already queued
+    /// for this instant, while the ones scheduled later on come after it.
+    Delay,
+    /// Resume the given process at the current time and then the one
+    /// yielding this effect, after the events already queued for this
+    /// instant, e.g. for coroutines passing control back and forth. The
+    /// target is usually blocked by `Wait`.
+    YieldTo(ProcessId),
+    /// The process that yields this effect will be resumed at the specified
+    /// absolute time, or immediately if that time is not in the future (in
+    /// that case a warning is printed in debug builds), e.g. for shift
+    /// changes happening at fixed times.
+    SleepUntil(Time),
+    /// Like `TimeOut` with the first delay, but the process fires no faster
+    /// than every minimum spacing, the second value: it is resumed at the
+    /// latest between the end of the delay and its previous resumption from
+    /// this effect plus the spacing, e.g. for a rate-limited source.
+    TimeOutThrottled(Time, Time),
+    /// Yielding this effect it is possible to schedule the specified event.
+    /// Its time is relative to the current one, i.e. a delay.
+    Event(Event<Time>),
+    /// Schedule the specified event at the absolute time it carries, as
+    /// `Simulation::schedule_event` does. The simulation fails with a
+    /// `TimeError` if that time is in the past.
+    AbsoluteEvent(Event<Time>),
     /// This effect is yielded to request a resource
     Request(ResourceId),
+    /// Request a resource, hold it for the given time once obtained, then
+    /// release it: the process is resumed after the release, as after
+    /// `Request`, `TimeOut` and `Release`. If interrupted while waiting, the
+    /// process is resumed as after a `Request`. Messages delivered while
+    /// waiting are kept in the inbox without resuming the process.
+    Hold(ResourceId, Time),
+    /// This effect is yielded to request a resource with the given priority.
+    /// If the queue discipline of the resource is `Priority`, waiters with
+    /// a lower value are served first. Plain requests have priority 0.
+    PriorityRequest(ResourceId, u32),
+    /// Request a resource with the given priority, evicting, if no unit is
+    /// available, the holder with the highest priority value, provided it is
+    /// higher than the requested one. The evicted process is interrupted and
+    /// resumed now, and must request the resource again: its release of the
+    /// unit it lost is ignored. Holders with the same or a lower value are
+    /// not evicted and the process waits in the queue as with
+    /// `PriorityRequest`. See `Context::preemption_elapsed` to resume the
+    /// work of the evicted process.
+    PreemptRequest(ResourceId, u32),
     /// This effect is yielded to release a resource that is not needed anymore.
     Release(ResourceId),
+    /// Release the resource after the given delay, going on in the meantime:
+    /// the process is resumed immediately and not when the resource is
+    /// released. It replaces a `TimeOut` followed by a `Release`.
+    ReleaseAfter(ResourceId, Time),
+    /// This effect is yielded to request a resource in shared mode
+    RequestShared(ResourceId),
+    /// This effect is yielded to release a resource held in shared mode.
+    ReleaseShared(ResourceId),
+    /// Change the capacity of a resource, see `Simulation::set_resource_capacity`.
+    SetCapacity(ResourceId, usize),
+    /// This effect is yielded to reserve the specified amount of a pool
+    Reserve(PoolId, f64),
+    /// This effect is yielded to give back an amount of a pool previously reserved.
+    Unreserve(PoolId, f64),
     /// Keep the process' state until it is resumed by another event.
     Wait,
-    /// Interrupt another process
+    /// Same as `Wait`, for a process meant to be resumed by the owner of the
+    /// simulation with `Simulation::wake_process`.
+    Suspend,
+    /// Interrupt another process. It is resumed now, and the end of its
+    /// `TimeOut` or `SleepUntil`, if any, is cancelled. If it is waiting for
+    /// a resource or a pool, it is taken out of the queue without obtaining it.
     Interrupt(ProcessId),
+    /// Interrupt another process, giving it the reason as a payload that it
+    /// obtains with `Context::check_interrupted`.
+    InterruptWith(ProcessId, T),
+    /// Interrupt, as `Interrupt` does, every live process in the group but
+    /// the one yielding the effect, e.g. the children of a supervisor.
+    InterruptGroup(GroupId),
     /// Send message to process (with latency)
-    SendMessage(ProcessId, T, f64)
+    SendMessage(ProcessId, T, Time),
+    /// Send message to process (with latency) and priority: it is read
+    /// before the messages with a higher priority value, see
+    /// `Context::push_message_with_priority`.
+    SendMessagePriority(ProcessId, T, Time, u32),
+    /// Send a message to a process at the current time, resuming the sender
+    /// before the message is delivered: unlike with `SendMessage` and no
+    /// latency, the recipient runs after the sender has gone on, also with the
+    /// `RoundRobin` scheduling policy, where the sender goes on even if it
+    /// already ran in the current round. The order is not guaranteed with a
+    /// custom ordering, see `Simulation::with_ordering`.
+    SendMessageNow(ProcessId, T),
+    /// Deliver a message of a `Channel`, built with `Sender::send`: the
+    /// message is put in the queue of the receiver, which is resumed now, as
+    /// with a `SendMessage` without latency, then the sender.
+    ChannelSend(ChannelMessage),
+    /// Keep the process' state until there is a message for it. If a message
+    /// is already in its inbox, the process is resumed immediately, otherwise
+    /// it is resumed when a message is sent to it.
+    WaitForMessage,
+    /// Keep the process' state until there are at least the given number of
+    /// messages in its inbox, e.g. for a coordinator collecting the reports
+    /// of its workers. The messages sent with `SendMessage` before that do
+    /// not resume the process.
+    Gather(usize),
+    /// Apply all the given effects, in order, at the current time, then
+    /// resume the process once, e.g. to send several messages without
+    /// letting other processes run in between.
+    ///
+    /// Only the effects that do not block the process are supported:
+    /// `Event` (which here does resume the process), `AbsoluteEvent`,
+    /// `Release`, `ReleaseShared`, `Unreserve`, `Put`, `SendMessage`,
+    /// `SendMessagePriority`, `Interrupt`, `InterruptWith` and `Now`.
+    Collect(Vec<Effect<T, Time, R>>),
+    /// Keep the process' state until the signal is fired with
+    /// `Context::fire_signal`.
+    WaitForSignal(SignalId),
+    /// Keep the process' state until the given process completes or is
+    /// cancelled, e.g. to wait for a child process. How it ended can be read
+    /// with `Context::join_outcome`; a process that does not exist counts as
+    /// cancelled.
+    Join(ProcessId),
+    /// Wait for the first of the given effects to happen. The process is
+    /// resumed as soon as one of them fires and the others are cancelled.
+    /// The index of the one that fired can be read with
+    /// `Context::selected_branch`.
+    ///
+    /// Only `Request`, `Get` and `TimeOut` are supported, e.g.
+    /// `Select(vec![Request(r), TimeOut(5.0)])` requests a resource giving up
+    /// after 5 time units.
+    Select(Vec<Effect<T, Time, R>>),
+    /// Request any one of the given resources, e.g. interchangeable servers.
+    /// The process obtains the first that is available, as with a `Select`
+    /// of `Request`s, and can read which one with `Context::granted_resource`
+    /// to release it later.
+    RequestAny(Vec<ResourceId>),
+    /// Request a resource, reneging if it is not obtained within the given
+    /// patience: the process then leaves the queue and is resumed anyway.
+    /// This is `Select(vec![Request(r), TimeOut(patience)])`; whether the
+    /// process was served can be read with `Context::acquisition_outcome`.
+    RequestWithPatience(ResourceId, Time),
+    /// Request a resource, giving up if it is not obtained within the given
+    /// time, as `RequestWithPatience` does. Whether the process obtained it
+    /// can be read with `Context::last_request_outcome`.
+    RequestWithTimeout(ResourceId, Time),
+    /// Request all the given, distinct, resources at once. The process is
+    /// resumed when all of them are available and, in the meantime, holds
+    /// none of them, so that processes requesting the same resources in a
+    /// different order can not deadlock each other.
+    ///
+    /// The process waits in the queue of each resource and obtains them when
+    /// it reaches the front of all the queues. The resources must be released
+    /// one by one.
+    RequestAll(Vec<ResourceId>),
+    /// Take from the store the first item, in the order they were put, that
+    /// matches the filter, waiting until one is put if there is none. The
+    /// process reads the item with `Context::take_item`.
+    Get(StoreId, ItemFilter<T>),
+    /// Put an item in the store, serving the processes waiting for it.
+    Put(StoreId, T),
+    /// Invoke the action with the `Context` of the process, then resume the
+    /// process at the same time, e.g. to update a metric when the scheduler
+    /// handles the effect rather than in the body of the generator.
+    Now(Action<T, Time, R>),
 }
 
-/// Identifies a process. Can be used to resume it from another one and to schedule it.
-pub type ProcessId = usize;
-/// Identifies a resource. Can be used to request and release it.
-pub type ResourceId = usize;
-
-#[derive(Debug)]
-struct Resource {
-    allocated: usize,
-    available: usize,
-    queue: VecDeque<ProcessId>,
-}
+/// Returns whether the `Debug` representation of the value is `expected`,
+/// without writing it to a new string.
+fn debug_matches<D: Debug>(value: &D, expected: &str) -> bool {
+    use std::fmt::Write;
 
-pub struct Context<T> {
-    time: Cell<f64>,
-    messages: RefCell<HashMap<ProcessId, VecDeque<T>>>,
-    interrupted: RefCell<HashSet<ProcessId>>
-}
+    struct Matcher<'a>(&'a str);
 
-impl<T> Context<T> {
-    /// Create a new `Context` environment.
-    pub fn new() -> Context<T> {
-        Context::default()
+    impl<'a> Write for Matcher<'a> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            if !self.0.starts_with(s) {
+                return Err(fmt::Error);
+            }
+            self.0 = &self.0[s.len()..];
+            Ok(())
+        }
     }
 
-    /// Returns the current simulation time
-    pub fn time(&self) -> f64 {
-        self.time.get()
-    }
+    let mut rest = Matcher(expected);
+    write!(rest, "{:?}", value).is_ok() && rest.0.is_empty()
+}
 
-    pub fn push_message(&self, pid: ProcessId, message: T) {
-        let mut m = self.messages.borrow_mut();
+/// Write a list of ids or effects separated by commas.
+fn write_list<I: Display>(f: &mut fmt::Formatter, items: &[I]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
 
-        match m.get_mut(&pid) {
-            Some(vd) => vd.push_back(message),
-            None => {
-                let mut vd = VecDeque::default();
-                vd.push_back(message);
-                m.insert(pid, vd);
+/// Processes are written as `#pid`, resources, pools and signals by their id.
+impl<T: Display, Time: Debug, R> Display for Effect<T, Time, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Effect::TimeOut(t) => write!(f, "TimeOut({:?})", t),
+            Effect::SleepUntil(t) => write!(f, "SleepUntil({:?})", t),
+            Effect::TimeOutThrottled(t, spacing) => write!(f, "TimeOutThrottled({:?}, {:?})", t, spacing),
+            Effect::Event(e) => write!(f, "Event({})", e),
+            Effect::AbsoluteEvent(e) => write!(f, "AbsoluteEvent({})", e),
+            Effect::Request(r) => write!(f, "Request({})", r),
+            Effect::Hold(r, t) => write!(f, "Hold({}, {:?})", r, t),
+            Effect::PriorityRequest(r, p) => write!(f, "PriorityRequest({}, {})", r, p),
+            Effect::PreemptRequest(r, p) => write!(f, "PreemptRequest({}, {})", r, p),
+            Effect::Release(r) => write!(f, "Release({})", r),
+            Effect::ReleaseAfter(r, t) => write!(f, "ReleaseAfter({}, {:?})", r, t),
+            Effect::RequestShared(r) => write!(f, "RequestShared({})", r),
+            Effect::ReleaseShared(r) => write!(f, "ReleaseShared({})", r),
+            Effect::SetCapacity(r, c) => write!(f, "SetCapacity({}, {})", r, c),
+            Effect::Reserve(p, a) => write!(f, "Reserve({}, {:?})", p, a),
+            Effect::Unreserve(p, a) => write!(f, "Unreserve({}, {:?})", p, a),
+            Effect::Wait => write!(f, "Wait"),
+            Effect::Suspend => write!(f, "Suspend"),
+            Effect::Delay => write!(f, "Delay"),
+            Effect::YieldTo(p) => write!(f, "YieldTo(#{})", p),
+            Effect::Interrupt(p) => write!(f, "Interrupt(#{})", p),
+            Effect::InterruptWith(p, m) => write!(f, "InterruptWith(#{}, {})", p, m),
+            Effect::InterruptGroup(g) => write!(f, "InterruptGroup({})", g),
+            Effect::SendMessage(p, m, t) => write!(f, "SendMessage(#{}, {}, {:?})", p, m, t),
+            Effect::SendMessageNow(p, m) => write!(f, "SendMessageNow(#{}, {})", p, m),
+            Effect::ChannelSend(m) => write!(f, "ChannelSend(#{})", m.to),
+            Effect::SendMessagePriority(p, m, t, prio) => {
+                write!(f, "SendMessagePriority(#{}, {}, {:?}, {})", p, m, t, prio)
+            }
+            Effect::WaitForMessage => write!(f, "WaitForMessage"),
+            Effect::Gather(n) => write!(f, "Gather({})", n),
+            Effect::WaitForSignal(s) => write!(f, "WaitForSignal({})", s),
+            Effect::Join(p) => write!(f, "Join(#{})", p),
+            Effect::Select(effects) => {
+                write!(f, "Select([")?;
+                write_list(f, effects)?;
+                write!(f, "])")
+            }
+            Effect::Collect(effects) => {
+                write!(f, "Collect([")?;
+                write_list(f, effects)?;
+                write!(f, "])")
             }
+            Effect::RequestAny(resources) => {
+                write!(f, "RequestAny([")?;
+                write_list(f, resources)?;
+                write!(f, "])")
+            }
+            Effect::RequestWithPatience(r, t) => write!(f, "RequestWithPatience({}, {:?})", r, t),
+            Effect::RequestWithTimeout(r, t) => write!(f, "RequestWithTimeout({}, {:?})", r, t),
+            Effect::RequestAll(resources) => {
+                write!(f, "RequestAll([")?;
+                write_list(f, resources)?;
+                write!(f, "])")
+            }
+            Effect::Get(s, _) => write!(f, "Get({})", s),
+            Effect::Put(s, item) => write!(f, "Put({}, {})", s, item),
+            Effect::Now(_) => write!(f, "Now"),
         }
     }
+}
 
-    pub fn pop_message(&self, pid: ProcessId) -> Option<T> {
-        match self.messages.borrow_mut().get_mut(&pid) {
-            Some(vd) => vd.pop_front(),
-            None => None
-        }
+/// The type used to represent the simulation time, both points in time and
+/// intervals between them.
+///
+/// It is implemented for `f64`, the default, and for `u64`, e.g. to count
+/// discrete ticks. `Default::default()` must be the zero, i.e. the time at
+/// which every simulation starts.
+pub trait SimTime: Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self> + Default + Debug {
+    /// Convert the time to `f64`, to compute statistics.
+    fn to_f64(self) -> f64;
+
+    /// Round the time to the nearest multiple of `resolution`, see
+    /// `Simulation::set_time_resolution`. By default the time is kept as is.
+    fn round_to(self, resolution: Self) -> Self {
+        let _ = resolution;
+        self
     }
+}
 
-    pub fn interrupt(&self, pid: ProcessId) {
-        self.interrupted.borrow_mut().insert(pid);
+impl SimTime for f64 {
+    fn to_f64(self) -> f64 {
+        self
     }
 
-    pub fn check_interrupted(&self, pid: ProcessId) -> bool {
-        self.interrupted.borrow_mut().remove(&pid)
+    fn round_to(self, resolution: f64) -> f64 {
+        (self / resolution).round() * resolution
     }
 }
 
+impl SimTime for u64 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
 
-impl<T> Default for Context<T> {
-    fn default() -> Self {
-        Context {
-            time: Cell::new(0.0),
-            messages: RefCell::new(HashMap::default()),
-            interrupted: RefCell::new(HashSet::default())
-        }
+    fn round_to(self, resolution: u64) -> u64 {
+        (self + resolution / 2) / resolution * resolution
     }
 }
 
-/// This struct provides the methods to create and run the simulation
-/// in a single thread.
+/// A point in simulation time, for the typed API of the simulations with
+/// `f64` time: only a `SimDuration` can be added to it, so that absolute
+/// times and delays can not be mixed up.
 ///
-/// It provides methods to create processes and finite resources that
-/// must be shared among them.
-///
-/// See the crate-level documentation for more information about how the
-/// simulation framework works
-pub struct Simulation<T> {
-    context: Rc<Context<T>>,
-    processes: HashMap<ProcessId, Option<Box<dyn Generator<Yield = Effect<T>, Return = ()> + Unpin>>>,
-    future_events: BinaryHeap<Reverse<Event>>,
-    processed_events: Vec<Event>,
-    resources: Vec<Resource>,
+/// ```compile_fail
+/// use desim::SimInstant;
+/// let t = SimInstant(1.0) + SimInstant(2.0);
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default, PartialEq, PartialOrd)]
+pub struct SimInstant(pub f64);
+
+/// A time interval, for the typed API of the simulations with `f64` time,
+/// see `SimInstant`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default, PartialEq, PartialOrd)]
+pub struct SimDuration(pub f64);
+
+impl Add<SimDuration> for SimInstant {
+    type Output = SimInstant;
+
+    fn add(self, d: SimDuration) -> SimInstant {
+        SimInstant(self.0 + d.0)
+    }
 }
 
-/*
-pub struct ParallelSimulation {
-    processes: Vec<Box<Generator<Yield = Effect, Return = ()>>>
+impl Sub<SimDuration> for SimInstant {
+    type Output = SimInstant;
+
+    fn sub(self, d: SimDuration) -> SimInstant {
+        SimInstant(self.0 - d.0)
+    }
 }
- */
 
-/// An event that can be scheduled by a process, yelding the `Event` `Effect`
-/// or by the owner of a `Simulation` through the `schedule` method
-#[derive(Debug, Copy, Clone)]
-pub struct Event {
-    /// Time interval between the current simulation time and the event schedule
-    pub time: f64,
-    /// Process to execute when the event occur
-    pub process: ProcessId,
+impl Sub for SimInstant {
+    type Output = SimDuration;
+
+    fn sub(self, other: SimInstant) -> SimDuration {
+        SimDuration(self.0 - other.0)
+    }
 }
 
-/// Specify which condition must be met for the simulation to stop.
-pub enum EndCondition {
-    /// Run the simulation until a certain point in time is reached.
-    Time(f64),
-    /// Run the simulation until there are no more events scheduled.
-    NoEvents,
-    /// Execute exactly N steps of the simulation.
-    NSteps(usize),
+impl Add for SimDuration {
+    type Output = SimDuration;
+
+    fn add(self, other: SimDuration) -> SimDuration {
+        SimDuration(self.0 + other.0)
+    }
 }
 
-impl<T> Simulation<T> {
-    /// Create a new `Simulation` environment.
-    pub fn new(ctx: Rc<Context<T>>) -> Simulation<T> {
-        Simulation {
-            context: ctx,
-            processes: HashMap::default(),
-            future_events: BinaryHeap::default(),
-            processed_events: Vec::default(),
-            resources: Vec::default(),
-        }
+impl Sub for SimDuration {
+    type Output = SimDuration;
+
+    fn sub(self, other: SimDuration) -> SimDuration {
+        SimDuration(self.0 - other.0)
     }
+}
 
-    /// Returns the log of processed events
-    pub fn processed_events(&self) -> &[Event] {
-        self.processed_events.as_slice()
+impl<T, R> Effect<T, f64, R> {
+    /// `TimeOut` for the given duration.
+    pub fn timeout(d: SimDuration) -> Effect<T, f64, R> {
+        Effect::TimeOut(d.0)
     }
 
-    /// Create a process.
-    ///
-    /// For more information about a process, see the crate level documentation
-    ///
-    /// Returns the identifier of the process.
-    pub fn create_process(
-        &mut self,
-        pid: ProcessId,
-        process: Box<dyn Generator<Yield = Effect<T>, Return = ()> + Unpin>,
-    ) {
-        if self.processes.contains_key(&pid) {
-            panic!("ERROR: duplicate PID {}", pid);
-        }
-        self.processes.insert(pid, Some(process));
+    /// `SleepUntil` the given time.
+    pub fn sleep_until(t: SimInstant) -> Effect<T, f64, R> {
+        Effect::SleepUntil(t.0)
     }
+}
 
-    /// Create a new finite resource, of which n instancies are available.
-    ///
-    /// For more information about a resource, see the crate level documentation
-    ///
-    /// Returns the identifier of the resource
-    pub fn create_resource(&mut self, n: usize) -> ResourceId {
-        let id = self.resources.len();
-        self.resources.push(Resource {
-            allocated: n,
-            available: n,
-            queue: VecDeque::new(),
-        });
-        id
+impl Event<f64> {
+    /// An event for the process at the given time, to pass to
+    /// `Simulation::schedule_event` or to yield with `Effect::AbsoluteEvent`.
+    pub fn at(time: SimInstant, process: ProcessId) -> Event<f64> {
+        Event { time: time.0, process, label: None }
     }
 
-    /// Schedule a process to be executed. Another way to schedule events is
-    /// yielding `Effect::Event` from a process during the simulation.
-    pub fn schedule_event(&mut self, event: Event) {
-        self.future_events.push(Reverse(event));
+    /// An event for the process after the given delay, to yield with
+    /// `Effect::Event`.
+    pub fn after(delay: SimDuration, process: ProcessId) -> Event<f64> {
+        Event { time: delay.0, process, label: None }
     }
+}
 
-    /// Proceed in the simulation by 1 step
-    pub fn step(&mut self) {
-        match self.future_events.pop() {
-            Some(Reverse(event)) => {
-                self.context.time.set(event.time);
-                match Pin::new(self.processes.get_mut(&event.process).expect("No such process").as_mut().expect("ERROR. Tried to resume a completed process.")).resume() {
-                    GeneratorState::Yielded(y) => match y {
-                        Effect::TimeOut(t) => self.future_events.push(Reverse(Event {
-                            time: self.context.time() + t,
-                            process: event.process,
-                        })),
-                        Effect::Event(mut e) =>{
-                            e.time += self.context.time();
-                            self.future_events.push(Reverse(e))
-                        },
-                        Effect::Request(r) => {
-                            let mut res = &mut self.resources[r];
-                            if res.available == 0 {
-                                // enqueue the process
-                                res.queue.push_back(event.process);
-                            } else {
-                                // the process can use the resource immediately
-                                self.future_events.push(Reverse(Event {
-                                    time: self.context.time(),
-                                    process: event.process,
-                                }));
-                                res.available -= 1;
-                            }
-                        }
-                        Effect::Release(r) => {
-                            let res = &mut self.resources[r];
-                            match res.queue.pop_front() {
-                                Some(p) =>
-                                // some processes in queue: schedule the next.
-                                    self.future_events.push(Reverse(Event{
-                                        time: self.context.time(),
-                                        process: p
-                                    })),
-                                None => {
-                                    assert!(res.available < res.allocated);
-                                    res.available += 1;
-                                }
-                            }
-                            // after releasing the resource the process
-                            // can be resumed
-                            self.future_events.push(Reverse(Event {
-                                time: self.context.time(),
-                                process: event.process,
-                            }))
-                        }
-                        Effect::Interrupt(pid) => {
-                            self.context.interrupt(pid);
-                            self.future_events.push(Reverse(Event {
-                                time: self.context.time(),
-                                process: pid,
-                            }));
-                            self.future_events.push(Reverse(Event {
-                                time: self.context.time(),
-                                process: event.process,
-                            }))
-                        }
-                        Effect::SendMessage(pid, message, delay) => {
-                            self.context.push_message(pid, message);
-                            self.future_events.push(Reverse(Event {
-                                time: self.context.time() + delay,
-                                process: pid,
-                            }));
-                            self.future_events.push(Reverse(Event {
-                                time: self.context.time(),
-                                process: event.process,
-                            }))
-                        }
-                        Effect::Wait => {}
-                    },
-                    GeneratorState::Complete(_) => {
-                        // FIXME: removing the process from the vector would invalidate
-                        // all existing `ProcessId`s, but keeping it would be a
-                        // waste of space since it is completed.
-                        // May be worth to use another data structure.
-                        // At least let's remove the generator itself.
-                        self.processes.get_mut(&event.process).expect("Invalid PID").take();
-                    }
-                }
-                self.processed_events.push(event);
-            }
-            None => {}
-        }
+/// Identifies a process. Can be used to resume it from another one and to schedule it.
+///
+/// The number is chosen when the process is created, e.g. `ProcessId(1)`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProcessId(pub usize);
+
+impl Display for ProcessId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
+}
 
-    /// Run the simulation until and ending condition is met.
-    pub fn run(mut self, until: EndCondition) -> Simulation<T> {
-        while !self.check_ending_condition(&until) {
-            self.step();
-        }
-        self
+type EffectHook<T, Time, R> = Box<dyn FnMut(ProcessId, &Effect<T, Time, R>) -> Result<(), SimulationError<Time>>>;
+
+type ProcessBox<T, Time, R> = Box<dyn Generator<Yield = Effect<T, Time, R>, Return = R> + Unpin>;
+/// Identifies a resource. Can be used to request and release it.
+///
+/// It is returned by `Simulation::create_resource`: resources are numbered
+/// from 0 in the order they are created.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResourceId(pub usize);
+
+impl Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
-/*
-    pub fn nonblocking_run(mut self, until: EndCondition) -> thread::JoinHandle<Simulation> {
-        thread::spawn(move || {
-            self.run(until)
-        })
+}
+/// Identifies a store. Can be used to get and put items.
+pub type StoreId = usize;
+
+/// Selects the items of a store a process can take, see `Effect::Get`.
+pub struct ItemFilter<T>(pub Rc<dyn Fn(&T) -> bool>);
+
+impl<T> ItemFilter<T> {
+    /// Create a filter from the given predicate.
+    pub fn new<F: Fn(&T) -> bool + 'static>(f: F) -> ItemFilter<T> {
+        ItemFilter(Rc::new(f))
     }
-*/
 
-    /// Return `true` if the ending condition was met, `false` otherwise.
-    fn check_ending_condition(&self, ending_condition: &EndCondition) -> bool {
-        match &ending_condition {
-            EndCondition::Time(t) => if self.context.time() >= *t {
-                return true
-            },
-            EndCondition::NoEvents => if self.future_events.len() == 0 {
-                return true
-            },
-            // FIXME: what if client call `run(EndCondition::NSteps(n)` after having called `step()` for some times?
-            EndCondition::NSteps(n) => if self.processed_events.len() == *n {
-                return true
-            },
-        }
-        false
+    /// Remove and return the first of the items that matches the filter.
+    fn take_from(&self, items: &mut VecDeque<T>) -> Option<T> {
+        let i = items.iter().position(|item| (self.0)(item))?;
+        items.remove(i)
     }
 }
 
-impl PartialEq for Event {
-    fn eq(&self, other: &Event) -> bool {
-        self.time == other.time
+impl<T> Clone for ItemFilter<T> {
+    fn clone(&self) -> Self {
+        ItemFilter(self.0.clone())
     }
 }
 
-impl Eq for Event {}
-
-impl PartialOrd for Event {
-    fn partial_cmp(&self, other: &Event) -> Option<Ordering> {
-        self.time.partial_cmp(&other.time)
+impl<T> Debug for ItemFilter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ItemFilter")
     }
 }
 
-impl Ord for Event {
-    fn cmp(&self, other: &Event) -> Ordering {
-        match self.time.partial_cmp(&other.time) {
-            Some(o) => o,
-            None => panic!("Event time was uncomparable. Maybe a NaN"),
-        }
+/// A function invoked by the scheduler with the `Context` of the process
+/// that yielded it, see `Effect::Now`.
+pub struct Action<T, Time = f64, R = ()>(pub Rc<dyn Fn(&Context<T, Time, R>)>);
+
+impl<T, Time, R> Action<T, Time, R> {
+    /// Create an action from the given function.
+    pub fn new<F: Fn(&Context<T, Time, R>) + 'static>(f: F) -> Action<T, Time, R> {
+        Action(Rc::new(f))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::rc::Rc;
-    use Context;
+impl<T, Time, R> Clone for Action<T, Time, R> {
+    fn clone(&self) -> Self {
+        Action(self.0.clone())
+    }
+}
 
-    #[derive(Debug, Copy, Clone, PartialEq)]
-    enum TestMessage {
-        MessageType1,
-        MessageType2(&'static str)
+impl<T, Time, R> Debug for Action<T, Time, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Action")
     }
+}
 
-    #[test]
-    fn it_works() {
-        use Simulation;
-        use Effect;
-        use Event;
+/// The items of a store and the processes waiting for one, with their filter.
+///
+/// The processes waiting are not serialized, since the filters can not be.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(deserialize = "T: serde::Deserialize<'de>")))]
+#[derive(Debug, Clone)]
+struct Store<T> {
+    items: VecDeque<T>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    queue: VecDeque<(ProcessId, ItemFilter<T>)>,
+}
 
-        let ctx = Rc::new(Context::<TestMessage>::new());
-        let ctx2 = ctx.clone();
-        let mut s = Simulation::new(ctx.clone());
-        s.create_process(1, Box::new(move || {
-            let mut a = 0.0;
-            loop {
-                a += 1.0;
-                println!("time {}", ctx.time());
-                
-                yield Effect::TimeOut(a);
+impl<T> Store<T> {
+    /// Serve, in order, the processes waiting for an item that is in the
+    /// store now and return them with the item taken.
+    fn dequeue_ready(&mut self) -> Vec<(ProcessId, T)> {
+        let mut ready = Vec::new();
+        let items = &mut self.items;
+        self.queue.retain(|(pid, filter)| match filter.take_from(items) {
+            Some(item) => {
+                ready.push((*pid, item));
+                false
             }
-        }));
-        s.schedule_event(Event{time: 0.0, process: 1});
-        s.step();
-        s.step();
-        assert_eq!(ctx2.time(), 1.0);
-        s.step();
-        assert_eq!(ctx2.time(), 3.0);
-        s.step();
-        assert_eq!(ctx2.time(), 6.0);
+            None => true,
+        });
+        ready
     }
+}
 
-    #[test]
-    fn run() {
-        use Simulation;
-        use Effect;
-        use Event;
-        use EndCondition;
+/// Identifies a pool. Can be used to reserve and unreserve part of it.
+pub type PoolId = usize;
+/// Identifies a monitor. Can be used to retrieve the collected data.
+pub type MonitorId = usize;
+/// Identifies a scheduled event. Can be used to cancel it.
+pub type EventId = usize;
+/// Identifies a signal. Can be used to wait for it and to fire it.
+pub type SignalId = usize;
+/// Identifies a group of processes. Can be used to interrupt or cancel them
+/// all at once.
+pub type GroupId = usize;
 
-        let ctx = Rc::new(Context::<TestMessage>::new());
-        let mut s = Simulation::new(ctx.clone());
-        s.create_process(1,  Box::new(|| {
-            let tik = 0.7;
-            loop{
-                println!("tik");
-                yield Effect::TimeOut(tik);
-            }
-        }));
-        s.schedule_event(Event{time: 0.0, process: 1});
-        let s = s.run(EndCondition::Time(10.0));
-        println!("{}", ctx.time());
-        assert!(ctx.time() >= 10.0);
-    }
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+struct Resource<Time> {
+    allocated: usize,
+    available: usize,
+    shared_holders: usize,
+    has_exclusive: bool,
+    /// Units still held after the capacity was reduced below the amount in use.
+    excess: usize,
+    /// The processes holding a unit in exclusive mode, with the priority of
+    /// their request.
+    holders: Vec<(ProcessId, u32)>,
+    /// The processes evicted by a `PreemptRequest` that have not released
+    /// the unit they lost yet, nor obtained another one.
+    preempted: Vec<ProcessId>,
+    /// The time each process last acquired a unit.
+    acquired: HashMap<ProcessId, Time>,
+    queue: ResourceQueue<Time>,
+    wait_stats: WaitStats,
+}
 
-    #[test]
-    fn resource() {
-        use Simulation;
-        use Effect;
-        use Event;
-        use EndCondition::NoEvents;
+/// The order in which the processes waiting for a resource are served.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QueueDiscipline {
+    /// First in, first out.
+    Fifo,
+    /// Last in, first out.
+    Lifo,
+    /// Lowest priority value first, FIFO among equal priorities.
+    Priority,
+}
 
-        let ctx = Rc::new(Context::<TestMessage>::new());
-        let mut s = Simulation::new(ctx.clone());
-        let r = s.create_resource(1);
+/// The processes waiting for a resource, kept in the order they will be
+/// served according to the discipline.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+struct ResourceQueue<Time> {
+    discipline: QueueDiscipline,
+    waiters: VecDeque<Waiter<Time>>,
+}
+
+impl<Time> ResourceQueue<Time> {
+    fn new(discipline: QueueDiscipline) -> ResourceQueue<Time> {
+        ResourceQueue {
+            discipline,
+            waiters: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, waiter: Waiter<Time>) {
+        match self.discipline {
+            QueueDiscipline::Fifo => self.waiters.push_back(waiter),
+            QueueDiscipline::Lifo => self.waiters.push_front(waiter),
+            QueueDiscipline::Priority => {
+                let i = self
+                    .waiters
+                    .iter()
+                    .position(|w| w.priority > waiter.priority)
+                    .unwrap_or_else(|| self.waiters.len());
+                self.waiters.insert(i, waiter);
+            }
+        }
+    }
+
+    /// Serve the waiters according to the new discipline, sorting the ones
+    /// already queued as if they had been enqueued with it. Waiters enqueued
+    /// at the same time keep their relative arrival order.
+    fn set_discipline(&mut self, discipline: QueueDiscipline)
+    where
+        Time: PartialOrd,
+    {
+        let mut waiters: Vec<Waiter<Time>> = self.waiters.drain(..).collect();
+        if self.discipline == QueueDiscipline::Lifo {
+            waiters.reverse();
+        }
+        // stable, so the arrival order is kept among equal times
+        waiters.sort_by(|a, b| a.since.partial_cmp(&b.since).unwrap());
+        self.discipline = discipline;
+        for waiter in waiters {
+            self.push(waiter);
+        }
+    }
+
+    fn front(&self) -> Option<&Waiter<Time>> {
+        self.waiters.front()
+    }
+
+    fn pop_front(&mut self) -> Option<Waiter<Time>> {
+        self.waiters.pop_front()
+    }
+
+    fn retain<F: FnMut(&Waiter<Time>) -> bool>(&mut self, f: F) {
+        self.waiters.retain(f)
+    }
+
+    fn iter(&self) -> std::collections::vec_deque::Iter<Waiter<Time>> {
+        self.waiters.iter()
+    }
+
+    /// Rearrange the waiters in the order of their processes in `order`.
+    /// Returns `false`, leaving the queue untouched, if `order` is not a
+    /// permutation of the processes waiting.
+    fn reorder(&mut self, order: &[ProcessId]) -> bool {
+        let mut waiting: Vec<ProcessId> = self.waiters.iter().map(|w| w.process).collect();
+        let mut wanted = order.to_vec();
+        waiting.sort();
+        wanted.sort();
+        if waiting != wanted {
+            return false;
+        }
+        let mut waiters = std::mem::replace(&mut self.waiters, VecDeque::new());
+        for pid in order {
+            let i = waiters.iter().position(|w| w.process == *pid).unwrap();
+            self.waiters.push_back(waiters.remove(i).unwrap());
+        }
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.waiters.clear()
+    }
+}
+
+/// A process waiting in the queue of a resource.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+struct Waiter<Time> {
+    process: ProcessId,
+    shared: bool,
+    priority: u32,
+    /// When the process was enqueued.
+    since: Time,
+    /// The process yielded `RequestAll` and is waiting in the queues of all
+    /// the requested resources.
+    all: bool,
+}
+
+/// Statistics about the time processes waited to obtain a resource.
+///
+/// Every acquisition is counted, including the ones granted immediately,
+/// that have a waiting time of zero.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct WaitStats {
+    /// Number of acquisitions.
+    pub count: usize,
+    /// Mean waiting time.
+    pub mean: f64,
+    /// Variance of the waiting time.
+    pub variance: f64,
+    /// Maximum waiting time.
+    pub max: f64,
+}
+
+impl WaitStats {
+    /// Returns the mean waiting time, 0 if nothing was acquired.
+    pub fn mean_wait(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the longest waiting time, 0 if nothing was acquired.
+    pub fn max_wait(&self) -> f64 {
+        self.max
+    }
+
+    /// Add a waiting time, updating the mean and the variance with Welford's
+    /// algorithm.
+    fn add(&mut self, wait: f64) {
+        let m2 = self.variance * self.count as f64;
+        let delta = wait - self.mean;
+        self.count += 1;
+        self.mean += delta / self.count as f64;
+        self.variance = (m2 + delta * (wait - self.mean)) / self.count as f64;
+        if wait > self.max {
+            self.max = wait;
+        }
+    }
+
+    fn merge(&mut self, other: &WaitStats) {
+        let count = self.count + other.count;
+        if count > 0 {
+            let delta = other.mean - self.mean;
+            let m2 = self.variance * self.count as f64
+                + other.variance * other.count as f64
+                + delta * delta * (self.count * other.count) as f64 / count as f64;
+            self.mean = (self.mean * self.count as f64 + other.mean * other.count as f64) / count as f64;
+            self.variance = m2 / count as f64;
+        }
+        self.count = count;
+        if other.max > self.max {
+            self.max = other.max;
+        }
+    }
+}
+
+/// Statistics about the resumptions of a process, collected after
+/// `Simulation::enable_process_stats`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ProcessStats {
+    /// Number of times the process was resumed.
+    pub resume_count: usize,
+    /// Simulation time elapsed between the scheduling of the process, i.e.
+    /// its last yield or the `schedule_event` that started it, and its
+    /// resumptions.
+    pub total_wait_time: f64,
+    /// Real time, in seconds, spent running the process between its
+    /// resumptions and the following yields, that take no simulation time.
+    pub total_active_time: f64,
+}
+
+/// Summary of a run, returned by `Simulation::summary`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RunSummary<Time = f64> {
+    /// The simulation time reached.
+    pub end_time: Time,
+    /// Number of events processed.
+    pub events_processed: usize,
+    /// Events processed per unit of simulation time, or 0 if no time passed.
+    pub events_per_time_unit: f64,
+    /// Number of processes that have not completed yet.
+    pub live_processes: usize,
+    /// Number of processes that have completed.
+    pub completed_processes: usize,
+    /// Number of resources created.
+    pub resources: usize,
+}
+
+impl<Time: Debug> Display for RunSummary<Time> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "end time: {:?}, events processed: {} ({:.3} per time unit), processes: {} live, {} completed, resources: {}",
+            self.end_time,
+            self.events_processed,
+            self.events_per_time_unit,
+            self.live_processes,
+            self.completed_processes,
+            self.resources
+        )
+    }
+}
+
+/// The occupancy of a resource at a given moment.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResourceState {
+    /// Number of units of the resource, i.e. its capacity.
+    pub allocated: usize,
+    /// Number of units that can be acquired in exclusive mode.
+    pub available: usize,
+    /// Number of units held in exclusive mode, that can exceed `allocated`
+    /// after the capacity was reduced.
+    pub in_use: usize,
+    /// Number of processes waiting in the queue of the resource.
+    pub queue_len: usize,
+}
+
+impl<Time: SimTime> Resource<Time> {
+    /// Try to assign the resource to the process, in exclusive or shared mode.
+    /// Returns `false` if the process must wait.
+    fn try_acquire(&mut self, pid: ProcessId, shared: bool, priority: u32) -> bool {
+        if shared {
+            if self.has_exclusive {
+                return false;
+            }
+            self.shared_holders += 1;
+        } else {
+            if self.available == 0 || self.shared_holders > 0 {
+                return false;
+            }
+            self.available -= 1;
+            self.has_exclusive = true;
+            self.holders.push((pid, priority));
+        }
+        true
+    }
+
+    /// Give back a unit held in exclusive mode by the process.
+    fn release(&mut self, pid: ProcessId) {
+        match self.holders.iter().position(|&(p, _)| p == pid) {
+            Some(i) => {
+                self.holders.remove(i);
+            }
+            None => {
+                if let Some(i) = self.preempted.iter().position(|&p| p == pid) {
+                    // the unit was already taken away
+                    self.preempted.remove(i);
+                    return;
+                }
+            }
+        }
+        if self.excess > 0 {
+            // the unit is not available anymore since the capacity was reduced
+            self.excess -= 1;
+        } else {
+            assert!(self.available < self.allocated);
+            self.available += 1;
+        }
+        self.has_exclusive = self.available < self.allocated || self.excess > 0;
+    }
+
+    /// Change the number of units of the resource, without taking back the
+    /// ones in use.
+    fn set_capacity(&mut self, capacity: usize) {
+        let in_use = self.allocated - self.available + self.excess;
+        self.allocated = capacity;
+        if in_use <= capacity {
+            self.available = capacity - in_use;
+            self.excess = 0;
+        } else {
+            self.available = 0;
+            self.excess = in_use - capacity;
+        }
+    }
+
+    /// Returns `true` if a unit can be acquired in exclusive mode now.
+    fn can_acquire_exclusive(&self) -> bool {
+        self.available > 0 && self.shared_holders == 0
+    }
+
+    /// Returns `true` if no unit is held, in either mode.
+    fn is_idle(&self) -> bool {
+        self.available == self.allocated && self.shared_holders == 0
+    }
+
+    /// Remove from the front of the queue all the processes that can acquire
+    /// the resource now, assign it to them and return their ids.
+    fn dequeue_ready(&mut self, now: Time) -> Vec<ProcessId> {
+        let mut ready = Vec::new();
+        while let Some(w) = self.queue.front() {
+            // a `RequestAll` is granted by the simulation, that looks at
+            // all the resources involved
+            if w.all {
+                break;
+            }
+            let (pid, shared, priority) = (w.process, w.shared, w.priority);
+            if !self.try_acquire(pid, shared, priority) {
+                break;
+            }
+            let waiter = self.queue.pop_front().unwrap();
+            self.wait_stats.add((now - waiter.since).to_f64());
+            ready.push(waiter.process);
+        }
+        ready
+    }
+}
+
+/// What a process that is not scheduled is waiting for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WaitReason {
+    /// The process is in the queue of the resource.
+    Resource(ResourceId),
+    /// The process is in the queue of the pool.
+    Pool(PoolId),
+    /// The process yielded `Effect::Get` and no item matches its filter.
+    Store(StoreId),
+    /// The process yielded `Effect::Select`.
+    Select,
+    /// The process yielded `Effect::WaitForMessage` or `Effect::Gather`.
+    Message,
+    /// The process yielded `Effect::WaitForSignal`.
+    Signal(SignalId),
+    /// The process yielded `Effect::Join` and the target is still alive.
+    Join(ProcessId),
+    /// The process must be resumed by an event scheduled for it, e.g. after
+    /// yielding `Effect::Wait` or because it was never started.
+    Event,
+}
+
+/// How a process that yielded `Effect::RequestWithPatience` was resumed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The process obtained the resource.
+    Served,
+    /// The patience expired first: the process left the queue without the
+    /// resource.
+    Reneged,
+}
+
+/// How the process awaited with `Effect::Join` ended.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JoinOutcome {
+    /// The process returned.
+    Completed,
+    /// The process was cancelled, or did not exist.
+    Cancelled,
+}
+
+/// An interruption of a process, see `Context::check_interrupted`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Interrupted<T> {
+    /// The interruption was raised without a payload, e.g. by
+    /// `Effect::Interrupt`.
+    Plain,
+    /// The interruption was raised with the given payload, e.g. by
+    /// `Effect::InterruptWith`.
+    With(T),
+}
+
+impl<T> Interrupted<T> {
+    fn from_payload(payload: Option<T>) -> Interrupted<T> {
+        match payload {
+            Some(payload) => Interrupted::With(payload),
+            None => Interrupted::Plain,
+        }
+    }
+}
+
+/// How a process that yielded `Effect::RequestWithTimeout` was resumed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RequestOutcome {
+    /// The process obtained the resource.
+    Acquired,
+    /// The timeout expired first: the process left the queue without the
+    /// resource.
+    TimedOut,
+}
+
+/// The kind of modelling mistake reported by `Simulation::validate`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ValidationKind {
+    /// An event is scheduled for a process that does not exist.
+    UnknownProcess,
+    /// A completed process still holds units of a resource. Detected only
+    /// if enabled with `Simulation::enable_hold_tracking`.
+    UnreleasedResource,
+    /// A process was created but never scheduled, so it will never start.
+    NeverScheduled,
+    /// A resource has no units, so requests for it can never be granted.
+    ZeroCapacity,
+}
+
+/// A possible modelling mistake found by `Simulation::validate`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationWarning {
+    /// The process or, for `UnreleasedResource` and `ZeroCapacity`, the
+    /// resource concerned.
+    pub pid_or_rid: usize,
+    /// What is wrong.
+    pub kind: ValidationKind,
+    /// A description of the problem, for the user.
+    pub message: String,
+}
+
+/// The alternatives a process is waiting for after yielding `Effect::Select`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+struct ArmedSelect<Time> {
+    /// Time of the earliest timeout and its index.
+    timeout: Option<(Time, usize)>,
+    /// The event scheduled for the timeout.
+    timeout_event: Option<EventId>,
+    /// Resources the process is enqueued on and the index of the request.
+    requests: Vec<(ResourceId, usize)>,
+    /// Stores the process waits for an item of and the index of the `Get`.
+    gets: Vec<(StoreId, usize)>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+struct Pool {
+    capacity: f64,
+    available: f64,
+    queue: VecDeque<(ProcessId, f64)>,
+    /// The amounts given back beyond the capacity are discarded, as the
+    /// tokens of a token bucket.
+    bucket: bool,
+}
+
+impl Pool {
+    /// Try to reserve the given amount. Returns `false` if the process must wait.
+    fn try_reserve(&mut self, amount: f64) -> bool {
+        if amount > self.available {
+            return false;
+        }
+        self.available -= amount;
+        true
+    }
+
+    /// Remove from the front of the queue all the processes whose reservation
+    /// can be satisfied now and return their ids.
+    fn dequeue_ready(&mut self) -> Vec<ProcessId> {
+        let mut ready = Vec::new();
+        while let Some(amount) = self.queue.front().map(|w| w.1) {
+            if !self.try_reserve(amount) {
+                break;
+            }
+            ready.push(self.queue.pop_front().unwrap().0);
+        }
+        ready
+    }
+}
+
+pub struct Context<T, Time = f64, R = ()> {
+    time: Cell<Time>,
+    /// The inbox of each process, with the priority of each message.
+    messages: RefCell<HashMap<ProcessId, VecDeque<(u32, T)>>>,
+    interrupted: RefCell<HashMap<ProcessId, Option<T>>>,
+    rng: Cell<u64>,
+    next_pid: Cell<ProcessId>,
+    spawned: RefCell<Vec<(ProcessId, ProcessBox<T, Time, R>)>>,
+    selected: RefCell<HashMap<ProcessId, Option<usize>>>,
+    granted: RefCell<HashMap<ProcessId, ResourceId>>,
+    /// How the process awaited by each joining process ended.
+    joined: RefCell<HashMap<ProcessId, JoinOutcome>>,
+    /// The item taken from a store by each process and not read yet.
+    items: RefCell<HashMap<ProcessId, T>>,
+    /// The processes that yielded `Effect::Wait` and are not scheduled yet.
+    waiting: RefCell<HashSet<ProcessId>>,
+    /// The time each process was created at.
+    created: RefCell<HashMap<ProcessId, Time>>,
+    /// The time each process evicted by a `PreemptRequest` last acquired
+    /// the unit it lost, and the time it was evicted.
+    preemptions: RefCell<HashMap<ProcessId, (Time, Time)>>,
+    /// The user data attached to each process. Not saved in checkpoints.
+    data: RefCell<HashMap<ProcessId, Rc<dyn Any>>>,
+    /// The state of the resources, updated by the simulation before resuming
+    /// each process.
+    resources: RefCell<Vec<ResourceState>>,
+    signals: RefCell<Vec<String>>,
+    series: RefCell<HashMap<String, Vec<(Time, f64)>>>,
+    /// Signals fired and not yet delivered to their waiters.
+    fired: RefCell<Vec<SignalId>>,
+    population: Cell<Population<Time>>,
+}
+
+/// The number of entities in the system, with its integral over time.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default)]
+struct Population<Time> {
+    count: usize,
+    /// Integral of `count` from time 0 to `since`.
+    area: f64,
+    /// When `count` last changed.
+    since: Time,
+}
+
+/// Error raised when the simulation time would be moved in an invalid way.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TimeError<Time = f64> {
+    /// The time of the next event is before the current time.
+    TimeTravelAttempted {
+        /// The current time.
+        from: Time,
+        /// The time of the event.
+        to: Time,
+    },
+}
+
+impl<Time: Debug> Display for TimeError<Time> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeError::TimeTravelAttempted { from, to } => {
+                write!(f, "tried to move the time back from {:?} to {:?}", from, to)
+            }
+        }
+    }
+}
+
+impl<Time: Debug> Error for TimeError<Time> {}
+
+/// Error returned by `Simulation::wake_process`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WakeError {
+    /// There is no process with the given id.
+    UnknownProcess(ProcessId),
+    /// The process has already completed.
+    Completed(ProcessId),
+    /// The process is not suspended, e.g. it is already scheduled.
+    NotSuspended(ProcessId),
+}
+
+impl Display for WakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WakeError::UnknownProcess(p) => write!(f, "process {} does not exist", p),
+            WakeError::Completed(p) => write!(f, "process {} has already completed", p),
+            WakeError::NotSuspended(p) => write!(f, "process {} is not suspended", p),
+        }
+    }
+}
+
+impl Error for WakeError {}
+
+/// Error returned by `Simulation::fork_process_at` when a process with the
+/// given id already exists.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DuplicatePidError(pub ProcessId);
+
+impl Display for DuplicatePidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "duplicate PID {}", self.0)
+    }
+}
+
+impl Error for DuplicatePidError {}
+
+/// Error that stops the simulation, returned by `Simulation::step`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimulationError<Time = f64> {
+    /// An event for the process was scheduled at a NaN time, e.g. because
+    /// the process yielded a NaN `TimeOut`.
+    NaNEventTime {
+        /// The process the event was scheduled for.
+        process: ProcessId,
+        /// The simulation time when the event was scheduled.
+        scheduled_at: Time,
+    },
+    /// An event for the process was scheduled at an infinite time.
+    InfiniteEventTime {
+        /// The process the event was scheduled for.
+        process: ProcessId,
+        /// The simulation time when the event was scheduled.
+        scheduled_at: Time,
+    },
+    /// The clock would have moved backwards.
+    Time(TimeError<Time>),
+    /// The process released a resource it does not hold. Detected only if
+    /// enabled with `Simulation::enable_hold_tracking`.
+    ReleaseWithoutHold {
+        /// The process that yielded the release.
+        process: ProcessId,
+        /// The resource released.
+        resource: ResourceId,
+    },
+    /// During a replay, the process yielded an effect different from the
+    /// one at `index` in the recording, or more effects than recorded, or
+    /// the run ended before the process yielded the one at `index`.
+    ReplayDivergence {
+        /// The process that yielded the effect.
+        process: ProcessId,
+        /// The position of the expected effect in the recording.
+        index: usize,
+    },
+    /// An event was scheduled for a process that already has a pending
+    /// event. Detected only if enabled with
+    /// `SimulationBuilder::with_unique_scheduling` or, for the processes
+    /// blocked by `Effect::Wait`, with `SimulationBuilder::with_strict_wakeups`.
+    DuplicateScheduling {
+        /// The process scheduled twice.
+        process: ProcessId,
+        /// The time of the event refused.
+        time: Time,
+    },
+    /// The process panicked while it was resumed. It is completed, without
+    /// a result, and the simulation can go on.
+    ProcessPanicked {
+        /// The process that panicked.
+        process: ProcessId,
+        /// The message of the panic.
+        message: String,
+    },
+    /// The number of events set with `Simulation::set_event_budget` has been
+    /// processed and there are more.
+    EventBudgetExceeded {
+        /// The number of events allowed.
+        budget: usize,
+    },
+}
+
+impl<Time> From<TimeError<Time>> for SimulationError<Time> {
+    fn from(e: TimeError<Time>) -> Self {
+        SimulationError::Time(e)
+    }
+}
+
+impl<Time: Debug> Display for SimulationError<Time> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimulationError::NaNEventTime { process, scheduled_at } => write!(
+                f,
+                "an event for process {} was scheduled at a NaN time, at time {:?}",
+                process, scheduled_at
+            ),
+            SimulationError::InfiniteEventTime { process, scheduled_at } => write!(
+                f,
+                "an event for process {} was scheduled at an infinite time, at time {:?}",
+                process, scheduled_at
+            ),
+            SimulationError::Time(e) => write!(f, "{}", e),
+            SimulationError::ReleaseWithoutHold { process, resource } => write!(
+                f,
+                "process {} released resource {} without holding it",
+                process, resource
+            ),
+            SimulationError::ReplayDivergence { process, index } => write!(
+                f,
+                "process {} diverged from the recording at effect {}",
+                process, index
+            ),
+            SimulationError::DuplicateScheduling { process, time } => write!(
+                f,
+                "process {} scheduled at time {:?} has already a pending event",
+                process, time
+            ),
+            SimulationError::ProcessPanicked { process, message } => {
+                write!(f, "process {} panicked: {}", process, message)
+            }
+            SimulationError::EventBudgetExceeded { budget } => {
+                write!(f, "the budget of {} events has been exceeded", budget)
+            }
+        }
+    }
+}
+
+impl<Time: Debug> Error for SimulationError<Time> {}
+
+/// The seed used by a new `Context` if not set with `Context::seed`.
+const DEFAULT_SEED: u64 = 0x853c_49e6_748f_ea9b;
+
+/// Advance the state of the generator and return a number uniformly
+/// distributed in `[0, 1)`.
+fn next_uniform(state: &mut u64) -> f64 {
+    // xorshift64*
+    let mut x = *state;
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    *state = x;
+    (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl<T, Time: SimTime, R> Context<T, Time, R> {
+    /// Create a new `Context` environment.
+    pub fn new() -> Context<T, Time, R> {
+        Context::default()
+    }
+
+    /// Returns the current simulation time
+    pub fn time(&self) -> Time {
+        self.time.get()
+    }
+}
+
+impl<T, R> Context<T, f64, R> {
+    /// Returns the current simulation time as a `SimInstant`.
+    pub fn now(&self) -> SimInstant {
+        SimInstant(self.time.get())
+    }
+}
+
+impl<T, Time: SimTime, R> Context<T, Time, R> {
+    /// Format the current simulation time rounded to the given number of
+    /// decimal places, e.g. for logs, hiding the floating point errors of
+    /// times like `0.7 * 14.0`.
+    pub fn format_time(&self, decimals: usize) -> String {
+        format!("{:.prec$}", self.time().to_f64(), prec = decimals)
+    }
+
+    /// Put a message in the inbox of the given process, with priority 0.
+    ///
+    /// Among messages with the same priority the inbox is a FIFO: they are
+    /// popped or drained in the same order they were pushed.
+    pub fn push_message(&self, pid: ProcessId, message: T) {
+        self.push_message_with_priority(pid, message, 0);
+    }
+
+    /// Put a message in the inbox of the given process, ahead of the ones
+    /// with a higher priority value and after the ones with a lower or equal
+    /// value.
+    pub fn push_message_with_priority(&self, pid: ProcessId, message: T, priority: u32) {
+        let mut m = self.messages.borrow_mut();
+        let vd = m.entry(pid).or_insert_with(VecDeque::default);
+        let i = vd
+            .iter()
+            .rposition(|&(p, _)| p <= priority)
+            .map_or(0, |i| i + 1);
+        vd.insert(i, (priority, message));
+    }
+
+    /// Returns the number of messages in the inbox of the given process.
+    pub fn message_count(&self, pid: ProcessId) -> usize {
+        self.messages.borrow().get(&pid).map_or(0, |vd| vd.len())
+    }
+
+    /// Returns the number of messages in the inbox of the given process, as
+    /// `message_count` does.
+    pub fn inbox_len(&self, pid: ProcessId) -> usize {
+        self.message_count(pid)
+    }
+
+    /// Remove and return the oldest message with the lowest priority value
+    /// in the inbox of the given process.
+    pub fn pop_message(&self, pid: ProcessId) -> Option<T> {
+        match self.messages.borrow_mut().get_mut(&pid) {
+            Some(vd) => vd.pop_front().map(|(_, m)| m),
+            None => None
+        }
+    }
+
+    /// Remove all the messages in the inbox of the given process, leaving it
+    /// empty, and return them in the order they would be popped.
+    pub fn drain_messages(&self, pid: ProcessId) -> impl Iterator<Item = T> {
+        let inbox = self.messages.borrow_mut().remove(&pid).unwrap_or_default();
+        inbox.into_iter().map(|(_, m)| m)
+    }
+
+    pub fn interrupt(&self, pid: ProcessId) {
+        self.interrupted.borrow_mut().insert(pid, None);
+    }
+
+    /// Mark the process as interrupted, with the given reason. It replaces
+    /// the one of an interruption not yet checked.
+    pub fn interrupt_with(&self, pid: ProcessId, payload: T) {
+        self.interrupted.borrow_mut().insert(pid, Some(payload));
+    }
+
+    /// Returns `None` if the process has not been interrupted, otherwise
+    /// the interruption, with its payload if any. The interruption is
+    /// consumed.
+    pub fn check_interrupted(&self, pid: ProcessId) -> Option<Interrupted<T>> {
+        self.interrupted.borrow_mut().remove(&pid).map(Interrupted::from_payload)
+    }
+
+    /// Move the clock forward to `t`.
+    ///
+    /// Returns an error, without changing the time, if `t` is before the
+    /// current time or is not comparable with it, e.g. NaN.
+    fn advance_time(&self, t: Time) -> Result<(), TimeError<Time>> {
+        let now = self.time.get();
+        match t.partial_cmp(&now) {
+            Some(Ordering::Greater) | Some(Ordering::Equal) => {
+                self.time.set(t);
+                Ok(())
+            }
+            _ => Err(TimeError::TimeTravelAttempted { from: now, to: t }),
+        }
+    }
+
+    /// Returns the index of the effect that resumed the process the last time
+    /// it yielded `Effect::Select`, or `None` if it was resumed by something
+    /// else, e.g. an interruption.
+    pub fn selected_branch(&self, pid: ProcessId) -> Option<usize> {
+        self.selected.borrow().get(&pid).cloned().and_then(|b| b)
+    }
+
+    /// Returns whether the process obtained the resource the last time it
+    /// yielded `Effect::RequestWithPatience`, or `None` if it was resumed by
+    /// something else, e.g. an interruption.
+    pub fn acquisition_outcome(&self, pid: ProcessId) -> Option<Outcome> {
+        match self.selected_branch(pid) {
+            Some(0) => Some(Outcome::Served),
+            Some(_) => Some(Outcome::Reneged),
+            None => None,
+        }
+    }
+
+    /// Returns whether the process obtained the resource the last time it
+    /// yielded `Effect::RequestWithTimeout`, or `None` if it was resumed by
+    /// something else, e.g. an interruption.
+    pub fn last_request_outcome(&self, pid: ProcessId) -> Option<RequestOutcome> {
+        match self.acquisition_outcome(pid) {
+            Some(Outcome::Served) => Some(RequestOutcome::Acquired),
+            Some(Outcome::Reneged) => Some(RequestOutcome::TimedOut),
+            None => None,
+        }
+    }
+
+    /// Returns the state of the resource when the running process was
+    /// resumed, e.g. to decide whether to request it.
+    ///
+    /// Panics if the resource does not exist.
+    pub fn resource_state(&self, id: ResourceId) -> ResourceState {
+        match self.resources.borrow().get(id.0) {
+            Some(state) => *state,
+            None => panic!("ERROR: resource {} does not exist", id),
+        }
+    }
+
+    /// Returns the number of processes waiting for the resource, see
+    /// `resource_state`.
+    pub fn resource_queue_len(&self, id: ResourceId) -> usize {
+        self.resource_state(id).queue_len
+    }
+
+    /// Returns the number of units of the resource that can be acquired, see
+    /// `resource_state`.
+    pub fn resource_available(&self, id: ResourceId) -> usize {
+        self.resource_state(id).available
+    }
+
+    /// Returns `true` if the process yielded `Effect::Wait` and has not been
+    /// scheduled again since.
+    pub fn is_waiting(&self, pid: ProcessId) -> bool {
+        self.waiting.borrow().contains(&pid)
+    }
+
+    /// Returns the processes that yielded `Effect::Wait` and have not been
+    /// scheduled again since, sorted by id.
+    pub fn waiting_processes(&self) -> Vec<ProcessId> {
+        let mut waiting: Vec<_> = self.waiting.borrow().iter().cloned().collect();
+        waiting.sort();
+        waiting
+    }
+
+    /// Returns the time elapsed since the process was created, e.g. for a
+    /// process to compute its own sojourn time, or `None` if it does not
+    /// exist or was created before the last `Simulation::reset`.
+    pub fn process_age(&self, pid: ProcessId) -> Option<Time> {
+        self.created.borrow().get(&pid).map(|&t| self.time() - t)
+    }
+
+    /// Returns the time the process held the unit it lost the last time it
+    /// was evicted by a `PreemptRequest`, e.g. to compute the remaining
+    /// service time when it gets the resource back, or `None` if it was
+    /// never evicted.
+    pub fn preemption_elapsed(&self, pid: ProcessId) -> Option<Time> {
+        self.preemptions.borrow().get(&pid).map(|&(since, evicted)| evicted - since)
+    }
+
+    /// Attach some data to the process, e.g. the class of the entity it
+    /// models, replacing the data attached before.
+    pub fn set_process_data<D: Any>(&self, pid: ProcessId, data: D) {
+        self.data.borrow_mut().insert(pid, Rc::new(data));
+    }
+
+    /// Returns the data attached to the process with `set_process_data`, or
+    /// `None` if there is none or it is not of type `D`.
+    pub fn process_data<D: Any>(&self, pid: ProcessId) -> Option<Rc<D>> {
+        self.data.borrow().get(&pid).and_then(|d| d.clone().downcast().ok())
+    }
+
+    /// Returns the resource obtained by the process the last time it yielded
+    /// `Effect::RequestAny`, or `Effect::Select` with some requests, or `None`
+    /// if it was resumed by something else.
+    pub fn granted_resource(&self, pid: ProcessId) -> Option<ResourceId> {
+        self.granted.borrow().get(&pid).cloned()
+    }
+
+    /// Returns how the process awaited the last time the given one yielded
+    /// `Effect::Join` ended, or `None` if it never joined one.
+    pub fn join_outcome(&self, pid: ProcessId) -> Option<JoinOutcome> {
+        self.joined.borrow().get(&pid).cloned()
+    }
+
+    /// Take the item the process obtained from a store yielding
+    /// `Effect::Get`, or `None` if it has already been taken.
+    pub fn take_item(&self, pid: ProcessId) -> Option<T> {
+        self.items.borrow_mut().remove(&pid)
+    }
+
+    /// Returns a `ProcessId` that is not used by any process yet,
+    /// to be used to spawn a new one.
+    pub fn new_pid(&self) -> ProcessId {
+        let pid = self.next_pid.get();
+        self.next_pid.set(ProcessId(pid.0 + 1));
+        pid
+    }
+
+    /// Create a new process while the simulation is running.
+    ///
+    /// The process is added to the simulation at the end of the current step
+    /// and scheduled to start at the current simulation time.
+    pub fn spawn(&self, pid: ProcessId, process: ProcessBox<T, Time, R>) {
+        self.spawned.borrow_mut().push((pid, process));
+    }
+
+    /// Create a new signal, that processes can wait for yielding
+    /// `Effect::WaitForSignal`.
+    pub fn create_signal(&self, name: &str) -> SignalId {
+        let mut signals = self.signals.borrow_mut();
+        signals.push(name.to_string());
+        signals.len() - 1
+    }
+
+    /// Returns the name of the signal.
+    pub fn signal_name(&self, id: SignalId) -> String {
+        self.signals.borrow()[id].clone()
+    }
+
+    /// Fire the signal, resuming at the current time all the processes
+    /// waiting for it.
+    ///
+    /// It can be called by a process or by the owner of the simulation
+    /// between steps. The signal is not remembered: a process that starts
+    /// waiting for it afterwards waits for the next time it is fired.
+    pub fn fire_signal(&self, id: SignalId) {
+        assert!(id < self.signals.borrow().len(), "ERROR: no signal with id {}", id);
+        self.fired.borrow_mut().push(id);
+    }
+
+    /// Append a sample with the given value, at the current time, to the named
+    /// series, e.g. to record the latency of each request.
+    pub fn record(&self, series: &str, value: f64) {
+        let time = self.time();
+        self.series
+            .borrow_mut()
+            .entry(series.to_string())
+            .or_insert_with(Vec::new)
+            .push((time, value));
+    }
+
+    /// Count an entity, e.g. a customer, entering the system, to compute
+    /// `Simulation::mean_number_in_system`.
+    pub fn entity_enter(&self) {
+        self.update_population(|count| count + 1);
+    }
+
+    /// Count an entity leaving the system.
+    ///
+    /// Panics if no entity is in the system.
+    pub fn entity_exit(&self) {
+        self.update_population(|count| {
+            count.checked_sub(1).expect("ERROR: entity_exit without entity_enter")
+        });
+    }
+
+    fn update_population<F: FnOnce(usize) -> usize>(&self, f: F) {
+        let mut p = self.population.get();
+        let now = self.time();
+        p.area += p.count as f64 * (now - p.since).to_f64();
+        p.since = now;
+        p.count = f(p.count);
+        self.population.set(p);
+    }
+
+    /// Seed the pseudo-random number generator of the context.
+    pub fn seed(&self, seed: u64) {
+        // the state of the xorshift generator must not be zero
+        self.rng.set(if seed == 0 { DEFAULT_SEED } else { seed });
+    }
+
+    /// Returns a random number uniformly distributed in `[0, 1)`.
+    pub fn rand_uniform(&self) -> f64 {
+        let mut x = self.rng.get();
+        let u = next_uniform(&mut x);
+        self.rng.set(x);
+        u
+    }
+
+    /// Returns a random number exponentially distributed with the given rate,
+    /// e.g. the interarrival time of a Poisson process.
+    pub fn rand_exponential(&self, rate: f64) -> f64 {
+        -(1.0 - self.rand_uniform()).ln() / rate
+    }
+
+    /// Returns an index of `weights` chosen at random with probability
+    /// proportional to its weight, e.g. to route an entity to a lane.
+    ///
+    /// Panics if a weight is negative or not finite, or if they sum to 0.
+    pub fn choose_weighted(&self, weights: &[f64]) -> usize {
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            panic!("ERROR: the weights must be finite and non-negative");
+        }
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            panic!("ERROR: the weights must sum to more than 0");
+        }
+        let mut x = self.rand_uniform() * total;
+        for (i, w) in weights.iter().enumerate() {
+            if x < *w {
+                return i;
+            }
+            x -= w;
+        }
+        // rounding errors: the last index with a positive weight
+        weights.iter().rposition(|w| *w > 0.0).unwrap()
+    }
+}
+
+impl<T: Clone, Time: SimTime, R> Context<T, Time, R> {
+    /// Overwrite the state of this context with the one of `other`.
+    fn restore_from(&self, other: &Context<T, Time, R>) {
+        self.time.set(other.time.get());
+        *self.messages.borrow_mut() = other.messages.borrow().clone();
+        *self.interrupted.borrow_mut() = other.interrupted.borrow().clone();
+        self.rng.set(other.rng.get());
+        self.next_pid.set(other.next_pid.get());
+        self.spawned.borrow_mut().clear();
+        *self.selected.borrow_mut() = other.selected.borrow().clone();
+        *self.granted.borrow_mut() = other.granted.borrow().clone();
+        *self.joined.borrow_mut() = other.joined.borrow().clone();
+        *self.items.borrow_mut() = other.items.borrow().clone();
+        *self.waiting.borrow_mut() = other.waiting.borrow().clone();
+        *self.created.borrow_mut() = other.created.borrow().clone();
+        *self.preemptions.borrow_mut() = other.preemptions.borrow().clone();
+        *self.data.borrow_mut() = other.data.borrow().clone();
+        *self.resources.borrow_mut() = other.resources.borrow().clone();
+        *self.signals.borrow_mut() = other.signals.borrow().clone();
+        *self.series.borrow_mut() = other.series.borrow().clone();
+        *self.fired.borrow_mut() = other.fired.borrow().clone();
+        self.population.set(other.population.get());
+    }
+}
+
+/// The processes spawned and not yet added to the simulation are not cloned.
+impl<T: Clone, Time: SimTime, R> Clone for Context<T, Time, R> {
+    fn clone(&self) -> Self {
+        Context {
+            time: self.time.clone(),
+            messages: self.messages.clone(),
+            interrupted: self.interrupted.clone(),
+            rng: self.rng.clone(),
+            next_pid: self.next_pid.clone(),
+            spawned: RefCell::new(Vec::new()),
+            selected: self.selected.clone(),
+            granted: self.granted.clone(),
+            joined: self.joined.clone(),
+            items: self.items.clone(),
+            waiting: self.waiting.clone(),
+            created: self.created.clone(),
+            preemptions: self.preemptions.clone(),
+            data: self.data.clone(),
+            resources: self.resources.clone(),
+            signals: self.signals.clone(),
+            series: self.series.clone(),
+            fired: self.fired.clone(),
+            population: self.population.clone(),
+        }
+    }
+}
+
+
+impl<T, Time: SimTime, R> Default for Context<T, Time, R> {
+    fn default() -> Self {
+        Context {
+            time: Cell::new(Time::default()),
+            messages: RefCell::new(HashMap::default()),
+            interrupted: RefCell::new(HashMap::default()),
+            rng: Cell::new(DEFAULT_SEED),
+            next_pid: Cell::new(ProcessId(0)),
+            spawned: RefCell::new(Vec::new()),
+            selected: RefCell::new(HashMap::default()),
+            granted: RefCell::new(HashMap::default()),
+            joined: RefCell::new(HashMap::default()),
+            items: RefCell::new(HashMap::default()),
+            waiting: RefCell::new(HashSet::default()),
+            created: RefCell::new(HashMap::default()),
+            preemptions: RefCell::new(HashMap::default()),
+            data: RefCell::new(HashMap::default()),
+            resources: RefCell::new(Vec::new()),
+            signals: RefCell::new(Vec::new()),
+            series: RefCell::new(HashMap::default()),
+            fired: RefCell::new(Vec::new()),
+            population: Cell::new(Population::default()),
+        }
+    }
+}
+
+/// A channel carrying messages of type `M`, independent of the type of the
+/// messages of the `Context`.
+///
+/// The messages in the channels are not part of the snapshots and
+/// checkpoints of the simulation.
+pub struct Channel<M> {
+    queues: Rc<RefCell<HashMap<ProcessId, VecDeque<M>>>>,
+}
+
+impl<M> Clone for Channel<M> {
+    fn clone(&self) -> Self {
+        Channel {
+            queues: self.queues.clone(),
+        }
+    }
+}
+
+impl<M> Default for Channel<M> {
+    fn default() -> Self {
+        Channel::new()
+    }
+}
+
+impl<M> Channel<M> {
+    /// Create a channel with no messages.
+    pub fn new() -> Channel<M> {
+        Channel {
+            queues: Rc::new(RefCell::new(HashMap::default())),
+        }
+    }
+
+    /// Returns a handle to send messages through the channel.
+    pub fn sender(&self) -> Sender<M> {
+        Sender {
+            queues: self.queues.clone(),
+        }
+    }
+
+    /// Returns a handle to read the messages sent to the given process
+    /// through the channel.
+    pub fn receiver(&self, pid: ProcessId) -> Receiver<M> {
+        Receiver {
+            pid,
+            queues: self.queues.clone(),
+        }
+    }
+}
+
+/// The sending end of a `Channel`.
+pub struct Sender<M> {
+    queues: Rc<RefCell<HashMap<ProcessId, VecDeque<M>>>>,
+}
+
+impl<M> Clone for Sender<M> {
+    fn clone(&self) -> Self {
+        Sender {
+            queues: self.queues.clone(),
+        }
+    }
+}
+
+impl<M: 'static> Sender<M> {
+    /// Returns the effect to yield to send the message to the given process,
+    /// see `Effect::ChannelSend`. The message is put in the queue of the
+    /// process when the effect is handled.
+    pub fn send<T, Time, R>(&self, pid: ProcessId, message: M) -> Effect<T, Time, R> {
+        let queues = self.queues.clone();
+        let deliver = move || {
+            queues.borrow_mut().entry(pid).or_insert_with(VecDeque::new).push_back(message);
+        };
+        Effect::ChannelSend(ChannelMessage {
+            to: pid,
+            deliver: Rc::new(RefCell::new(Some(Box::new(deliver)))),
+        })
+    }
+}
+
+/// A message sent through a `Channel`, with its type erased, carried by
+/// `Effect::ChannelSend`. The clones of the effect share the message, which
+/// is delivered once.
+#[derive(Clone)]
+pub struct ChannelMessage {
+    to: ProcessId,
+    deliver: Delivery,
+}
+
+/// Puts a message in the queue of its receiver, taken when it is run.
+type Delivery = Rc<RefCell<Option<Box<dyn FnOnce()>>>>;
+
+impl ChannelMessage {
+    /// Returns the process the message is sent to.
+    pub fn to(&self) -> ProcessId {
+        self.to
+    }
+
+    /// Put the message in the queue of the receiver, if not done yet.
+    fn deliver(&self) {
+        if let Some(deliver) = self.deliver.borrow_mut().take() {
+            deliver();
+        }
+    }
+}
+
+impl Debug for ChannelMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ChannelMessage({:?})", self.to)
+    }
+}
+
+/// The receiving end of a `Channel` for one process.
+pub struct Receiver<M> {
+    pid: ProcessId,
+    queues: Rc<RefCell<HashMap<ProcessId, VecDeque<M>>>>,
+}
+
+impl<M> Receiver<M> {
+    /// Remove and return the oldest message sent to the process, as
+    /// `Context::pop_message` does.
+    pub fn pop_message(&self) -> Option<M> {
+        self.queues.borrow_mut().get_mut(&self.pid).and_then(|q| q.pop_front())
+    }
+
+    /// Returns the number of messages sent to the process and not read yet.
+    pub fn message_count(&self) -> usize {
+        self.queues.borrow().get(&self.pid).map_or(0, |q| q.len())
+    }
+}
+
+/// A context that can be shared between threads, available with the `sync`
+/// feature.
+///
+/// It provides the clock, the inboxes, the interruptions, the random number
+/// generator and the recorded series of `Context`, behind locks. Clones share
+/// the same state. Attached to a simulation with `attach_sync_context`, its
+/// clock follows the one of the simulation, so processes can use it in place
+/// of the `Context` and other threads can read what they recorded.
+#[cfg(feature = "sync")]
+pub struct SyncContext<T, Time = f64> {
+    time: Arc<Mutex<Time>>,
+    /// The inbox of each process, with the priority of each message.
+    messages: Arc<Mutex<HashMap<ProcessId, VecDeque<(u32, T)>>>>,
+    interrupted: Arc<Mutex<HashMap<ProcessId, Option<T>>>>,
+    rng: Arc<Mutex<u64>>,
+    series: Arc<Mutex<HashMap<String, Vec<(Time, f64)>>>>,
+}
+
+#[cfg(feature = "sync")]
+impl<T, Time> Clone for SyncContext<T, Time> {
+    fn clone(&self) -> Self {
+        SyncContext {
+            time: self.time.clone(),
+            messages: self.messages.clone(),
+            interrupted: self.interrupted.clone(),
+            rng: self.rng.clone(),
+            series: self.series.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T, Time: SimTime> SyncContext<T, Time> {
+    pub fn new() -> SyncContext<T, Time> {
+        SyncContext {
+            time: Arc::new(Mutex::new(Time::default())),
+            messages: Arc::new(Mutex::new(HashMap::default())),
+            interrupted: Arc::new(Mutex::new(HashMap::default())),
+            rng: Arc::new(Mutex::new(DEFAULT_SEED)),
+            series: Arc::new(Mutex::new(HashMap::default())),
+        }
+    }
+
+    /// Returns the current simulation time.
+    pub fn time(&self) -> Time {
+        *self.time.lock().unwrap()
+    }
+
+    /// Put a message in the inbox of the given process, with priority 0, as
+    /// `Context::push_message` does.
+    pub fn push_message(&self, pid: ProcessId, message: T) {
+        self.push_message_with_priority(pid, message, 0);
+    }
+
+    /// Put a message in the inbox of the given process, ahead of the ones
+    /// with a higher priority value and after the ones with a lower or equal
+    /// value.
+    pub fn push_message_with_priority(&self, pid: ProcessId, message: T, priority: u32) {
+        let mut m = self.messages.lock().unwrap();
+        let vd = m.entry(pid).or_insert_with(VecDeque::default);
+        let i = vd
+            .iter()
+            .rposition(|&(p, _)| p <= priority)
+            .map_or(0, |i| i + 1);
+        vd.insert(i, (priority, message));
+    }
+
+    /// Returns the number of messages in the inbox of the given process.
+    pub fn message_count(&self, pid: ProcessId) -> usize {
+        self.messages.lock().unwrap().get(&pid).map_or(0, |vd| vd.len())
+    }
+
+    /// Remove and return the oldest message with the lowest priority value
+    /// in the inbox of the given process.
+    pub fn pop_message(&self, pid: ProcessId) -> Option<T> {
+        match self.messages.lock().unwrap().get_mut(&pid) {
+            Some(vd) => vd.pop_front().map(|(_, m)| m),
+            None => None
+        }
+    }
+
+    pub fn interrupt(&self, pid: ProcessId) {
+        self.interrupted.lock().unwrap().insert(pid, None);
+    }
+
+    /// Mark the process as interrupted, with the given reason. It replaces
+    /// the one of an interruption not yet checked.
+    pub fn interrupt_with(&self, pid: ProcessId, payload: T) {
+        self.interrupted.lock().unwrap().insert(pid, Some(payload));
+    }
+
+    /// Returns `None` if the process has not been interrupted, otherwise
+    /// the interruption, with its payload if any. The interruption is
+    /// consumed.
+    pub fn check_interrupted(&self, pid: ProcessId) -> Option<Interrupted<T>> {
+        self.interrupted.lock().unwrap().remove(&pid).map(Interrupted::from_payload)
+    }
+
+    /// Seed the pseudo-random number generator of the context.
+    pub fn seed(&self, seed: u64) {
+        // the state of the xorshift generator must not be zero
+        *self.rng.lock().unwrap() = if seed == 0 { DEFAULT_SEED } else { seed };
+    }
+
+    /// Returns a random number uniformly distributed in `[0, 1)`.
+    pub fn rand_uniform(&self) -> f64 {
+        next_uniform(&mut self.rng.lock().unwrap())
+    }
+
+    /// Returns a random number exponentially distributed with the given rate.
+    pub fn rand_exponential(&self, rate: f64) -> f64 {
+        -(1.0 - self.rand_uniform()).ln() / rate
+    }
+
+    /// Append a sample with the given value, at the current time, to the named
+    /// series.
+    pub fn record(&self, series: &str, value: f64) {
+        let time = self.time();
+        self.series
+            .lock()
+            .unwrap()
+            .entry(series.to_string())
+            .or_insert_with(Vec::new)
+            .push((time, value));
+    }
+
+    /// Returns the `(time, value)` samples recorded in the named series, empty
+    /// if nothing was recorded.
+    pub fn series(&self, name: &str) -> Vec<(Time, f64)> {
+        self.series.lock().unwrap().get(name).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T, Time: SimTime> Default for SyncContext<T, Time> {
+    fn default() -> Self {
+        SyncContext::new()
+    }
+}
+
+/// This struct provides the methods to create and run the simulation
+/// in a single thread.
+///
+/// It provides methods to create processes and finite resources that
+/// must be shared among them.
+///
+/// See the crate-level documentation for more information about how the
+/// simulation framework works
+pub struct Simulation<T, Time = f64, R = ()> {
+    context: Rc<Context<T, Time, R>>,
+    /// The contexts attached with `attach_subcontext`, sorted by the first
+    /// `ProcessId` of each.
+    subcontexts: Vec<(usize, Rc<Context<T, Time, R>>)>,
+    /// The contexts attached with `attach_sync_context`.
+    #[cfg(feature = "sync")]
+    sync_contexts: Vec<SyncContext<T, Time>>,
+    processes: HashMap<ProcessId, Option<ProcessBox<T, Time, R>>>,
+    future_events: EventQueue<Time>,
+    processed_events: Vec<Event<Time>>,
+    /// Which processed events are kept in the log.
+    log_policy: LogPolicy,
+    /// Number of processed events removed from the log.
+    dropped_events: usize,
+    per_process_log: Option<HashMap<ProcessId, Vec<Time>>>,
+    wall_times: Option<Vec<Duration>>,
+    /// The statistics of each process, with the time it was last scheduled.
+    process_stats: Option<HashMap<ProcessId, (ProcessStats, Time)>>,
+    /// Units of each resource held by each process, if tracked.
+    holdings: Option<HashMap<ProcessId, HashMap<ResourceId, usize>>>,
+    /// With hold tracking, the time each process held at least one resource
+    /// before its current holding span, and when that span started.
+    busy: HashMap<ProcessId, (f64, Time)>,
+    resources: Vec<Resource<Time>>,
+    pools: Vec<Pool>,
+    stores: Vec<Store<T>>,
+    selects: HashMap<ProcessId, ArmedSelect<Time>>,
+    message_waiters: HashSet<ProcessId>,
+    /// The processes that yielded `Effect::Gather`, with the number of
+    /// messages they wait for.
+    gathers: HashMap<ProcessId, usize>,
+    signal_waiters: HashMap<SignalId, Vec<ProcessId>>,
+    /// The processes that yielded `Effect::Join`, by the process they wait
+    /// for.
+    joiners: HashMap<ProcessId, Vec<ProcessId>>,
+    /// The members of each group.
+    groups: Vec<Vec<ProcessId>>,
+    deadlock_handler: Option<Box<dyn FnMut(&[(ProcessId, WaitReason)])>>,
+    /// Invoked with each effect yielded, to record or replay a run.
+    effect_hook: Option<EffectHook<T, Time, R>>,
+    /// During a replay, the number of effects matched so far, with the
+    /// process of each effect of the recording.
+    replay_progress: Option<(Rc<Cell<usize>>, Vec<ProcessId>)>,
+    monitors: Vec<Monitor<T, Time, R>>,
+    resource_hooks: Vec<ResourceHook<Time>>,
+    /// Invoked once the events at a time have been processed, see
+    /// `set_timestamp_monitor`.
+    timestamp_monitor: Option<Box<dyn FnMut(Time, &Context<T, Time, R>)>>,
+    /// Whether events have been processed at the current time since the
+    /// timestamp monitor was last invoked.
+    timestamp_pending: bool,
+    /// The last samples of the metric of an `EndCondition::SteadyState`
+    /// during the current run.
+    steady_samples: RefCell<VecDeque<f64>>,
+    results: HashMap<ProcessId, R>,
+    /// When each completed process completed.
+    completion_times: HashMap<ProcessId, Time>,
+    policy: SchedulingPolicy,
+    /// Processes already resumed at the current time in this round, with
+    /// the `RoundRobin` policy.
+    round: HashSet<ProcessId>,
+    /// The event that ends the `TimeOut` or `SleepUntil` of each process
+    /// sleeping, cancelled if the process is interrupted.
+    wakeups: HashMap<ProcessId, EventId>,
+    /// The processes blocked by `Effect::Wait` that have been scheduled
+    /// again and not resumed yet.
+    woken: HashSet<ProcessId>,
+    /// When each process yielding `Effect::TimeOutThrottled` last fired.
+    last_fires: HashMap<ProcessId, Time>,
+    /// How many times each event for a missing process has been deferred.
+    deferred: HashMap<EventId, usize>,
+    /// The messages sent and not yet delivered, with their priority, by the
+    /// event that delivers them.
+    in_transit: HashMap<EventId, (u32, T)>,
+    /// The resources to release, on behalf of their holder, by the event.
+    auto_releases: HashMap<EventId, ResourceId>,
+    /// The processes that yielded `Effect::Hold`, not granted the resource
+    /// yet, with the resource, the holding time and, once granted, the event
+    /// that resumes them.
+    pending_holds: HashMap<ProcessId, (ResourceId, Time, Option<EventId>)>,
+    missing_policy: MissingPolicy,
+    nan_policy: NanPolicy,
+    time_unit: Option<String>,
+    /// Statistics collected before this time are discarded.
+    warmup: Option<Time>,
+    /// Refuse to schedule a process that already has a pending event.
+    unique_scheduling: bool,
+    /// The maximum number of events to process.
+    event_budget: Option<usize>,
+    /// Refuse to wake up twice a process blocked by `Effect::Wait`.
+    strict_wakeups: bool,
+}
+
+/// A metric sampled periodically, added with `Simulation::add_monitor`.
+struct Monitor<T, Time, R> {
+    name: String,
+    interval: Time,
+    next_sample: Time,
+    f: Box<dyn Fn(&Simulation<T, Time, R>) -> f64>,
+    data: Vec<(Time, f64)>,
+}
+
+/// A callback invoked when a resource becomes idle or saturated, added with
+/// `Simulation::on_resource_idle` or `Simulation::on_resource_saturated`.
+struct ResourceHook<Time> {
+    resource: ResourceId,
+    /// Invoked when the resource becomes idle, otherwise when it becomes
+    /// saturated.
+    idle: bool,
+    f: Box<dyn Fn(Time)>,
+    /// Whether the resource was idle, or saturated, after the last step.
+    active: bool,
+}
+
+/// A snapshot of the state of a simulation, taken with `Simulation::snapshot`
+/// and applied with `Simulation::restore`.
+///
+/// It captures the clock, the future events, the event log, the state of
+/// resources and pools (including their queues), the messages in transit,
+/// the processes waiting for a message or a signal, the process groups and
+/// the `Context` messages, interruptions and random number generator state.
+/// It does not capture the processes: generators can not be cloned, so the
+/// state of each of them is the one reached when the snapshot is restored,
+/// unless the user supplies new processes with matching `ProcessId`s.
+pub struct SimulationSnapshot<T, Time = f64, R = ()> {
+    context: Context<T, Time, R>,
+    future_events: EventQueue<Time>,
+    processed_events: Vec<Event<Time>>,
+    dropped_events: usize,
+    per_process_log: Option<HashMap<ProcessId, Vec<Time>>>,
+    resources: Vec<Resource<Time>>,
+    pools: Vec<Pool>,
+    stores: Vec<Store<T>>,
+    selects: HashMap<ProcessId, ArmedSelect<Time>>,
+    message_waiters: HashSet<ProcessId>,
+    gathers: HashMap<ProcessId, usize>,
+    signal_waiters: HashMap<SignalId, Vec<ProcessId>>,
+    joiners: HashMap<ProcessId, Vec<ProcessId>>,
+    groups: Vec<Vec<ProcessId>>,
+    results: HashMap<ProcessId, R>,
+    completion_times: HashMap<ProcessId, Time>,
+    round: HashSet<ProcessId>,
+    wakeups: HashMap<ProcessId, EventId>,
+    woken: HashSet<ProcessId>,
+    last_fires: HashMap<ProcessId, Time>,
+    deferred: HashMap<EventId, usize>,
+    in_transit: HashMap<EventId, (u32, T)>,
+    auto_releases: HashMap<EventId, ResourceId>,
+    pending_holds: HashMap<ProcessId, (ResourceId, Time, Option<EventId>)>,
+    holdings: Option<HashMap<ProcessId, HashMap<ResourceId, usize>>>,
+    busy: HashMap<ProcessId, (f64, Time)>,
+}
+
+/// The structure of a simulation, returned by `Simulation::clone_config`, to
+/// create others with the same setup but different processes, e.g. for
+/// parameter sweeps.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationConfig {
+    /// The capacity and the queue discipline of each resource, in creation
+    /// order.
+    pub resources: Vec<(usize, QueueDiscipline)>,
+    /// The state of the random number generator of the `Context`, i.e. its
+    /// seed if no number was drawn yet.
+    pub seed: u64,
+}
+
+/// The state of a simulation, without the processes, saved with
+/// `Simulation::checkpoint`, e.g. to store it and continue a long running
+/// simulation after a failure.
+///
+/// With the `serde` feature it implements `Serialize` and `Deserialize`.
+/// It contains the clock, the future and processed events, resources, pools,
+/// the items of the stores (but not the processes waiting for one), the
+/// `Context` messages (also the ones in transit), interruptions, signals,
+/// recorded series and random number generator state, the process groups,
+/// the results of the completed processes and which processes are waiting
+/// for what.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct SimulationCheckpoint<T, Time = f64, R = ()> {
+    time: Time,
+    messages: HashMap<ProcessId, VecDeque<(u32, T)>>,
+    interrupted: HashMap<ProcessId, Option<T>>,
+    rng: u64,
+    next_pid: ProcessId,
+    selected: HashMap<ProcessId, Option<usize>>,
+    granted: HashMap<ProcessId, ResourceId>,
+    joined: HashMap<ProcessId, JoinOutcome>,
+    items: HashMap<ProcessId, T>,
+    waiting: HashSet<ProcessId>,
+    created: HashMap<ProcessId, Time>,
+    preemptions: HashMap<ProcessId, (Time, Time)>,
+    signals: Vec<String>,
+    series: HashMap<String, Vec<(Time, f64)>>,
+    population: Population<Time>,
+    /// The future events with their ids, in the order they will be processed.
+    future_events: Vec<(EventId, Event<Time>)>,
+    next_event_id: EventId,
+    processed_events: Vec<Event<Time>>,
+    dropped_events: usize,
+    resources: Vec<Resource<Time>>,
+    pools: Vec<Pool>,
+    stores: Vec<Store<T>>,
+    selects: HashMap<ProcessId, ArmedSelect<Time>>,
+    message_waiters: HashSet<ProcessId>,
+    /// The processes that yielded `Effect::Gather`, with the number of
+    /// messages they wait for.
+    gathers: HashMap<ProcessId, usize>,
+    signal_waiters: HashMap<SignalId, Vec<ProcessId>>,
+    joiners: HashMap<ProcessId, Vec<ProcessId>>,
+    groups: Vec<Vec<ProcessId>>,
+    wakeups: HashMap<ProcessId, EventId>,
+    woken: HashSet<ProcessId>,
+    last_fires: HashMap<ProcessId, Time>,
+    deferred: HashMap<EventId, usize>,
+    in_transit: HashMap<EventId, (u32, T)>,
+    auto_releases: HashMap<EventId, ResourceId>,
+    pending_holds: HashMap<ProcessId, (ResourceId, Time, Option<EventId>)>,
+    completed: Vec<ProcessId>,
+    results: HashMap<ProcessId, R>,
+    completion_times: HashMap<ProcessId, Time>,
+    holdings: Option<HashMap<ProcessId, HashMap<ResourceId, usize>>>,
+    busy: HashMap<ProcessId, (f64, Time)>,
+}
+
+impl<T, Time: SimTime, R> SimulationCheckpoint<T, Time, R> {
+    /// Returns the simulation time at which the checkpoint was taken.
+    pub fn time(&self) -> Time {
+        self.time
+    }
+}
+
+impl<T, Time: SimTime, R> SimulationSnapshot<T, Time, R> {
+    /// Returns the simulation time at which the snapshot was taken.
+    pub fn time(&self) -> Time {
+        self.context.time()
+    }
+}
+
+/// Cloning a simulation copies the clock, the future events, the resources,
+/// the event log and the `Context`, which is not shared with the original.
+/// The deadlock handler, the monitors and the resource callbacks are not
+/// cloned.
+///
+/// Generators can not be cloned, so the clone shares no generator state with
+/// the original simulation. Processes that have already completed remain
+/// completed in the clone, while the ones that are still alive are not
+/// present and must be created again, with the same `ProcessId`, before the
+/// events targeting them are processed. Use `shared_context` on the clone to
+/// obtain the `Context` to be used by the new generators.
+impl<T: Clone, Time: SimTime, R: Clone> Clone for Simulation<T, Time, R> {
+    fn clone(&self) -> Self {
+        Simulation {
+            context: Rc::new((*self.context).clone()),
+            subcontexts: self.subcontexts.iter().map(|(o, c)| (*o, Rc::new((**c).clone()))).collect(),
+            #[cfg(feature = "sync")]
+            sync_contexts: Vec::new(),
+            processes: self
+                .processes
+                .iter()
+                .filter(|(_, p)| p.is_none())
+                .map(|(pid, _)| (*pid, None))
+                .collect(),
+            future_events: self.future_events.clone(),
+            processed_events: self.processed_events.clone(),
+            log_policy: self.log_policy,
+            dropped_events: self.dropped_events,
+            per_process_log: self.per_process_log.clone(),
+            wall_times: self.wall_times.clone(),
+            process_stats: self.process_stats.clone(),
+            holdings: self.holdings.clone(),
+            busy: self.busy.clone(),
+            resources: self.resources.clone(),
+            pools: self.pools.clone(),
+            stores: self.stores.clone(),
+            selects: self.selects.clone(),
+            message_waiters: self.message_waiters.clone(),
+            gathers: self.gathers.clone(),
+            signal_waiters: self.signal_waiters.clone(),
+            joiners: self.joiners.clone(),
+            groups: self.groups.clone(),
+            deadlock_handler: None,
+            effect_hook: None,
+            replay_progress: None,
+            monitors: Vec::new(),
+            resource_hooks: Vec::new(),
+            timestamp_monitor: None,
+            timestamp_pending: false,
+            steady_samples: RefCell::new(VecDeque::new()),
+            results: self.results.clone(),
+            completion_times: self.completion_times.clone(),
+            policy: self.policy,
+            missing_policy: self.missing_policy,
+            nan_policy: self.nan_policy,
+            time_unit: self.time_unit.clone(),
+            round: self.round.clone(),
+            wakeups: self.wakeups.clone(),
+            woken: self.woken.clone(),
+            last_fires: self.last_fires.clone(),
+            deferred: self.deferred.clone(),
+            in_transit: self.in_transit.clone(),
+            auto_releases: self.auto_releases.clone(),
+            pending_holds: self.pending_holds.clone(),
+            warmup: self.warmup,
+            unique_scheduling: self.unique_scheduling,
+            event_budget: self.event_budget,
+            strict_wakeups: self.strict_wakeups,
+        }
+    }
+}
+
+/// Shows the state of the simulation in short, without the processes
+/// themselves.
+impl<T, Time: SimTime, R> Debug for Simulation<T, Time, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Simulation")
+            .field("time", &self.context.time())
+            .field("processes", &self.processes.len())
+            .field("future_events", &self.future_events.len())
+            .field("processed_events", &self.processed_event_count())
+            .finish()
+    }
+}
+
+/*
+pub struct ParallelSimulation {
+    processes: Vec<Box<Generator<Yield = Effect, Return = ()>>>
+}
+ */
+
+/// An event that can be scheduled by a process, yelding the `Event` `Effect`
+/// or by the owner of a `Simulation` through the `schedule` method
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone)]
+pub struct Event<Time = f64> {
+    /// Time interval between the current simulation time and the event
+    /// schedule when yielded with `Effect::Event`, otherwise the absolute
+    /// time of the event.
+    pub time: Time,
+    /// Process to execute when the event occur
+    pub process: ProcessId,
+    /// Optional tag, e.g. "arrival", kept in the log of processed events
+    /// for reporting. The "arrival" and "departure" tags are counted by
+    /// `Simulation::arrival_count` and `Simulation::departure_count`. It
+    /// does not take part in the comparisons and it is not saved in
+    /// checkpoints.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub label: Option<&'static str>,
+}
+
+/// Specify which condition must be met for the simulation to stop.
+pub enum EndCondition<T, Time = f64, R = ()> {
+    /// Run the simulation until a certain point in time is reached.
+    Time(Time),
+    /// Run the simulation until there are no more events scheduled, see
+    /// `Simulation::is_idle`.
+    NoEvents,
+    /// Execute exactly N steps of the simulation, counted from the start of
+    /// the run, or fewer if the events run out.
+    NSteps(usize),
+    /// Run the simulation until the given process completes. If it never
+    /// does, e.g. because it is unknown or was cancelled, the simulation runs
+    /// until there are no more events, as with `NoEvents`.
+    ProcessDone(ProcessId),
+    /// Run the simulation until the predicate, called before each step,
+    /// returns `true`.
+    Custom(Box<dyn Fn(&Simulation<T, Time, R>) -> bool>),
+    /// Run the simulation until the metric, sampled before each step, is
+    /// steady: its last `window` samples all differ from their mean by at
+    /// most `tolerance`. If it never is, the simulation runs until there are
+    /// no more events, as with `NoEvents`.
+    SteadyState {
+        /// The metric to sample, e.g. the mean length of a queue.
+        metric: Box<dyn Fn(&Simulation<T, Time, R>) -> f64>,
+        /// The number of samples considered, that must be positive.
+        window: usize,
+        /// The maximum difference between a sample and the mean.
+        tolerance: f64,
+    },
+}
+
+/// The order in which the events scheduled for the same time are processed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SchedulingPolicy {
+    /// In the order they were scheduled.
+    Fifo,
+    /// In rounds: every process with an event at the current time is resumed
+    /// once before any of them is resumed again, so that a process can not
+    /// monopolize a time instant.
+    RoundRobin,
+}
+
+/// What to do with an event for a process that does not exist, e.g. because
+/// it is created later on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MissingPolicy {
+    /// Panic, the default.
+    Panic,
+    /// Drop the event, printing a warning.
+    Drop,
+    /// Schedule the event again at the same time, after the other events
+    /// already scheduled, up to the given number of times, then drop it.
+    Defer(usize),
+}
+
+/// What to do with an event scheduled at a NaN or infinite time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NanPolicy {
+    /// Return the error, the default.
+    Reject,
+    /// Panic where the event is scheduled.
+    Panic,
+    /// Drop the event, printing a warning.
+    Skip,
+}
+
+/// Which processed events are kept in the log of the simulation, see
+/// `Simulation::set_log_policy`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LogPolicy {
+    /// Keep all the events, the default.
+    All,
+    /// Keep no event.
+    None,
+    /// Keep only the given number of most recent events.
+    LastN(usize),
+    /// Keep one event every given number, starting from the first one.
+    Sampled(usize),
+}
+
+impl<T, Time: SimTime, R> Simulation<T, Time, R> {
+    /// Create a new `Simulation` environment.
+    ///
+    /// Use `builder` to set options, like capacity hints or a warm-up time.
+    pub fn new(ctx: Rc<Context<T, Time, R>>) -> Simulation<T, Time, R> {
+        SimulationBuilder::from_context(ctx).build().0
+    }
+
+    /// Create a new `Simulation` with the resources and the seed of the
+    /// given configuration, ready for new processes.
+    pub fn from_config(ctx: Rc<Context<T, Time, R>>, config: &SimulationConfig) -> Simulation<T, Time, R> {
+        ctx.seed(config.seed);
+        let mut s = Simulation::new(ctx);
+        for &(n, discipline) in &config.resources {
+            s.create_resource(n, discipline);
+        }
+        s
+    }
+
+    /// Returns the configuration of the simulation, i.e. its resources and
+    /// the state of its random number generator, to create others with
+    /// `from_config`.
+    pub fn clone_config(&self) -> SimulationConfig {
+        SimulationConfig {
+            resources: self.resources.iter().map(|r| (r.allocated, r.queue.discipline)).collect(),
+            seed: self.context.rng.get(),
+        }
+    }
+
+    /// Create a new `Simulation` together with its `Context`, returning a
+    /// handle to the latter for the generators.
+    pub fn with_context() -> (Simulation<T, Time, R>, Rc<Context<T, Time, R>>) {
+        SimulationBuilder::new().build()
+    }
+
+    /// Create a new `Simulation` that processes the events in the order given
+    /// by `cmp` instead of by time only, e.g. to break ties by process.
+    /// Events that compare equal are processed in the order they were
+    /// scheduled.
+    ///
+    /// `cmp` must order events by time first: an event taken before an
+    /// earlier one makes `step` return a `TimeError`. The
+    /// ordering is not saved in checkpoints.
+    pub fn with_ordering<F>(ctx: Rc<Context<T, Time, R>>, cmp: F) -> Simulation<T, Time, R>
+    where
+        F: Fn(&Event<Time>, &Event<Time>) -> Ordering + 'static,
+    {
+        let mut s = Simulation::new(ctx);
+        s.future_events.ordering = Some(Rc::new(cmp));
+        s
+    }
+
+    /// Attach another `Context`, e.g. for an independent subsystem, whose
+    /// processes have ids from `pid_offset` on, up to the offset of the next
+    /// attached context. Processes spawned through it get ids from the
+    /// offset on, and its clock follows the one of the simulation.
+    ///
+    /// Messages and interruptions, sent by effects or pushed to any context,
+    /// reach the context of the target process. Attached contexts are not
+    /// captured by snapshots and checkpoints.
+    ///
+    /// Panics if `pid_offset` is 0 or used by another attached context.
+    pub fn attach_subcontext(&mut self, ctx: Rc<Context<T, Time, R>>, pid_offset: usize) {
+        if pid_offset == 0 || self.subcontexts.iter().any(|&(o, _)| o == pid_offset) {
+            panic!("ERROR: the offset {} of the context is already in use", pid_offset);
+        }
+        ctx.time.set(self.context.time());
+        if ctx.next_pid.get().0 < pid_offset {
+            ctx.next_pid.set(ProcessId(pid_offset));
+        }
+        let i = self.subcontexts.iter().position(|&(o, _)| o > pid_offset).unwrap_or(self.subcontexts.len());
+        self.subcontexts.insert(i, (pid_offset, ctx));
+    }
+
+    /// Attach a `SyncContext`, available with the `sync` feature, whose clock
+    /// follows the one of the simulation from now on. Its messages and
+    /// interruptions are read by the processes themselves: effects still
+    /// deliver them to the `Context` of the target process.
+    ///
+    /// Attached sync contexts are not cloned, nor captured by snapshots and
+    /// checkpoints.
+    #[cfg(feature = "sync")]
+    pub fn attach_sync_context(&mut self, ctx: SyncContext<T, Time>)
+    where
+        T: Send,
+    {
+        *ctx.time.lock().unwrap() = self.context.time();
+        self.sync_contexts.push(ctx);
+    }
+
+    /// Start building a simulation with a new `Context`, see
+    /// `SimulationBuilder`.
+    pub fn builder() -> SimulationBuilder<T, Time, R> {
+        SimulationBuilder::new()
+    }
+
+    /// Returns the log of processed events
+    pub fn processed_events(&self) -> &[Event<Time>] {
+        match self.log_policy {
+            LogPolicy::LastN(n) if self.processed_events.len() > n => {
+                &self.processed_events[self.processed_events.len() - n..]
+            }
+            _ => self.processed_events.as_slice(),
+        }
+    }
+
+    /// Returns the number of events processed, including the ones no longer
+    /// in the log because of `shrink_log` or the `LogPolicy`.
+    pub fn processed_event_count(&self) -> u64 {
+        (self.dropped_events + self.processed_events.len()) as u64
+    }
+
+    /// Returns the processed events with the given label.
+    pub fn events_with_label<'a>(&'a self, label: &'a str) -> impl Iterator<Item = &'a Event<Time>> {
+        self.processed_events().iter().filter(move |e| e.label == Some(label))
+    }
+
+    /// Returns the number of processed events with the "arrival" label.
+    /// Only the events still in the log are counted, see `set_log_policy`.
+    pub fn arrival_count(&self) -> usize {
+        self.events_with_label("arrival").count()
+    }
+
+    /// Returns the number of processed events with the "departure" label.
+    /// Only the events still in the log are counted, see `set_log_policy`.
+    pub fn departure_count(&self) -> usize {
+        self.events_with_label("departure").count()
+    }
+
+    /// Returns the number of departures per unit of time since the start of
+    /// the simulation, or 0 if no time has elapsed.
+    pub fn throughput(&self) -> f64 {
+        let elapsed = self.context.time().to_f64();
+        if elapsed > 0.0 {
+            self.departure_count() as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the processed events with a time in `[from, to)`, found with a
+    /// binary search since the log is sorted by time.
+    pub fn events_between(&self, from: Time, to: Time) -> std::slice::Iter<Event<Time>> {
+        let log = self.processed_events();
+        let start = log.partition_point(|e| e.time < from);
+        let end = log.partition_point(|e| e.time < to).max(start);
+        log[start..end].iter()
+    }
+
+    /// Returns the number of events processed in the last `window` time
+    /// units, i.e. with a time in `(now - window, now]`, divided by `window`,
+    /// e.g. for a stopping rule on the throughput of the model. Only the
+    /// events still in the log are counted, see `set_log_policy`.
+    ///
+    /// Panics if `window` is not positive.
+    pub fn event_rate(&self, window: Time) -> f64 {
+        assert!(window > Time::default(), "ERROR: the window of the event rate must be positive");
+        let now = self.context.time();
+        let log = self.processed_events();
+        let start = if window < now {
+            log.partition_point(|e| e.time <= now - window)
+        } else {
+            0
+        };
+        (log.len() - start) as f64 / window.to_f64()
+    }
+
+    /// Remove from the log the processed events with a time before the given
+    /// one, e.g. to bound the memory used by long simulations.
+    ///
+    /// `validate` may then report the processes whose events were removed
+    /// as never scheduled.
+    pub fn shrink_log(&mut self, before: Time) {
+        let n = self.processed_events.partition_point(|e| e.time < before);
+        self.processed_events.drain(..n);
+        self.dropped_events += n;
+    }
+
+    /// Keep in the log only the `n` most recent processed events, as a
+    /// circular buffer. The events removed are still counted by `summary`.
+    pub fn set_log_capacity(&mut self, n: usize) {
+        self.set_log_policy(LogPolicy::LastN(n));
+    }
+
+    /// Set which processed events are kept in the log, e.g. to bound the
+    /// memory used by long simulations. The default is `LogPolicy::All`.
+    ///
+    /// The events already in the log are trimmed for `LogPolicy::LastN`,
+    /// and kept otherwise. The events not kept are still counted by
+    /// `summary`, but `validate` may report their processes as never
+    /// scheduled.
+    pub fn set_log_policy(&mut self, policy: LogPolicy) {
+        if let LogPolicy::Sampled(k) = policy {
+            assert!(k > 0, "ERROR: the sampling interval of the log must be positive");
+        }
+        self.log_policy = policy;
+        if let LogPolicy::LastN(n) = policy {
+            self.trim_log(n);
+        }
+    }
+
+    /// Remove from the log all the events but the `n` most recent.
+    fn trim_log(&mut self, n: usize) {
+        let excess = self.processed_events.len().saturating_sub(n);
+        self.processed_events.drain(..excess);
+        self.dropped_events += excess;
+    }
+
+    /// Add a monitor, that samples the metric computed by `f` every
+    /// `sample_interval`, starting from the current time.
+    ///
+    /// The samples are taken while the simulation runs, when the time of the
+    /// next event is reached, so they reflect the state of the simulation
+    /// before the events that happen at the very same time are processed.
+    ///
+    /// Returns the identifier of the monitor, to retrieve the collected data
+    /// with `monitor_data`.
+    pub fn add_monitor(
+        &mut self,
+        name: &str,
+        sample_interval: Time,
+        f: Box<dyn Fn(&Simulation<T, Time, R>) -> f64>,
+    ) -> MonitorId {
+        assert!(sample_interval > Time::default(), "ERROR: the sample interval must be positive");
+        let id = self.monitors.len();
+        self.monitors.push(Monitor {
+            name: name.to_string(),
+            interval: sample_interval,
+            next_sample: self.context.time(),
+            f,
+            data: Vec::new(),
+        });
+        id
+    }
+
+    /// Returns the name of the monitor.
+    pub fn monitor_name(&self, id: MonitorId) -> &str {
+        &self.monitors[id].name
+    }
+
+    /// Returns the `(time, value)` samples collected by the monitor.
+    pub fn monitor_data(&self, id: MonitorId) -> &[(Time, f64)] {
+        self.monitors[id].data.as_slice()
+    }
+
+    /// Returns the `(time, value)` samples recorded with `Context::record` in
+    /// the named series, empty if nothing was recorded.
+    pub fn series(&self, name: &str) -> Vec<(Time, f64)> {
+        self.context.series.borrow().get(name).cloned().unwrap_or_default()
+    }
+
+    /// Returns a histogram of the values recorded in the named series, as
+    /// the lower bound and the number of values of each of `bins` intervals
+    /// of the same width, between the minimum and the maximum value.
+    pub fn histogram(&self, name: &str, bins: usize) -> Vec<(f64, usize)> {
+        assert!(bins > 0, "ERROR: a histogram needs at least one bin");
+        let series = self.context.series.borrow();
+        let values: Vec<f64> = match series.get(name) {
+            Some(samples) if !samples.is_empty() => samples.iter().map(|s| s.1).collect(),
+            _ => return Vec::new(),
+        };
+        let min = values.iter().cloned().fold(std::f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(std::f64::NEG_INFINITY, f64::max);
+        let width = (max - min) / bins as f64;
+        let mut counts = vec![0; bins];
+        for v in values {
+            let bin = if width > 0.0 { ((v - min) / width) as usize } else { 0 };
+            // the maximum belongs to the last bin
+            counts[bin.min(bins - 1)] += 1;
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| (min + i as f64 * width, c))
+            .collect()
+    }
+
+    /// Returns the time-average number of entities in the system, counted
+    /// with `Context::entity_enter` and `Context::entity_exit`, from time 0
+    /// to now, e.g. to check Little's law against the arrival rate and the
+    /// mean time spent in the system. Returns 0 at time 0.
+    pub fn mean_number_in_system(&self) -> f64 {
+        let p = self.context.population.get();
+        let now = self.context.time();
+        let elapsed = now.to_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+        (p.area + p.count as f64 * (now - p.since).to_f64()) / elapsed
+    }
+
+    /// Returns the number of events scheduled and not processed yet.
+    pub fn future_event_count(&self) -> usize {
+        self.future_events.len()
+    }
+
+    /// Returns the events scheduled and not processed yet, in no particular
+    /// order.
+    pub fn future_events(&self) -> impl Iterator<Item = &Event<Time>> {
+        self.future_events.iter()
+    }
+
+    /// Returns the events scheduled and not processed yet, in the order
+    /// they will be processed.
+    pub fn future_events_sorted(&self) -> Vec<Event<Time>> {
+        self.future_events.entries().into_iter().map(|(_, e)| e).collect()
+    }
+
+    /// Print the log of processed events to the standard output, one per
+    /// line, preceded by their time and unit if one is set with
+    /// `set_time_unit`.
+    pub fn print_trace(&self) {
+        for e in self.processed_events().iter() {
+            match self.time_unit {
+                Some(ref unit) => println!("[{:?} {}] {}", e.time, unit, e),
+                None => println!("{}", e),
+            }
+        }
+    }
+
+    /// Make `step`, and so the runs, fail with
+    /// `SimulationError::EventBudgetExceeded` once the given number of events
+    /// has been processed, whatever the end condition, e.g. to stop a model
+    /// that keeps scheduling events at the same time. `None` removes the
+    /// limit.
+    pub fn set_event_budget(&mut self, budget: Option<usize>) {
+        self.event_budget = budget;
+    }
+
+    /// Set the unit of the simulation time, e.g. "ms" or "cycles", shown in
+    /// the trace output.
+    pub fn set_time_unit(&mut self, label: &str) {
+        self.time_unit = Some(label.to_string());
+    }
+
+    /// Returns the unit of the simulation time, if set.
+    pub fn time_unit(&self) -> Option<&str> {
+        self.time_unit.as_ref().map(|u| u.as_str())
+    }
+
+    /// Returns the number of processes that have not completed yet.
+    pub fn live_process_count(&self) -> usize {
+        self.processes.values().filter(|p| p.is_some()).count()
+    }
+
+    /// Returns the number of processes that have completed.
+    pub fn completed_process_count(&self) -> usize {
+        self.processes.values().filter(|p| p.is_none()).count()
+    }
+
+    /// Returns how many of the events scheduled now `run(until)` would
+    /// process, without resuming any process, e.g. to estimate the length of
+    /// a run.
+    ///
+    /// The events the processes would schedule when resumed are not known,
+    /// so the count is exact only if they schedule none. `ProcessDone` and
+    /// `Custom` depend on the processes and are treated as `NoEvents`.
+    pub fn count_events_to(&self, until: EndCondition<T, Time, R>) -> usize {
+        let events = self.future_events.entries();
+        match until {
+            EndCondition::Time(t) => {
+                let mut now = self.context.time();
+                let mut count = 0;
+                for (_, e) in events {
+                    if now >= t {
+                        break;
+                    }
+                    now = e.time;
+                    count += 1;
+                }
+                count
+            }
+            EndCondition::NSteps(n) => n.min(events.len()),
+            _ => events.len(),
+        }
+    }
+
+    /// Returns the summary of the run so far.
+    pub fn summary(&self) -> RunSummary<Time> {
+        let end_time = self.context.time();
+        let events_processed = self.processed_event_count() as usize;
+        let elapsed = end_time.to_f64();
+        RunSummary {
+            end_time,
+            events_processed,
+            events_per_time_unit: if elapsed > 0.0 { events_processed as f64 / elapsed } else { 0.0 },
+            live_processes: self.live_process_count(),
+            completed_processes: self.completed_process_count(),
+            resources: self.resources.len(),
+        }
+    }
+
+    /// Iterate, in no particular order, over the ids of the processes created,
+    /// including the ones that have completed.
+    pub fn process_ids<'a>(&'a self) -> impl Iterator<Item = ProcessId> + 'a {
+        self.processes.keys().cloned()
+    }
+
+    /// Returns the next event that will be processed, if any, without
+    /// processing it.
+    pub fn peek_next_event(&self) -> Option<&Event<Time>> {
+        self.future_events.peek()
+    }
+
+    /// Keep, from now on, a separate log of the times of the events
+    /// processed for each process.
+    ///
+    /// This makes `process_event_times` available, at the cost of storing
+    /// every event time twice.
+    pub fn enable_per_process_log(&mut self) {
+        if self.per_process_log.is_none() {
+            self.per_process_log = Some(HashMap::default());
+        }
+    }
+
+    /// Returns the times of the events processed for the given process, in
+    /// the order they were processed.
+    ///
+    /// Returns `None` if the per process log is not enabled or if no event
+    /// has been processed for that process since it was enabled.
+    pub fn process_event_times(&self, pid: ProcessId) -> Option<&[Time]> {
+        self.per_process_log
+            .as_ref()
+            .and_then(|log| log.get(&pid))
+            .map(|times| times.as_slice())
+    }
+
+    /// Keep track of the units of each resource held by each process, so that
+    /// releasing a resource not held is reported by `step` as
+    /// `SimulationError::ReleaseWithoutHold`, instead of going unnoticed or
+    /// making the simulation panic later on.
+    ///
+    /// Must be enabled before any resource is acquired.
+    pub fn enable_hold_tracking(&mut self) {
+        if self.holdings.is_none() {
+            self.holdings = Some(HashMap::default());
+        }
+    }
+
+    /// Returns the total simulated time the process held at least one unit
+    /// of some resource, including the current holding span if any.
+    ///
+    /// Requires `enable_hold_tracking`, otherwise it is always 0.
+    pub fn process_busy_time(&self, pid: ProcessId) -> f64 {
+        let holding = self
+            .holdings
+            .as_ref()
+            .and_then(|holdings| holdings.get(&pid))
+            .map_or(false, |held| held.values().any(|&n| n > 0));
+        match self.busy.get(&pid) {
+            Some(&(total, since)) if holding => total + (self.context.time() - since).to_f64(),
+            Some(&(total, _)) => total,
+            None => 0.0,
+        }
+    }
+
+    /// Measure, from now on, the real time taken by each step that processes
+    /// an event.
+    pub fn enable_wall_clock_tracking(&mut self) {
+        if self.wall_times.is_none() {
+            self.wall_times = Some(Vec::new());
+        }
+    }
+
+    /// Returns the real time taken by each step since the tracking was
+    /// enabled, or an empty slice if it is not enabled.
+    pub fn wall_time_per_step(&self) -> &[Duration] {
+        self.wall_times.as_ref().map(|t| t.as_slice()).unwrap_or(&[])
+    }
+
+    /// Collect, from now on, the `ProcessStats` of each process.
+    pub fn enable_process_stats(&mut self) {
+        if self.process_stats.is_none() {
+            self.process_stats = Some(HashMap::default());
+        }
+    }
+
+    /// Returns the statistics of the given process, or `None` if they are
+    /// not enabled or the process has not been resumed since.
+    pub fn process_stats(&self, pid: ProcessId) -> Option<&ProcessStats> {
+        self.process_stats
+            .as_ref()
+            .and_then(|stats| stats.get(&pid))
+            .map(|&(ref stats, _)| stats)
+    }
+
+    /// Returns the current simulation time.
+    ///
+    /// This is the same as reading the time from the `Context`, without the
+    /// need to keep a separate handle to it.
+    ///
+    /// ```
+    /// #![feature(generators, generator_trait)]
+    /// # extern crate desim;
+    /// # use std::rc::Rc;
+    /// use desim::{Context, Effect, Event, Simulation, EndCondition, ProcessId};
+    ///
+    /// # fn main() {
+    /// let mut s = Simulation::new(Rc::new(Context::<()>::new()));
+    /// s.create_process(ProcessId(0), Box::new(|| {
+    ///     yield Effect::TimeOut(3.0);
+    /// }));
+    /// s.schedule_event(Event { time: 1.0, process: ProcessId(0), label: None }).unwrap();
+    /// let s = s.run(EndCondition::NoEvents);
+    /// assert_eq!(s.now(), 4.0);
+    /// # }
+    /// ```
+    pub fn now(&self) -> Time {
+        self.context.time()
+    }
+
+    /// Returns the `Context` shared with the processes of this simulation,
+    /// e.g. to access the messaging and interruption helpers.
+    pub fn context(&self) -> &Context<T, Time, R> {
+        &self.context
+    }
+
+    /// Returns a new handle to the `Context` shared with the processes of
+    /// this simulation.
+    ///
+    /// This is mostly useful after cloning or forking a simulation, to build
+    /// the generators that must be supplied to the copy.
+    pub fn shared_context(&self) -> Rc<Context<T, Time, R>> {
+        self.context.clone()
+    }
+
+    /// Create a process.
+    ///
+    /// For more information about a process, see the crate level documentation
+    ///
+    /// Returns the identifier of the process.
+    pub fn create_process(
+        &mut self,
+        pid: ProcessId,
+        process: ProcessBox<T, Time, R>,
+    ) {
+        if self.processes.contains_key(&pid) {
+            panic!("ERROR: duplicate PID {}", pid);
+        }
+        let ctx = self.context_of(pid);
+        if pid >= ctx.next_pid.get() {
+            ctx.next_pid.set(ProcessId(pid.0 + 1));
+        }
+        ctx.created.borrow_mut().insert(pid, ctx.time());
+        self.processes.insert(pid, Some(process));
+    }
+
+    /// Create a process and schedule it at `start_time`, e.g. between two
+    /// steps to inject the arrivals read from a trace.
+    ///
+    /// Returns an error, and leaves the simulation unchanged, if a process
+    /// with the same id exists. Panics if `start_time` is NaN or infinite.
+    pub fn fork_process_at(
+        &mut self,
+        pid: ProcessId,
+        process: ProcessBox<T, Time, R>,
+        start_time: Time,
+    ) -> Result<(), DuplicatePidError> {
+        if self.processes.contains_key(&pid) {
+            return Err(DuplicatePidError(pid));
+        }
+        self.create_process(pid, process);
+        if let Err(e) = self.schedule_event(Event { time: start_time, process: pid, label: None }) {
+            panic!("ERROR: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Take the value returned by the process when it completed.
+    ///
+    /// Returns `None` if the process has not completed yet or if its result
+    /// has already been taken.
+    ///
+    /// The type of the results is the third type parameter of `Simulation`,
+    /// `()` by default. To collect values from the processes, choose it when
+    /// creating the `Context` and `return` them from the generators:
+    ///
+    /// ```
+    /// #![feature(generators, generator_trait)]
+    /// # extern crate desim;
+    /// # use std::rc::Rc;
+    /// use desim::{Context, Effect, Event, Simulation, EndCondition, ProcessId};
+    ///
+    /// struct Outcome {
+    ///     finished_at: f64,
+    /// }
+    ///
+    /// # fn main() {
+    /// // before: Context::<()>::new()
+    /// let ctx = Rc::new(Context::<(), f64, Outcome>::new());
+    /// let mut s = Simulation::new(ctx.clone());
+    /// s.create_process(ProcessId(0), Box::new(move || {
+    ///     yield Effect::TimeOut(3.0);
+    ///     Outcome { finished_at: ctx.time() }
+    /// }));
+    /// s.schedule_event(Event { time: 1.0, process: ProcessId(0), label: None }).unwrap();
+    /// let mut s = s.run(EndCondition::NoEvents);
+    /// assert_eq!(s.take_result(ProcessId(0)).unwrap().finished_at, 4.0);
+    /// assert!(s.take_result(ProcessId(0)).is_none());
+    /// # }
+    /// ```
+    pub fn take_result(&mut self, pid: ProcessId) -> Option<R> {
+        self.results.remove(&pid)
+    }
+
+    /// Returns the time the process completed, or `None` if it has not
+    /// completed or was cancelled.
+    pub fn completion_time(&self, pid: ProcessId) -> Option<Time> {
+        self.completion_times.get(&pid).cloned()
+    }
+
+    /// Replace the generator of a live process, e.g. to switch to another
+    /// behavior at a phase change. The process keeps its id, its pending
+    /// events, its place in the queues and its inbox: the next event for it
+    /// resumes the new generator from its start.
+    ///
+    /// Resources and pool capacity held by the process are not released and
+    /// the new generator is not told about them: release them before
+    /// replacing the process, or from the new generator.
+    ///
+    /// Panics if the process does not exist or has completed.
+    pub fn replace_process(&mut self, pid: ProcessId, process: ProcessBox<T, Time, R>) {
+        match self.processes.get_mut(&pid) {
+            Some(p @ Some(_)) => *p = Some(process),
+            _ => panic!("ERROR: process {} does not exist or has completed", pid),
+        }
+    }
+
+    /// Remove a process from the simulation, together with all its pending
+    /// events, and take it out of the queues of resources and pools. The
+    /// messages sent to it, also the ones in transit, and its interruption
+    /// are dropped. The processes that joined it are resumed, with
+    /// `JoinOutcome::Cancelled`.
+    ///
+    /// Resources and pool capacity held by the process are not released:
+    /// release them before cancelling the process if needed. The releases
+    /// scheduled by `Effect::ReleaseAfter` are kept and still happen at their
+    /// time.
+    pub fn cancel_process(&mut self, pid: ProcessId) {
+        self.processes.remove(&pid);
+        self.cancel_process_events(pid);
+        self.pending_holds.remove(&pid);
+        self.woken.remove(&pid);
+        self.last_fires.remove(&pid);
+        self.selects.remove(&pid);
+        self.message_waiters.remove(&pid);
+        self.gathers.remove(&pid);
+        let ctx = self.context_of(pid);
+        ctx.waiting.borrow_mut().remove(&pid);
+        // nobody will read them
+        ctx.messages.borrow_mut().remove(&pid);
+        ctx.interrupted.borrow_mut().remove(&pid);
+        ctx.items.borrow_mut().remove(&pid);
+        for waiters in self.signal_waiters.values_mut() {
+            waiters.retain(|&p| p != pid);
+        }
+        for joiners in self.joiners.values_mut() {
+            joiners.retain(|&p| p != pid);
+        }
+        self.leave_queues(pid);
+        self.leave_groups(pid);
+        self.wake_joiners(pid, JoinOutcome::Cancelled);
+    }
+
+    /// Resume the processes waiting for the given one to end.
+    fn wake_joiners(&mut self, pid: ProcessId, outcome: JoinOutcome) {
+        let now = self.context.time();
+        for p in self.joiners.remove(&pid).unwrap_or_default() {
+            self.context_of(p).joined.borrow_mut().insert(p, outcome);
+            self.future_events.push(Event { time: now, process: p, label: None });
+        }
+    }
+
+    /// Remove the pending events of the process, e.g. the ends of its
+    /// timeouts, so that they do not resume it, and return how many were
+    /// removed. The messages in transit to the process are dropped, while
+    /// the releases scheduled by `Effect::ReleaseAfter` are kept.
+    pub fn cancel_process_events(&mut self, pid: ProcessId) -> usize {
+        let ids: Vec<EventId> = self
+            .future_events
+            .heap
+            .iter()
+            .filter(|e| e.1.process == pid && !self.auto_releases.contains_key(&e.0))
+            .map(|e| e.0)
+            .collect();
+        for id in ids.iter() {
+            self.future_events.remove(*id);
+            self.in_transit.remove(id);
+        }
+        self.wakeups.remove(&pid);
+        ids.len()
+    }
+
+    /// Remove the process from the groups it belongs to.
+    fn leave_groups(&mut self, pid: ProcessId) {
+        for group in self.groups.iter_mut() {
+            group.retain(|&p| p != pid);
+        }
+    }
+
+    /// Take the process out of the queues of resources, pools and stores, serving
+    /// the ones behind it if they can be served now.
+    fn leave_queues(&mut self, pid: ProcessId) {
+        for r in self.resource_ids() {
+            if self.resources[r.0].queue.iter().any(|w| w.process == pid) {
+                self.resources[r.0].queue.retain(|w| w.process != pid);
+                self.wake_ready(r);
+            }
+        }
+        let now = self.context.time();
+        for pool in self.pools.iter_mut() {
+            if pool.queue.iter().any(|w| w.0 == pid) {
+                pool.queue.retain(|w| w.0 != pid);
+                for p in pool.dequeue_ready() {
+                    self.future_events.push(Event { time: now, process: p, label: None });
+                }
+            }
+        }
+        for store in self.stores.iter_mut() {
+            store.queue.retain(|w| w.0 != pid);
+        }
+    }
+
+    /// Create a new, empty, group of processes.
+    pub fn create_group(&mut self) -> GroupId {
+        self.groups.push(Vec::new());
+        self.groups.len() - 1
+    }
+
+    /// Add a process to a group. A process can belong to several groups and
+    /// leaves them when it completes or is cancelled.
+    pub fn add_to_group(&mut self, pid: ProcessId, gid: GroupId) {
+        let group = &mut self.groups[gid];
+        if !group.contains(&pid) {
+            group.push(pid);
+        }
+    }
+
+    /// Returns the processes in the group.
+    pub fn group_members(&self, gid: GroupId) -> &[ProcessId] {
+        self.groups[gid].as_slice()
+    }
+
+    /// Cancel all the processes in the group, see `cancel_process`, and
+    /// leave it empty.
+    pub fn cancel_group(&mut self, gid: GroupId) {
+        let members = std::mem::take(&mut self.groups[gid]);
+        for pid in members {
+            self.cancel_process(pid);
+        }
+    }
+
+    /// Interrupt all the live processes in the group, as `Effect::Interrupt`
+    /// does, resuming them at the current time.
+    pub fn broadcast_interrupt(&mut self, gid: GroupId) {
+        for i in 0..self.groups[gid].len() {
+            let pid = self.groups[gid][i];
+            if let Some(&Some(_)) = self.processes.get(&pid) {
+                self.context_of(pid).interrupt(pid);
+                self.wake_interrupted(pid);
+            }
+        }
+    }
+
+    /// Create a new finite resource, of which n instancies are available,
+    /// whose waiting processes are served according to `discipline`.
+    ///
+    /// For more information about a resource, see the crate level documentation
+    ///
+    /// Returns the identifier of the resource
+    pub fn create_resource(&mut self, n: usize, discipline: QueueDiscipline) -> ResourceId {
+        let id = ResourceId(self.resources.len());
+        self.resources.push(Resource {
+            allocated: n,
+            available: n,
+            shared_holders: 0,
+            has_exclusive: false,
+            excess: 0,
+            holders: Vec::new(),
+            preempted: Vec::new(),
+            acquired: HashMap::default(),
+            queue: ResourceQueue::new(discipline),
+            wait_stats: WaitStats::default(),
+        });
+        id
+    }
+
+    /// Change the number of units of the resource.
+    ///
+    /// When the capacity grows, the processes in the queue that can use the
+    /// new units are resumed. When it shrinks, the units in use are not taken
+    /// back from their holders: the reduction takes effect as they release
+    /// them.
+    pub fn set_resource_capacity(&mut self, id: ResourceId, capacity: usize) {
+        self.resources[id.0].set_capacity(capacity);
+        self.wake_ready(id);
+    }
+
+    /// Returns the number of resources created.
+    pub fn resource_count(&self) -> usize {
+        self.resources.len()
+    }
+
+    /// Iterate over the ids of the resources created, in creation order.
+    pub fn resource_ids(&self) -> impl Iterator<Item = ResourceId> {
+        (0..self.resources.len()).map(ResourceId)
+    }
+
+    /// Returns the current occupancy of the given resource.
+    pub fn resource_state(&self, id: ResourceId) -> ResourceState {
+        let res = &self.resources[id.0];
+        ResourceState {
+            allocated: res.allocated,
+            available: res.available,
+            in_use: res.allocated - res.available + res.excess,
+            queue_len: res.queue.len(),
+        }
+    }
+
+    /// Returns the queue discipline of the given resource.
+    pub fn resource_discipline(&self, id: ResourceId) -> QueueDiscipline {
+        self.resources[id.0].queue.discipline
+    }
+
+    /// Change the queue discipline of the given resource, e.g. to switch to
+    /// `Priority` during an overload. The processes already waiting are
+    /// sorted as if they had been enqueued with the new discipline, by the
+    /// time they started waiting.
+    pub fn set_queue_discipline(&mut self, id: ResourceId, discipline: QueueDiscipline) {
+        self.resources[id.0].queue.set_discipline(discipline);
+        self.wake_ready(id);
+    }
+
+    /// Returns the processes waiting for the given resource, in the order
+    /// they will be served.
+    pub fn resource_queue_snapshot(&self, id: ResourceId) -> Vec<ProcessId> {
+        self.resources[id.0].queue.iter().map(|w| w.process).collect()
+    }
+
+    /// Returns the processes holding at least one unit of the given resource,
+    /// sorted by id. Together with `resource_queue_snapshot`, it shows who
+    /// has and who wants the resource.
+    ///
+    /// The processes holding it in shared mode are included only with
+    /// `enable_hold_tracking`.
+    pub fn resource_holders(&self, id: ResourceId) -> Vec<ProcessId> {
+        let mut holders: Vec<ProcessId> = match self.holdings.as_ref() {
+            Some(holdings) => holdings
+                .iter()
+                .filter(|(_, held)| held.get(&id).map_or(false, |&n| n > 0))
+                .map(|(&pid, _)| pid)
+                .collect(),
+            None => self.resources[id.0].holders.iter().map(|&(pid, _)| pid).collect(),
+        };
+        holders.sort();
+        holders.dedup();
+        holders
+    }
+
+    /// Change the order in which the processes waiting for the given resource
+    /// will be served, e.g. to implement aging on top of the queue
+    /// discipline. New waiters are still queued according to the discipline.
+    ///
+    /// Panics if `order` is not a permutation of the processes in the queue,
+    /// as returned by `resource_queue_snapshot`.
+    pub fn reorder_resource_queue(&mut self, id: ResourceId, order: &[ProcessId]) {
+        if !self.resources[id.0].queue.reorder(order) {
+            panic!("ERROR: the new order is not a permutation of the queue of resource {}", id);
+        }
+        self.wake_ready(id);
+    }
+
+    /// Invoke `f`, with the current time and the `Context`, once all the
+    /// events at a time have been processed: before the clock advances, when
+    /// there are no more events or when a run stops with no other event due
+    /// at that time. It sees a consistent state rather than the one between
+    /// events at the same time.
+    ///
+    /// Events scheduled by the owner of the simulation at a time already
+    /// reported make it invoke `f` again for that time.
+    pub fn set_timestamp_monitor(&mut self, f: Box<dyn FnMut(Time, &Context<T, Time, R>)>) {
+        self.timestamp_monitor = Some(f);
+    }
+
+    /// Invoke the timestamp monitor if events were processed at the current
+    /// time since it was last invoked.
+    fn fire_timestamp_monitor(&mut self) {
+        if self.timestamp_pending {
+            self.timestamp_pending = false;
+            if let Some(f) = self.timestamp_monitor.as_mut() {
+                f(self.context.time(), &self.context);
+            }
+        }
+    }
+
+    /// Invoke `f`, with the current time, whenever the resource becomes
+    /// completely free, i.e. no unit is held, after a step.
+    pub fn on_resource_idle(&mut self, id: ResourceId, f: Box<dyn Fn(Time)>) {
+        let active = self.resources[id.0].is_idle();
+        self.resource_hooks.push(ResourceHook { resource: id, idle: true, f, active });
+    }
+
+    /// Invoke `f`, with the current time, whenever no more units of the
+    /// resource are available after a step.
+    pub fn on_resource_saturated(&mut self, id: ResourceId, f: Box<dyn Fn(Time)>) {
+        let active = self.resources[id.0].available == 0;
+        self.resource_hooks.push(ResourceHook { resource: id, idle: false, f, active });
+    }
+
+    /// Invoke the resource callbacks whose condition has become true.
+    fn fire_resource_hooks(&mut self) {
+        let now = self.context.time();
+        for hook in self.resource_hooks.iter_mut() {
+            let res = &self.resources[hook.resource.0];
+            let active = if hook.idle { res.is_idle() } else { res.available == 0 };
+            if active && !hook.active {
+                (hook.f)(now);
+            }
+            hook.active = active;
+        }
+    }
+
+    /// Returns the statistics about the time processes waited to obtain the
+    /// given resource.
+    pub fn resource_wait_stats(&self, id: ResourceId) -> WaitStats {
+        self.resources[id.0].wait_stats
+    }
+
+    /// Returns the statistics about the time processes waited to obtain any
+    /// of the resources of the simulation.
+    pub fn wait_stats(&self) -> WaitStats {
+        let mut stats = WaitStats::default();
+        for res in self.resources.iter() {
+            stats.merge(&res.wait_stats);
+        }
+        stats
+    }
+
+    /// Create a new pool with the given capacity.
+    ///
+    /// For more information about a pool, see the crate level documentation
+    ///
+    /// Returns the identifier of the pool
+    pub fn create_pool(&mut self, capacity: f64) -> PoolId {
+        let id = self.pools.len();
+        self.pools.push(Pool {
+            capacity,
+            available: capacity,
+            queue: VecDeque::new(),
+            bucket: false,
+        });
+        id
+    }
+
+    /// Create a new store with the given items.
+    ///
+    /// For more information about a store, see the crate level documentation
+    ///
+    /// Returns the identifier of the store
+    pub fn create_store(&mut self, items: Vec<T>) -> StoreId {
+        self.stores.push(Store {
+            items: items.into(),
+            queue: VecDeque::new(),
+        });
+        self.stores.len() - 1
+    }
+
+    /// Returns the items in the store, in the order they were put.
+    pub fn store_items(&self, id: StoreId) -> impl Iterator<Item = &T> {
+        self.stores[id].items.iter()
+    }
+
+    /// Schedule a process to be executed. Another way to schedule events is
+    /// yielding `Effect::Event` from a process during the simulation.
+    ///
+    /// Returns the identifier of the event, that can be used to cancel it, or
+    /// an error if the time of the event is NaN or infinite, see
+    /// `set_nan_policy`.
+    pub fn schedule_event(&mut self, event: Event<Time>) -> Result<EventId, SimulationError<Time>> {
+        if !self.accept_time(event.process, event.time)? {
+            return Ok(self.future_events.skip_id());
+        }
+        self.check_unique(&event)?;
+        let now = self.context.time();
+        if let Some(stats) = self.process_stats.as_mut() {
+            stats.entry(event.process).or_insert((ProcessStats::default(), now));
+        }
+        self.check_wakeup(&event)?;
+        Ok(self.future_events.push(event))
+    }
+
+    /// Schedule many events at once, e.g. a trace of recorded arrivals, as
+    /// `schedule_event` does for each of them.
+    ///
+    /// Returns the identifiers of the events, or the first error: the events
+    /// before the one refused remain scheduled.
+    pub fn schedule_events<I>(&mut self, events: I) -> Result<Vec<EventId>, SimulationError<Time>>
+    where
+        I: IntoIterator<Item = Event<Time>>,
+    {
+        let events = events.into_iter();
+        self.future_events.reserve(events.size_hint().0);
+        events.map(|e| self.schedule_event(e)).collect()
+    }
+
+    /// Schedule the process at each of the given times, see
+    /// `schedule_events`.
+    pub fn schedule_arrivals(&mut self, times: &[Time], process: ProcessId) -> Result<Vec<EventId>, SimulationError<Time>> {
+        self.schedule_events(times.iter().map(|&time| Event { time, process, label: None }))
+    }
+
+    /// With unique scheduling, returns an error if the process of the event
+    /// has already a pending event.
+    fn check_unique(&self, event: &Event<Time>) -> Result<(), SimulationError<Time>> {
+        if self.unique_scheduling && self.future_events.pending.contains_key(&event.process) {
+            return Err(SimulationError::DuplicateScheduling {
+                process: event.process,
+                time: event.time,
+            });
+        }
+        Ok(())
+    }
+
+    /// Track the processes blocked by `Effect::Wait` that the event wakes up,
+    /// warning, or failing with strict wake-ups, if one of them had already
+    /// been woken up.
+    fn check_wakeup(&mut self, event: &Event<Time>) -> Result<(), SimulationError<Time>> {
+        let pid = event.process;
+        if self.context_of(pid).waiting.borrow_mut().remove(&pid) {
+            self.woken.insert(pid);
+        } else if self.woken.contains(&pid) {
+            if self.strict_wakeups {
+                return Err(SimulationError::DuplicateScheduling {
+                    process: pid,
+                    time: event.time,
+                });
+            }
+            eprintln!(
+                "WARNING: process {} blocked by Wait woken up again at time {:?}",
+                pid, event.time
+            );
+        }
+        Ok(())
+    }
+
+    /// Remove a scheduled event before it is processed.
+    ///
+    /// Returns the event, or `None` if it has already been processed or
+    /// cancelled.
+    pub fn cancel_event(&mut self, id: EventId) -> Option<Event<Time>> {
+        self.future_events.remove(id)
+    }
+
+    /// Proceed in the simulation by 1 step.
+    ///
+    /// Returns the event processed, `None` if there are no events, or the
+    /// error that prevents the simulation from going on. Without events
+    /// nothing happens, see `is_idle`.
+    pub fn step(&mut self) -> Result<Option<Event<Time>>, SimulationError<Time>> {
+        if let Some(budget) = self.event_budget {
+            if self.processed_event_count() >= budget as u64 && !self.future_events.is_empty() {
+                return Err(SimulationError::EventBudgetExceeded { budget });
+            }
+        }
+        let started = if self.wall_times.is_some() {
+            Some(Instant::now())
+        } else {
+            None
+        };
+        // signals fired by the owner of the simulation since the last step
+        self.deliver_signals();
+        self.route_messages();
+        match self.next_event() {
+            Some((id, event)) => {
+                self.advance_clock(event.time)?;
+                self.timestamp_pending = true;
+                if let Some((priority, message)) = self.in_transit.remove(&id) {
+                    let ctx = self.context_of(event.process);
+                    ctx.push_message_with_priority(event.process, message, priority);
+                    // a gathering process is resumed only by the last message
+                    if let Some(&n) = self.gathers.get(&event.process) {
+                        if ctx.message_count(event.process) < n {
+                            self.log_processed(event);
+                            return Ok(Some(event));
+                        }
+                    }
+                    // and a holding one by the grant of the resource
+                    if self.pending_holds.contains_key(&event.process) {
+                        self.log_processed(event);
+                        return Ok(Some(event));
+                    }
+                }
+                if let Some(r) = self.auto_releases.remove(&id) {
+                    // released on behalf of the holder, that is not resumed
+                    self.record_release(event.process, r)?;
+                    self.resources[r.0].release(event.process);
+                    self.wake_ready(r);
+                    self.fire_resource_hooks();
+                    self.log_processed(event);
+                    return Ok(Some(event));
+                }
+                let retries = self.deferred.remove(&id).unwrap_or(0);
+                if !self.processes.contains_key(&event.process) {
+                    match self.missing_policy {
+                        MissingPolicy::Panic => {}
+                        MissingPolicy::Defer(max) if retries < max => {
+                            let id = self.future_events.push(event);
+                            self.deferred.insert(id, retries + 1);
+                            return Ok(Some(event));
+                        }
+                        _ => {
+                            eprintln!(
+                                "WARNING: dropped the event at time {:?} for the missing process {}",
+                                event.time, event.process
+                            );
+                            return Ok(Some(event));
+                        }
+                    }
+                }
+                self.message_waiters.remove(&event.process);
+                self.gathers.remove(&event.process);
+                self.woken.remove(&event.process);
+                if self.wakeups.get(&event.process) == Some(&id) {
+                    self.wakeups.remove(&event.process);
+                }
+                let granted = match self.pending_holds.get(&event.process) {
+                    Some(&(r, d, Some(grant))) if grant == id => Some((r, d)),
+                    _ => None,
+                };
+                if let Some((r, d)) = granted {
+                    // granted: release the resource and resume the process
+                    // at the end of the holding time
+                    self.pending_holds.remove(&event.process);
+                    let release = self.future_events.push(Event {
+                        time: event.time + d,
+                        process: event.process,
+                        label: Some("release"),
+                    });
+                    self.auto_releases.insert(release, r);
+                    let wakeup = self.future_events.push(Event {
+                        time: event.time + d,
+                        process: event.process,
+                        label: None,
+                    });
+                    self.wakeups.insert(event.process, wakeup);
+                    self.log_processed(event);
+                    return Ok(Some(event));
+                }
+                if let Some(select) = self.selects.remove(&event.process) {
+                    // resumed by the timeout or by something else
+                    let branch = match select.timeout_event {
+                        Some(timeout) if timeout == id => select.timeout.map(|(_, i)| i),
+                        Some(timeout) => {
+                            self.future_events.remove(timeout);
+                            None
+                        }
+                        None => None,
+                    };
+                    self.context_of(event.process).selected.borrow_mut().insert(event.process, branch);
+                    self.context_of(event.process).granted.borrow_mut().remove(&event.process);
+                    self.disarm_select(event.process, &select, None);
+                }
+                self.share_resource_states();
+                let resumed = if self.process_stats.is_some() {
+                    Some(Instant::now())
+                } else {
+                    None
+                };
+                let process = self.processes.get_mut(&event.process).expect("No such process").as_mut().expect("ERROR. Tried to resume a completed process.");
+                let state = match panic::catch_unwind(AssertUnwindSafe(|| Pin::new(process).resume())) {
+                    Ok(state) => state,
+                    Err(payload) => {
+                        self.processes.get_mut(&event.process).expect("Invalid PID").take();
+                        self.leave_groups(event.process);
+                        let message = match payload.downcast::<String>() {
+                            Ok(m) => *m,
+                            Err(payload) => payload.downcast_ref::<&str>().map_or("", |m| *m).to_string(),
+                        };
+                        return Err(SimulationError::ProcessPanicked {
+                            process: event.process,
+                            message,
+                        });
+                    }
+                };
+                if let (Some(resumed), Some(stats)) = (resumed, self.process_stats.as_mut()) {
+                    let now = self.context.time();
+                    let entry = stats.entry(event.process).or_insert((ProcessStats::default(), now));
+                    entry.0.resume_count += 1;
+                    entry.0.total_wait_time += (now - entry.1).to_f64();
+                    entry.0.total_active_time += resumed.elapsed().as_secs_f64();
+                    // the effect yielded is scheduled now
+                    entry.1 = now;
+                }
+                // the process may have fired signals before yielding
+                self.deliver_signals();
+                self.route_messages();
+                if let (GeneratorState::Yielded(y), Some(hook)) = (&state, self.effect_hook.as_mut()) {
+                    hook(event.process, y)?;
+                }
+                match state {
+                    GeneratorState::Yielded(y) => match y {
+                        Effect::TimeOut(t) => {
+                            if self.accept_delay(event.process, t)? {
+                                let wakeup = self.future_events.push(Event {
+                                    time: self.context.time() + t,
+                                    process: event.process,
+                                    label: None,
+                                });
+                                self.wakeups.insert(event.process, wakeup);
+                            }
+                        }
+                        Effect::SleepUntil(t) => {
+                            let now = self.context.time();
+                            if self.accept_time(event.process, t)? {
+                                if cfg!(debug_assertions) && t < now {
+                                    eprintln!(
+                                        "WARNING: process {} yielded SleepUntil({:?}) at time {:?}, resumed immediately",
+                                        event.process, t, now
+                                    );
+                                }
+                                let wakeup = self.future_events.push(Event {
+                                    time: if t > now { t } else { now },
+                                    process: event.process,
+                                    label: None,
+                                });
+                                self.wakeups.insert(event.process, wakeup);
+                            }
+                        }
+                        Effect::TimeOutThrottled(t, spacing) => {
+                            let now = self.context.time();
+                            if self.accept_delay(event.process, t)? && self.accept_delay(event.process, spacing)? {
+                                let mut time = now + t;
+                                if let Some(&last) = self.last_fires.get(&event.process) {
+                                    if last + spacing > time {
+                                        time = last + spacing;
+                                    }
+                                }
+                                self.last_fires.insert(event.process, time);
+                                let wakeup = self.future_events.push(Event {
+                                    time,
+                                    process: event.process,
+                                    label: None,
+                                });
+                                self.wakeups.insert(event.process, wakeup);
+                            }
+                        }
+                        Effect::Event(e) => self.schedule_yielded(e, false)?,
+                        Effect::AbsoluteEvent(e) => self.schedule_yielded(e, true)?,
+                        Effect::Request(r) | Effect::RequestShared(r) | Effect::PriorityRequest(r, _) | Effect::Hold(r, _) => {
+                            let (shared, priority) = match y {
+                                Effect::RequestShared(_) => (true, 0),
+                                Effect::PriorityRequest(_, priority) => (false, priority),
+                                _ => (false, 0),
+                            };
+                            if let Effect::Hold(_, d) = y {
+                                if self.accept_delay(event.process, d)? {
+                                    self.pending_holds.insert(event.process, (r, d, None));
+                                }
+                            }
+                            let res = &mut self.resources[r.0];
+                            if res.queue.is_empty() && res.try_acquire(event.process, shared, priority) {
+                                res.wait_stats.add(0.0);
+                                self.record_hold(event.process, r);
+                                // the process can use the resource immediately
+                                let grant = self.future_events.push(Event {
+                                    time: self.context.time(),
+                                    process: event.process,
+                                    label: None,
+                                });
+                                if let Some(hold) = self.pending_holds.get_mut(&event.process) {
+                                    hold.2 = Some(grant);
+                                }
+                            } else {
+                                // enqueue the process
+                                res.queue.push(Waiter {
+                                    process: event.process,
+                                    shared,
+                                    priority,
+                                    since: self.context.time(),
+                                    all: false,
+                                });
+                            }
+                        }
+                        Effect::PreemptRequest(r, priority) => {
+                            let now = self.context.time();
+                            let res = &mut self.resources[r.0];
+                            let victim = if res.can_acquire_exclusive() {
+                                None
+                            } else {
+                                res.holders
+                                    .iter()
+                                    .filter(|&&(_, p)| p > priority)
+                                    .max_by_key(|&&(_, p)| p)
+                                    .map(|&(pid, _)| pid)
+                            };
+                            if let Some(victim) = victim {
+                                res.release(victim);
+                                // the release of the victim is still expected
+                                res.preempted.push(victim);
+                                let since = res.acquired.remove(&victim).unwrap_or(now);
+                                self.context_of(victim).preemptions.borrow_mut().insert(victim, (since, now));
+                                self.context_of(victim).interrupt(victim);
+                                self.wake_interrupted(victim);
+                            }
+                            let res = &mut self.resources[r.0];
+                            if (victim.is_some() || res.queue.is_empty())
+                                && res.try_acquire(event.process, false, priority)
+                            {
+                                res.wait_stats.add(0.0);
+                                self.record_hold(event.process, r);
+                                self.future_events.push(Event {
+                                    time: now,
+                                    process: event.process,
+                                    label: None,
+                                });
+                            } else {
+                                res.queue.push(Waiter {
+                                    process: event.process,
+                                    shared: false,
+                                    priority,
+                                    since: now,
+                                    all: false,
+                                });
+                            }
+                        }
+                        Effect::Release(r) | Effect::ReleaseShared(r) => {
+                            let shared = match y {
+                                Effect::ReleaseShared(_) => true,
+                                _ => false,
+                            };
+                            self.release_yielded(event.process, r, shared)?;
+                            // after releasing the resource the process
+                            // can be resumed
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::ReleaseAfter(r, delay) => {
+                            let now = self.context.time();
+                            if self.accept_delay(event.process, delay)? {
+                                let release = self.future_events.push(Event {
+                                    time: now + delay,
+                                    process: event.process,
+                                    label: Some("release"),
+                                });
+                                self.auto_releases.insert(release, r);
+                            }
+                            self.future_events.push(Event {
+                                time: now,
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::SetCapacity(r, capacity) => {
+                            self.set_resource_capacity(r, capacity);
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::Reserve(p, amount) => {
+                            let pool = &mut self.pools[p];
+                            assert!(amount <= pool.capacity, "ERROR: reservation exceeds pool capacity");
+                            if pool.queue.is_empty() && pool.try_reserve(amount) {
+                                self.future_events.push(Event {
+                                    time: self.context.time(),
+                                    process: event.process,
+                                    label: None,
+                                });
+                            } else {
+                                pool.queue.push_back((event.process, amount));
+                            }
+                        }
+                        Effect::Unreserve(p, amount) => {
+                            self.unreserve(p, amount);
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::Get(s, filter) => {
+                            let store = &mut self.stores[s];
+                            match filter.take_from(&mut store.items) {
+                                Some(item) => {
+                                    self.context_of(event.process).items.borrow_mut().insert(event.process, item);
+                                    self.future_events.push(Event {
+                                        time: self.context.time(),
+                                        process: event.process,
+                                        label: None,
+                                    });
+                                }
+                                None => store.queue.push_back((event.process, filter)),
+                            }
+                        }
+                        Effect::Put(s, item) => {
+                            self.put_item(s, item);
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::Interrupt(pid) => {
+                            self.context_of(pid).interrupt(pid);
+                            self.wake_interrupted(pid);
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::InterruptGroup(gid) => {
+                            for i in 0..self.groups[gid].len() {
+                                let pid = self.groups[gid][i];
+                                let live = match self.processes.get(&pid) {
+                                    Some(&Some(_)) => true,
+                                    _ => false,
+                                };
+                                if live && pid != event.process {
+                                    self.context_of(pid).interrupt(pid);
+                                    self.wake_interrupted(pid);
+                                }
+                            }
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::InterruptWith(pid, payload) => {
+                            self.context_of(pid).interrupt_with(pid, payload);
+                            self.wake_interrupted(pid);
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::SendMessage(pid, message, delay) => {
+                            self.send_yielded(pid, message, delay, 0)?;
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::SendMessagePriority(pid, message, delay, priority) => {
+                            self.send_yielded(pid, message, delay, priority)?;
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::SendMessageNow(pid, message) => {
+                            // the sender is scheduled first, and is not
+                            // skipped by the round robin in favour of the
+                            // recipient
+                            self.round.remove(&event.process);
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                            self.send_yielded(pid, message, Time::default(), 0)?;
+                        }
+                        Effect::Collect(effects) => {
+                            for e in effects {
+                                self.apply_collected(event.process, e)?;
+                            }
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::Select(effects) => self.arm_select(event.process, effects)?,
+                        Effect::RequestAll(resources) => {
+                            let now = self.context.time();
+                            let free = resources.iter().all(|&r| {
+                                let res = &self.resources[r.0];
+                                res.queue.is_empty() && res.can_acquire_exclusive()
+                            });
+                            for r in resources {
+                                let res = &mut self.resources[r.0];
+                                if free {
+                                    res.try_acquire(event.process, false, 0);
+                                    res.wait_stats.add(0.0);
+                                    self.record_hold(event.process, r);
+                                } else {
+                                    res.queue.push(Waiter {
+                                        process: event.process,
+                                        shared: false,
+                                        priority: 0,
+                                        since: now,
+                                        all: true,
+                                    });
+                                }
+                            }
+                            if free {
+                                self.future_events.push(Event {
+                                    time: now,
+                                    process: event.process,
+                                    label: None,
+                                });
+                            }
+                        }
+                        Effect::RequestAny(resources) => {
+                            let effects = resources.into_iter().map(Effect::Request).collect();
+                            self.arm_select(event.process, effects)?;
+                        }
+                        Effect::RequestWithPatience(r, patience) | Effect::RequestWithTimeout(r, patience) => {
+                            let effects = vec![Effect::Request(r), Effect::TimeOut(patience)];
+                            self.arm_select(event.process, effects)?;
+                        }
+                        Effect::Wait | Effect::Suspend => {
+                            if !self.future_events.pending.contains_key(&event.process) {
+                                self.context_of(event.process).waiting.borrow_mut().insert(event.process);
+                            }
+                        }
+                        Effect::Now(action) => {
+                            (action.0)(self.context_of(event.process));
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::Delay => {
+                            self.future_events.push(Event {
+                                time: self.context.time(),
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::ChannelSend(message) => {
+                            let now = self.context.time();
+                            let e = Event { time: now, process: message.to, label: None };
+                            self.check_unique(&e)?;
+                            self.check_wakeup(&e)?;
+                            message.deliver();
+                            self.future_events.push(e);
+                            self.future_events.push(Event {
+                                time: now,
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::YieldTo(target) => {
+                            let now = self.context.time();
+                            let e = Event { time: now, process: target, label: None };
+                            self.check_unique(&e)?;
+                            self.check_wakeup(&e)?;
+                            self.future_events.push(e);
+                            self.future_events.push(Event {
+                                time: now,
+                                process: event.process,
+                                label: None,
+                            });
+                        }
+                        Effect::WaitForSignal(signal) => {
+                            self.signal_waiters.entry(signal).or_insert_with(Vec::new).push(event.process);
+                        }
+                        Effect::Join(target) => match self.processes.get(&target) {
+                            Some(Some(_)) => self.joiners.entry(target).or_insert_with(Vec::new).push(event.process),
+                            ended => {
+                                let outcome = if ended.is_some() {
+                                    JoinOutcome::Completed
+                                } else {
+                                    JoinOutcome::Cancelled
+                                };
+                                self.context_of(event.process).joined.borrow_mut().insert(event.process, outcome);
+                                self.future_events.push(Event {
+                                    time: self.context.time(),
+                                    process: event.process,
+                                    label: None,
+                                });
+                            }
+                        },
+                        Effect::WaitForMessage => {
+                            // otherwise the process is resumed by `SendMessage`
+                            if self.context_of(event.process).message_count(event.process) > 0 {
+                                self.future_events.push(Event {
+                                    time: self.context.time(),
+                                    process: event.process,
+                                    label: None,
+                                });
+                            } else {
+                                self.message_waiters.insert(event.process);
+                            }
+                        }
+                        Effect::Gather(n) => {
+                            // otherwise the process is resumed by the `SendMessage`
+                            // that brings the n-th message
+                            if self.context_of(event.process).message_count(event.process) >= n {
+                                self.future_events.push(Event {
+                                    time: self.context.time(),
+                                    process: event.process,
+                                    label: None,
+                                });
+                            } else {
+                                self.gathers.insert(event.process, n);
+                            }
+                        }
+                    },
+                    GeneratorState::Complete(result) => {
+                        // FIXME: removing the process from the vector would invalidate
+                        // all existing `ProcessId`s, but keeping it would be a
+                        // waste of space since it is completed.
+                        // May be worth to use another data structure.
+                        // At least let's remove the generator itself.
+                        self.processes.get_mut(&event.process).expect("Invalid PID").take();
+                        self.results.insert(event.process, result);
+                        self.completion_times.insert(event.process, event.time);
+                        self.leave_groups(event.process);
+                        self.wake_joiners(event.process, JoinOutcome::Completed);
+                    }
+                }
+                self.log_processed(event);
+                self.create_spawned();
+                // the waiting processes scheduled by the one just resumed
+                let pending = &self.future_events.pending;
+                for c in self.contexts() {
+                    c.waiting.borrow_mut().retain(|p| !pending.contains_key(p));
+                }
+                self.fire_resource_hooks();
+                if let (Some(started), Some(times)) = (started, self.wall_times.as_mut()) {
+                    times.push(started.elapsed());
+                }
+                Ok(Some(event))
+            }
+            None => {
+                self.fire_timestamp_monitor();
+                self.check_replay_complete()?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Add the processed event to the log, according to the log policy, and
+    /// to the one of its process.
+    fn log_processed(&mut self, event: Event<Time>) {
+        if let Some(log) = self.per_process_log.as_mut() {
+            log.entry(event.process).or_insert_with(Vec::new).push(event.time);
+        }
+        let index = self.dropped_events + self.processed_events.len();
+        match self.log_policy {
+            LogPolicy::All => self.processed_events.push(event),
+            LogPolicy::None => self.dropped_events += 1,
+            LogPolicy::LastN(n) => {
+                self.processed_events.push(event);
+                // the events beyond the capacity are hidden by
+                // `processed_events` and removed in batches
+                if self.processed_events.len() >= 2 * n.max(1) {
+                    self.trim_log(n);
+                }
+            }
+            LogPolicy::Sampled(k) if index % k == 0 => self.processed_events.push(event),
+            LogPolicy::Sampled(_) => self.dropped_events += 1,
+        }
+    }
+
+    /// Returns an error if a replay is in progress and the recording has
+    /// effects not yielded yet, once no events are left to yield them.
+    fn check_replay_complete(&self) -> Result<(), SimulationError<Time>> {
+        if let Some((ref index, ref processes)) = self.replay_progress {
+            if let Some(&process) = processes.get(index.get()) {
+                return Err(SimulationError::ReplayDivergence { process, index: index.get() });
+            }
+        }
+        Ok(())
+    }
+
+    /// Proceed in the simulation by up to `n` steps, stopping early if there
+    /// are no more events. Returns the number of steps taken.
+    ///
+    /// Stops at the first error returned by `step`.
+    pub fn step_n(&mut self, n: usize) -> Result<usize, SimulationError<Time>> {
+        for i in 0..n {
+            if self.step()?.is_none() {
+                return Ok(i);
+            }
+        }
+        Ok(n)
+    }
+
+    /// Process all the events scheduled for the current time, including the
+    /// ones scheduled by them with no delay, without advancing the clock
+    /// (the delta cycle of hardware simulators).
+    ///
+    /// Stops at the first error returned by `step`.
+    pub fn settle(&mut self) -> Result<(), SimulationError<Time>> {
+        // signals fired by the owner may wake processes now
+        self.deliver_signals();
+        while self.future_events.peek().map(|e| e.time == self.context.time()).unwrap_or(false) {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Process all the events scheduled up to `target`, included, then move
+    /// the clock to `target` even if no event happens then, e.g. to skip an
+    /// idle period. Events scheduled after `target` are not processed.
+    ///
+    /// Stops at the first error returned by `step` and returns an error if
+    /// `target` is in the past or NaN.
+    pub fn advance_to(&mut self, target: Time) -> Result<(), SimulationError<Time>> {
+        self.deliver_signals();
+        while self.future_events.peek().map(|e| e.time <= target).unwrap_or(false) {
+            self.step()?;
+        }
+        self.advance_clock(target)
+    }
+
+    /// Move the clock forward to `t`, first discarding the statistics if the
+    /// warm-up period ends and sampling the monitors due.
+    fn advance_clock(&mut self, t: Time) -> Result<(), SimulationError<Time>> {
+        if t > self.context.time() {
+            self.fire_timestamp_monitor();
+        }
+        if let Some(warmup) = self.warmup {
+            if self.context.time() < warmup && t >= warmup {
+                self.clear_statistics();
+            }
+        }
+        self.sample_monitors(t);
+        self.context.advance_time(t)?;
+        for (_, c) in self.subcontexts.iter() {
+            c.time.set(t);
+        }
+        #[cfg(feature = "sync")]
+        for c in self.sync_contexts.iter() {
+            *c.time.lock().unwrap() = t;
+        }
+        Ok(())
+    }
+
+    /// Set what to do with the events for processes that do not exist. The
+    /// default is `MissingPolicy::Panic`.
+    pub fn on_missing_process(&mut self, policy: MissingPolicy) {
+        self.missing_policy = policy;
+    }
+
+    /// Set what to do with the events scheduled at a NaN or infinite time,
+    /// by `schedule_event` or by the effects yielded. The default is
+    /// `NanPolicy::Reject`.
+    pub fn set_nan_policy(&mut self, policy: NanPolicy) {
+        self.nan_policy = policy;
+    }
+
+    /// Check the time of an event scheduled for the process, according to
+    /// the `NanPolicy`: returns `Ok(false)` if the event must be dropped.
+    fn accept_time(&self, pid: ProcessId, t: Time) -> Result<bool, SimulationError<Time>> {
+        match check_time(pid, t, self.context.time()) {
+            Ok(()) => Ok(true),
+            Err(e) => match self.nan_policy {
+                NanPolicy::Reject => Err(e),
+                NanPolicy::Panic => panic!("ERROR: {}", e),
+                NanPolicy::Skip => {
+                    eprintln!("WARNING: {}, the event is dropped", e);
+                    Ok(false)
+                }
+            },
+        }
+    }
+
+    /// Check the delay of a `TimeOut` yielded by the process as
+    /// `accept_time` does, and panic if it is negative.
+    fn accept_delay(&self, pid: ProcessId, t: Time) -> Result<bool, SimulationError<Time>> {
+        let accepted = self.accept_time(pid, t)?;
+        if accepted && t < Time::default() {
+            panic!("ERROR: process {} yielded a negative TimeOut ({:?})", pid, t);
+        }
+        Ok(accepted)
+    }
+
+    /// Round the time of every event scheduled from now on to the nearest
+    /// multiple of `resolution`, so that floating point errors accumulated
+    /// by repeated delays do not keep apart events meant to be simultaneous.
+    ///
+    /// Disabled by default, as some models need the full precision.
+    /// Panics if the resolution is not positive.
+    pub fn set_time_resolution(&mut self, resolution: Time) {
+        assert!(resolution > Time::default(), "ERROR: the time resolution must be positive");
+        self.future_events.resolution = Some(resolution);
+    }
+
+    /// Set the order in which the events scheduled for the same time are
+    /// processed. The default is `SchedulingPolicy::Fifo`.
+    pub fn set_scheduling_policy(&mut self, policy: SchedulingPolicy) {
+        self.policy = policy;
+        self.round.clear();
+    }
+
+    /// Take the next event to process according to the scheduling policy.
+    fn next_event(&mut self) -> Option<(EventId, Event<Time>)> {
+        if self.policy == SchedulingPolicy::Fifo {
+            return self.future_events.pop();
+        }
+        let (time, process) = match self.future_events.peek() {
+            Some(e) => (e.time, e.process),
+            None => return None,
+        };
+        if time != self.context.time() {
+            self.round.clear();
+        }
+        let next = if self.round.contains(&process) {
+            match self.future_events.first_at(time, &self.round) {
+                Some(id) => self.future_events.remove(id).map(|e| (id, e)),
+                None => {
+                    // every process with an event now has run: new round
+                    self.round.clear();
+                    self.future_events.pop()
+                }
+            }
+        } else {
+            self.future_events.pop()
+        };
+        if let Some((_, ref e)) = next {
+            self.round.insert(e.process);
+        }
+        next
+    }
+
+    /// Resume all the processes waiting for the signals fired through the
+    /// `Context`.
+    fn deliver_signals(&mut self) {
+        let fired: Vec<_> = self.contexts().flat_map(|c| c.fired.borrow_mut().drain(..).collect::<Vec<_>>()).collect();
+        for signal in fired {
+            for p in self.signal_waiters.remove(&signal).unwrap_or_default() {
+                self.future_events.push(Event {
+                    time: self.context.time(),
+                    process: p,
+                    label: None,
+                });
+            }
+        }
+    }
+
+    /// Discard the statistics collected so far, at the end of the warm-up.
+    fn clear_statistics(&mut self) {
+        for res in self.resources.iter_mut() {
+            res.wait_stats = WaitStats::default();
+        }
+        self.context.series.borrow_mut().clear();
+        for m in self.monitors.iter_mut() {
+            m.data.clear();
+        }
+    }
+
+    /// Schedule an event yielded with `Effect::Event`, whose time is a delay,
+    /// or with `Effect::AbsoluteEvent`.
+    fn schedule_yielded(&mut self, mut e: Event<Time>, absolute: bool) -> Result<(), SimulationError<Time>> {
+        let now = self.context.time();
+        if self.accept_time(e.process, e.time)? {
+            if !absolute {
+                e.time = e.time + now;
+            } else if e.time < now {
+                return Err(TimeError::TimeTravelAttempted { from: now, to: e.time }.into());
+            }
+            self.check_unique(&e)?;
+            self.check_wakeup(&e)?;
+            self.future_events.push(e);
+        }
+        Ok(())
+    }
+
+    /// Give back a unit of the resource held by the process, scheduling the
+    /// waiting processes that can now use it.
+    fn release_yielded(&mut self, pid: ProcessId, r: ResourceId, shared: bool) -> Result<(), SimulationError<Time>> {
+        self.record_release(pid, r)?;
+        let res = &mut self.resources[r.0];
+        if shared {
+            assert!(res.shared_holders > 0);
+            res.shared_holders -= 1;
+        } else {
+            res.release(pid);
+        }
+        // some processes in queue: schedule the ones
+        // that can now use the resource.
+        self.wake_ready(r);
+        Ok(())
+    }
+
+    /// Give back an amount of the pool, scheduling the waiting processes
+    /// whose reservation can now be satisfied.
+    fn unreserve(&mut self, p: PoolId, amount: f64) {
+        let pool = &mut self.pools[p];
+        assert!(pool.bucket || pool.available + amount <= pool.capacity * (1.0 + 4.0 * std::f64::EPSILON));
+        pool.available = (pool.available + amount).min(pool.capacity);
+        for p in pool.dequeue_ready() {
+            self.future_events.push(Event {
+                time: self.context.time(),
+                process: p,
+                label: None,
+            });
+        }
+    }
+
+    /// Add the item to the store, scheduling the waiting processes that can
+    /// now take one.
+    fn put_item(&mut self, s: StoreId, item: T) {
+        self.stores[s].items.push_back(item);
+        for (p, item) in self.stores[s].dequeue_ready() {
+            self.context_of(p).items.borrow_mut().insert(p, item);
+            if let Some(select) = self.selects.remove(&p) {
+                // the `Get` won: cancel the other alternatives
+                let branch = select.gets.iter().find(|&&(store, _)| store == s).map(|&(_, i)| i);
+                if let Some(id) = select.timeout_event {
+                    self.future_events.remove(id);
+                }
+                self.context_of(p).selected.borrow_mut().insert(p, branch);
+                self.disarm_select(p, &select, None);
+            }
+            self.future_events.push(Event {
+                time: self.context.time(),
+                process: p,
+                label: None,
+            });
+        }
+    }
+
+    /// Schedule the delivery of a message sent with `Effect::SendMessage` or
+    /// `Effect::SendMessagePriority`.
+    fn send_yielded(&mut self, pid: ProcessId, message: T, delay: Time, priority: u32) -> Result<(), SimulationError<Time>> {
+        if self.accept_time(pid, delay)? {
+            // delivered to the inbox when the event fires
+            let delivery = self.future_events.push(Event {
+                time: self.context.time() + delay,
+                process: pid,
+                label: None,
+            });
+            self.in_transit.insert(delivery, (priority, message));
+        }
+        Ok(())
+    }
+
+    /// Apply one of the effects of an `Effect::Collect` yielded by the
+    /// process, without resuming it.
+    fn apply_collected(&mut self, pid: ProcessId, effect: Effect<T, Time, R>) -> Result<(), SimulationError<Time>> {
+        match effect {
+            Effect::Event(e) => self.schedule_yielded(e, false)?,
+            Effect::AbsoluteEvent(e) => self.schedule_yielded(e, true)?,
+            Effect::Release(r) => self.release_yielded(pid, r, false)?,
+            Effect::ReleaseShared(r) => self.release_yielded(pid, r, true)?,
+            Effect::Unreserve(p, amount) => self.unreserve(p, amount),
+            Effect::Put(s, item) => self.put_item(s, item),
+            Effect::SendMessage(to, message, delay) => self.send_yielded(to, message, delay, 0)?,
+            Effect::SendMessagePriority(to, message, delay, priority) => {
+                self.send_yielded(to, message, delay, priority)?
+            }
+            Effect::Interrupt(to) => {
+                self.context_of(to).interrupt(to);
+                self.wake_interrupted(to);
+            }
+            Effect::InterruptWith(to, payload) => {
+                self.context_of(to).interrupt_with(to, payload);
+                self.wake_interrupted(to);
+            }
+            Effect::Now(action) => (action.0)(self.context_of(pid)),
+            _ => panic!("ERROR: process {} yielded an unsupported effect in Collect", pid),
+        }
+        Ok(())
+    }
+
+    /// Resume an interrupted process now, instead of at the end of its
+    /// `TimeOut` or `SleepUntil`, if any.
+    fn wake_interrupted(&mut self, pid: ProcessId) {
+        if let Some(wakeup) = self.wakeups.remove(&pid) {
+            self.future_events.remove(wakeup);
+        }
+        // an interrupted `Hold` is resumed as a `Request`
+        self.pending_holds.remove(&pid);
+        // a process waiting for a resource or pool gives up, so that it is
+        // not granted it later on
+        self.leave_queues(pid);
+        self.future_events.push(Event {
+            time: self.context.time(),
+            process: pid,
+            label: None,
+        });
+    }
+
+    /// Record the samples of the monitors due up to `time`, included.
+    fn sample_monitors(&mut self, time: Time) {
+        if self.monitors.is_empty() {
+            return;
+        }
+        let now = self.context.time();
+        let mut monitors = std::mem::replace(&mut self.monitors, Vec::new());
+        for m in monitors.iter_mut() {
+            while m.next_sample <= time {
+                // let the metric see the time of the sample
+                self.context.time.set(m.next_sample);
+                let value = (m.f)(self);
+                m.data.push((m.next_sample, value));
+                m.next_sample = m.next_sample + m.interval;
+            }
+        }
+        self.context.time.set(now);
+        self.monitors = monitors;
+    }
+
+    /// Grant the resource to the processes in its queue that can use it now
+    /// and schedule them.
+    fn wake_ready(&mut self, r: ResourceId) {
+        let now = self.context.time();
+        for p in self.resources[r.0].dequeue_ready(now) {
+            self.record_hold(p, r);
+            if let Some(select) = self.selects.remove(&p) {
+                // the request won: cancel the other alternatives
+                let branch = select.requests.iter().find(|&&(res, _)| res == r).map(|&(_, i)| i);
+                if let Some(id) = select.timeout_event {
+                    self.future_events.remove(id);
+                }
+                self.context_of(p).selected.borrow_mut().insert(p, branch);
+                self.context_of(p).granted.borrow_mut().insert(p, r);
+                self.disarm_select(p, &select, Some(r));
+            }
+            let grant = self.future_events.push(Event {
+                time: now,
+                process: p,
+                label: None,
+            });
+            if let Some(hold) = self.pending_holds.get_mut(&p) {
+                hold.2 = Some(grant);
+            }
+        }
+        let front = self.resources[r.0].queue.front().filter(|w| w.all).map(|w| w.process);
+        if let Some(p) = front {
+            self.try_grant_all(p);
+        }
+    }
+
+    /// Count a unit of the resource as held by the process, if the holdings
+    /// are tracked.
+    fn record_hold(&mut self, pid: ProcessId, r: ResourceId) {
+        let now = self.context.time();
+        let res = &mut self.resources[r.0];
+        res.acquired.insert(pid, now);
+        if let Some(i) = res.preempted.iter().position(|&p| p == pid) {
+            // an evicted process that obtains the resource again releases
+            // the new unit, not the one it lost, still counted as held
+            res.preempted.remove(i);
+            return;
+        }
+        if let Some(holdings) = self.holdings.as_mut() {
+            let held = holdings.entry(pid).or_insert_with(HashMap::default);
+            if held.values().all(|&n| n == 0) {
+                self.busy.entry(pid).or_insert((0.0, now)).1 = now;
+            }
+            *held.entry(r).or_insert(0) += 1;
+        }
+    }
+
+    /// Count a unit of the resource as given back by the process, if the
+    /// holdings are tracked. Returns an error if the process does not hold it.
+    fn record_release(&mut self, pid: ProcessId, r: ResourceId) -> Result<(), SimulationError<Time>> {
+        if let Some(holdings) = self.holdings.as_mut() {
+            match holdings.get_mut(&pid).and_then(|held| held.get_mut(&r)) {
+                Some(n) if *n > 0 => *n -= 1,
+                _ => {
+                    return Err(SimulationError::ReleaseWithoutHold {
+                        process: pid,
+                        resource: r,
+                    })
+                }
+            }
+            if holdings[&pid].values().all(|&n| n == 0) {
+                let now = self.context.time();
+                if let Some(busy) = self.busy.get_mut(&pid) {
+                    busy.0 += (now - busy.1).to_f64();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Grant all the resources requested with `RequestAll` by the process, if
+    /// it is at the front of all their queues and all of them are available.
+    fn try_grant_all(&mut self, pid: ProcessId) {
+        let requested: Vec<ResourceId> = self
+            .resource_ids()
+            .filter(|&r| self.resources[r.0].queue.iter().any(|w| w.process == pid && w.all))
+            .collect();
+        let ready = requested.iter().all(|&r| {
+            let res = &self.resources[r.0];
+            res.queue.front().map(|w| w.process) == Some(pid) && res.can_acquire_exclusive()
+        });
+        if !ready {
+            return;
+        }
+        let now = self.context.time();
+        for &r in requested.iter() {
+            let res = &mut self.resources[r.0];
+            let waiter = res.queue.pop_front().unwrap();
+            res.try_acquire(pid, false, waiter.priority);
+            res.wait_stats.add((now - waiter.since).to_f64());
+            self.record_hold(pid, r);
+        }
+        self.future_events.push(Event { time: now, process: pid, label: None });
+        // the processes behind it may be served now
+        for r in requested {
+            self.wake_ready(r);
+        }
+    }
+
+    /// Remove the process from the queues of the resources requested by
+    /// `select`, except `granted`.
+    fn disarm_select(&mut self, pid: ProcessId, select: &ArmedSelect<Time>, granted: Option<ResourceId>) {
+        for &(r, _) in select.requests.iter() {
+            if Some(r) != granted {
+                self.resources[r.0].queue.retain(|w| w.process != pid);
+                // the process may have been blocking the ones behind it
+                self.wake_ready(r);
+            }
+        }
+        for &(s, _) in select.gets.iter() {
+            self.stores[s].queue.retain(|w| w.0 != pid);
+        }
+    }
+
+    /// Handle `Effect::Select`: resume the process now if one of the
+    /// alternatives can happen immediately, otherwise arm all of them.
+    fn arm_select(&mut self, pid: ProcessId, effects: Vec<Effect<T, Time, R>>) -> Result<(), SimulationError<Time>> {
+        let now = self.context.time();
+        let mut dropped = Vec::new();
+        for (i, e) in effects.iter().enumerate() {
+            if let Effect::TimeOut(t) = *e {
+                if !self.accept_delay(pid, t)? {
+                    dropped.push(i);
+                }
+            }
+        }
+        for (i, e) in effects.iter().enumerate() {
+            match *e {
+                Effect::Request(r) => {
+                    let res = &mut self.resources[r.0];
+                    if res.queue.is_empty() && res.try_acquire(pid, false, 0) {
+                        res.wait_stats.add(0.0);
+                        self.record_hold(pid, r);
+                        self.context_of(pid).selected.borrow_mut().insert(pid, Some(i));
+                        self.context_of(pid).granted.borrow_mut().insert(pid, r);
+                        self.future_events.push(Event { time: now, process: pid, label: None });
+                        return Ok(());
+                    }
+                }
+                Effect::Get(s, ref filter) => {
+                    if let Some(item) = filter.take_from(&mut self.stores[s].items) {
+                        self.context_of(pid).items.borrow_mut().insert(pid, item);
+                        self.context_of(pid).selected.borrow_mut().insert(pid, Some(i));
+                        self.future_events.push(Event { time: now, process: pid, label: None });
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut select = ArmedSelect {
+            timeout: None,
+            timeout_event: None,
+            requests: Vec::new(),
+            gets: Vec::new(),
+        };
+        for (i, e) in effects.into_iter().enumerate() {
+            match e {
+                Effect::Request(r) => {
+                    self.resources[r.0].queue.push(Waiter {
+                        process: pid,
+                        shared: false,
+                        priority: 0,
+                        since: now,
+                        all: false,
+                    });
+                    select.requests.push((r, i));
+                }
+                Effect::Get(s, filter) => {
+                    self.stores[s].queue.push_back((pid, filter));
+                    select.gets.push((s, i));
+                }
+                Effect::TimeOut(_) if dropped.contains(&i) => {}
+                Effect::TimeOut(t) => {
+                    let at = now + t;
+                    match select.timeout {
+                        Some((prev, _)) if prev <= at => {}
+                        _ => select.timeout = Some((at, i)),
+                    }
+                }
+                _ => panic!("ERROR: process {} yielded an unsupported effect in Select", pid),
+            }
+        }
+        if let Some((t, _)) = select.timeout {
+            select.timeout_event = Some(self.future_events.push(Event { time: t, process: pid, label: None }));
+        }
+        self.selects.insert(pid, select);
+        Ok(())
+    }
+
+    /// Let the processes read the state of the resources from the contexts.
+    ///
+    /// The buffers of the contexts are overwritten in place, so that no
+    /// allocation is made once they have grown to the number of resources.
+    fn share_resource_states(&self) {
+        let mut states = self.context.resources.borrow_mut();
+        states.clear();
+        states.extend(self.resource_ids().map(|r| self.resource_state(r)));
+        for (_, c) in &self.subcontexts {
+            let mut shared = c.resources.borrow_mut();
+            shared.clear();
+            shared.extend_from_slice(&states);
+        }
+    }
+
+    /// The primary context, followed by the attached ones.
+    fn contexts(&self) -> impl Iterator<Item = &Context<T, Time, R>> {
+        std::iter::once(&*self.context).chain(self.subcontexts.iter().map(|(_, c)| &**c))
+    }
+
+    /// The context of the given process: the attached one with the greatest
+    /// offset not above its id, or the primary one.
+    fn context_of(&self, pid: ProcessId) -> &Context<T, Time, R> {
+        match self.subcontexts.iter().rev().find(|&&(offset, _)| pid.0 >= offset) {
+            Some((_, c)) => c,
+            None => &self.context,
+        }
+    }
+
+    /// Move the messages and interruptions pushed to a context for the
+    /// processes of another one, e.g. by a process of an attached context
+    /// to one of the primary context.
+    fn route_messages(&self) {
+        if self.subcontexts.is_empty() {
+            return;
+        }
+        for c in self.contexts() {
+            let owned_elsewhere = |p: &&ProcessId| !std::ptr::eq(self.context_of(**p), c);
+            let stray: Vec<ProcessId> = c.messages.borrow().keys().filter(owned_elsewhere).cloned().collect();
+            for p in stray {
+                let inbox = c.messages.borrow_mut().remove(&p).unwrap_or_default();
+                for (priority, message) in inbox {
+                    self.context_of(p).push_message_with_priority(p, message, priority);
+                }
+            }
+            let stray: Vec<ProcessId> = c.interrupted.borrow().keys().filter(owned_elsewhere).cloned().collect();
+            for p in stray {
+                if let Some(payload) = c.interrupted.borrow_mut().remove(&p) {
+                    self.context_of(p).interrupted.borrow_mut().insert(p, payload);
+                }
+            }
+        }
+    }
+
+    /// Create and schedule the processes spawned through the `Context`.
+    fn create_spawned(&mut self) {
+        let spawned: Vec<_> = self.contexts().flat_map(|c| c.spawned.borrow_mut().drain(..).collect::<Vec<_>>()).collect();
+        for (pid, process) in spawned {
+            self.create_process(pid, process);
+            self.future_events.push(Event {
+                time: self.context.time(),
+                process: pid,
+                label: None,
+            });
+        }
+    }
+
+    /// Run the simulation until and ending condition is met, or there are
+    /// no more events to process.
+    ///
+    /// If the simulation is deadlocked when it stops, the handler set with
+    /// `on_deadlock` is invoked.
+    ///
+    /// Panics if a step returns an error: use `run_strict` or `step` to
+    /// handle it.
+    pub fn run(mut self, until: EndCondition<T, Time, R>) -> Simulation<T, Time, R> {
+        self.run_mut(until);
+        self
+    }
+
+    /// Run the simulation as `run` does, without consuming it, e.g. to run
+    /// it in phases and inspect it in between.
+    pub fn run_mut(&mut self, until: EndCondition<T, Time, R>) {
+        if let Err(e) = self.run_until(&until) {
+            panic!("ERROR: {}", e);
+        }
+    }
+
+    /// Run the simulation as `run` does, but stop at the first error and
+    /// return it together with the simulation, that can be inspected (e.g.
+    /// its `processed_events`) as it was when the error occurred.
+    pub fn run_strict(
+        mut self,
+        until: EndCondition<T, Time, R>,
+    ) -> Result<Simulation<T, Time, R>, (Simulation<T, Time, R>, SimulationError<Time>)> {
+        match self.run_until(&until) {
+            Ok(()) => Ok(self),
+            Err(e) => Err((self, e)),
+        }
+    }
+
+    /// Run the simulation as `run` does, processing each event when the real
+    /// time elapsed since the start of the run corresponds to its simulation
+    /// time, e.g. for demos or hardware in the loop. `scale` is the amount of
+    /// simulation time per second of real time.
+    ///
+    /// If the processing of the events falls behind the real time a warning
+    /// is printed and the events are processed as soon as possible until it
+    /// catches up. Panics if `scale` is not positive or a step returns an
+    /// error.
+    pub fn run_realtime(mut self, scale: f64, until: EndCondition<T, Time, R>) -> Simulation<T, Time, R> {
+        assert!(scale > 0.0, "ERROR: the real time scale must be positive");
+        let start = Instant::now();
+        let start_time = self.context.time();
+        let mut behind = false;
+        let paced = self.run_paced(&until, |next| {
+            let due = Duration::from_secs_f64(((next - start_time).to_f64() / scale).max(0.0));
+            let elapsed = start.elapsed();
+            if elapsed < due {
+                thread::sleep(due - elapsed);
+                behind = false;
+            } else if elapsed - due > Duration::from_millis(10) && !behind {
+                eprintln!(
+                    "WARNING: the simulation is {:?} behind the real time at time {:?}",
+                    elapsed - due, next
+                );
+                behind = true;
+            }
+        });
+        if let Err(e) = paced {
+            panic!("ERROR: {}", e);
+        }
+        self
+    }
+
+    fn run_until(&mut self, until: &EndCondition<T, Time, R>) -> Result<(), SimulationError<Time>> {
+        self.run_paced(until, |_| {})
+    }
+
+    /// Run until the ending condition is met, invoking `pace` with the time
+    /// of the next event before each step, e.g. to wait for the real time.
+    fn run_paced<F: FnMut(Time)>(&mut self, until: &EndCondition<T, Time, R>, mut pace: F) -> Result<(), SimulationError<Time>> {
+        self.deliver_signals();
+        let mut steps = 0;
+        while !self.check_ending_condition(until, steps) {
+            if let Some(next) = self.future_events.peek().map(|e| e.time) {
+                pace(next);
+            }
+            match self.step()? {
+                Some(_) => steps += 1,
+                None => break,
+            }
+        }
+        let now = self.context.time();
+        if self.future_events.peek().map_or(true, |e| e.time > now) {
+            // nothing else can happen at the current time
+            self.fire_timestamp_monitor();
+        }
+        if self.future_events.is_empty() {
+            self.check_replay_complete()?;
+        }
+        if self.is_deadlocked() {
+            if let Some(mut handler) = self.deadlock_handler.take() {
+                handler(&self.stuck_processes());
+                self.deadlock_handler = Some(handler);
+            }
+        }
+        Ok(())
+    }
+
+    /// Attach some data to the process, see `Context::set_process_data`.
+    pub fn set_process_data<D: Any>(&mut self, pid: ProcessId, data: D) {
+        self.context_of(pid).set_process_data(pid, data);
+    }
+
+    /// Returns the data attached to the process, see
+    /// `Context::process_data`.
+    pub fn process_data<D: Any>(&self, pid: ProcessId) -> Option<Rc<D>> {
+        self.context_of(pid).process_data(pid)
+    }
+
+    /// Returns `true` if there are no events to process, so that `step` does
+    /// nothing.
+    ///
+    /// This does not mean that the simulation is finished: processes waiting
+    /// e.g. for a message or in `Effect::Wait` can still be resumed by events
+    /// scheduled later by the owner of the simulation, see `is_deadlocked`.
+    pub fn is_idle(&self) -> bool {
+        self.future_events.is_empty()
+    }
+
+    /// Returns `true` if there are processes that have not completed yet, but
+    /// no more events to process, so that no process can ever be resumed.
+    ///
+    /// This usually means that the model is stuck, e.g. processes waiting for
+    /// a message that is never sent or for a resource that is never released.
+    pub fn is_deadlocked(&self) -> bool {
+        self.future_events.is_empty()
+            && self.processes.values().any(|p| p.is_some())
+    }
+
+    /// Returns the processes that yielded `Effect::Wait` and have not been
+    /// scheduled again since, sorted by id.
+    pub fn waiting_processes(&self) -> Vec<ProcessId> {
+        let mut waiting: Vec<_> = self.contexts().flat_map(|c| c.waiting_processes()).collect();
+        waiting.sort();
+        waiting
+    }
+
+    /// Resume, at the current time, a process that yielded `Effect::Suspend`
+    /// or `Effect::Wait` and has not been scheduled again since.
+    pub fn wake_process(&mut self, pid: ProcessId) -> Result<(), WakeError> {
+        match self.processes.get(&pid) {
+            None => return Err(WakeError::UnknownProcess(pid)),
+            Some(None) => return Err(WakeError::Completed(pid)),
+            Some(Some(_)) => {}
+        }
+        if !self.context_of(pid).is_waiting(pid) {
+            return Err(WakeError::NotSuspended(pid));
+        }
+        let now = self.context.time();
+        self.schedule_event(Event { time: now, process: pid, label: None })
+            .expect("ERROR: a suspended process can always be scheduled now");
+        Ok(())
+    }
+
+    /// Returns the processes that have not completed yet and are not
+    /// scheduled, with what each of them is waiting for.
+    pub fn stuck_processes(&self) -> Vec<(ProcessId, WaitReason)> {
+        let scheduled: HashSet<ProcessId> = self.future_events.iter().map(|e| e.process).collect();
+        let mut stuck = Vec::new();
+        for (&pid, p) in self.processes.iter() {
+            if p.is_none() || scheduled.contains(&pid) {
+                continue;
+            }
+            let reason = if self.selects.contains_key(&pid) {
+                WaitReason::Select
+            } else if self.message_waiters.contains(&pid) || self.gathers.contains_key(&pid) {
+                WaitReason::Message
+            } else if let Some((&s, _)) = self.signal_waiters.iter().find(|(_, w)| w.contains(&pid)) {
+                WaitReason::Signal(s)
+            } else if let Some((&p, _)) = self.joiners.iter().find(|(_, j)| j.contains(&pid)) {
+                WaitReason::Join(p)
+            } else if let Some(r) = self.resources.iter().position(|r| r.queue.iter().any(|w| w.process == pid)) {
+                WaitReason::Resource(ResourceId(r))
+            } else if let Some(p) = self.pools.iter().position(|p| p.queue.iter().any(|w| w.0 == pid)) {
+                WaitReason::Pool(p)
+            } else if let Some(s) = self.stores.iter().position(|s| s.queue.iter().any(|w| w.0 == pid)) {
+                WaitReason::Store(s)
+            } else {
+                WaitReason::Event
+            };
+            stuck.push((pid, reason));
+        }
+        stuck.sort_by_key(|&(pid, _)| pid);
+        stuck
+    }
+
+    /// Look for common modelling mistakes, e.g. before running the
+    /// simulation, and return a warning for each of them.
+    ///
+    /// Generators can not be inspected, so a `Request` without a matching
+    /// `Release` is found only after the process completed still holding the
+    /// resource, and only if hold tracking is enabled.
+    pub fn validate(&self) -> Vec<ValidationWarning> {
+        let mut warnings = Vec::new();
+        let mut unknown: Vec<ProcessId> = self
+            .future_events
+            .iter()
+            .map(|e| e.process)
+            .filter(|pid| !self.processes.contains_key(pid))
+            .collect();
+        unknown.sort();
+        unknown.dedup();
+        for pid in unknown {
+            warnings.push(ValidationWarning {
+                pid_or_rid: pid.0,
+                kind: ValidationKind::UnknownProcess,
+                message: format!("an event is scheduled for process {}, that does not exist", pid),
+            });
+        }
+        let started: HashSet<ProcessId> = self.processed_events.iter().map(|e| e.process).collect();
+        for (pid, reason) in self.stuck_processes() {
+            if reason == WaitReason::Event && !started.contains(&pid) {
+                warnings.push(ValidationWarning {
+                    pid_or_rid: pid.0,
+                    kind: ValidationKind::NeverScheduled,
+                    message: format!("process {} is never scheduled", pid),
+                });
+            }
+        }
+        if let Some(holdings) = self.holdings.as_ref() {
+            let mut unreleased: Vec<(ResourceId, ProcessId)> = holdings
+                .iter()
+                .filter(|&(pid, _)| self.processes.get(pid).map_or(false, |p| p.is_none()))
+                .flat_map(|(&pid, held)| held.iter().filter(|&(_, &n)| n > 0).map(move |(&r, _)| (r, pid)))
+                .collect();
+            unreleased.sort();
+            for (r, pid) in unreleased {
+                warnings.push(ValidationWarning {
+                    pid_or_rid: r.0,
+                    kind: ValidationKind::UnreleasedResource,
+                    message: format!("process {} completed without releasing resource {}", pid, r),
+                });
+            }
+        }
+        for (r, res) in self.resources.iter().enumerate() {
+            if res.allocated == 0 {
+                warnings.push(ValidationWarning {
+                    pid_or_rid: r,
+                    kind: ValidationKind::ZeroCapacity,
+                    message: format!("resource {} has no units", r),
+                });
+            }
+        }
+        warnings
+    }
+
+    /// Set a handler invoked by `run` if the simulation is deadlocked when it
+    /// stops, with the list of stuck processes and what they are waiting for.
+    pub fn on_deadlock(&mut self, handler: Box<dyn FnMut(&[(ProcessId, WaitReason)])>) {
+        self.deadlock_handler = Some(handler);
+    }
+
+    /// Record, from now on, every effect yielded by the processes, with the
+    /// process that yielded it. Run the returned `RecordingSimulation` and
+    /// call `finish` to obtain the `Recording`.
+    pub fn record(mut self) -> RecordingSimulation<T, Time, R>
+    where
+        T: Clone + 'static,
+        Time: 'static,
+        R: Clone + 'static,
+    {
+        let effects = Rc::new(RefCell::new(Vec::new()));
+        let log = effects.clone();
+        self.replay_progress = None;
+        self.effect_hook = Some(Box::new(move |pid, effect: &Effect<T, Time, R>| {
+            log.borrow_mut().push((pid, effect.clone()));
+            Ok(())
+        }));
+        RecordingSimulation {
+            simulation: self,
+            effects,
+        }
+    }
+
+    /// Check, from now on, that the processes yield the effects of the
+    /// recording, in the same order.
+    ///
+    /// The generators still run, since they keep the state of the processes,
+    /// but any deviation from the recorded run, e.g. because of a change in
+    /// the model or a source of nondeterminism, is reported by `step` as
+    /// `SimulationError::ReplayDivergence` at the first effect that differs.
+    /// If the run ends with recorded effects never yielded, the error is
+    /// reported, for the first of them, once no events are left.
+    ///
+    /// Effects are compared through their `Debug` representation, written
+    /// once for the recording and matched without allocating for each yield.
+    pub fn replay(&mut self, recording: &Recording<T, Time, R>)
+    where
+        T: Debug + 'static,
+        Time: 'static,
+        R: Debug + 'static,
+    {
+        let expected: Vec<_> = recording
+            .effects
+            .iter()
+            .map(|(pid, effect)| (*pid, format!("{:?}", effect)))
+            .collect();
+        let index = Rc::new(Cell::new(0));
+        self.replay_progress = Some((index.clone(), expected.iter().map(|&(pid, _)| pid).collect()));
+        self.effect_hook = Some(Box::new(move |pid, effect: &Effect<T, Time, R>| {
+            let i = index.get();
+            match expected.get(i) {
+                Some((p, e)) if *p == pid && debug_matches(effect, e) => {
+                    index.set(i + 1);
+                    Ok(())
+                }
+                _ => Err(SimulationError::ReplayDivergence { process: pid, index: i }),
+            }
+        }));
+    }
+/*
+    pub fn nonblocking_run(mut self, until: EndCondition) -> thread::JoinHandle<Simulation> {
+        thread::spawn(move || {
+            self.run(until)
+        })
+    }
+*/
+
+    /// Reset the simulation to time 0, to run the same model again, e.g. for
+    /// another replication in a Monte Carlo study.
+    ///
+    /// The event log, the future events, the messages and the interruptions
+    /// are cleared and all the resources and pools are made fully available
+    /// again, with empty queues. Processes and resources are not destroyed,
+    /// but generators can not be rewound: processes that already completed
+    /// remain completed and the ones that are alive keep their state, so the
+    /// model must be supplied fresh processes, with new ids, to start over.
+    pub fn reset(&mut self) {
+        #[cfg(feature = "sync")]
+        for c in self.sync_contexts.iter() {
+            *c.time.lock().unwrap() = Time::default();
+        }
+        for c in self.contexts() {
+            c.time.set(Time::default());
+            c.messages.borrow_mut().clear();
+            c.interrupted.borrow_mut().clear();
+            c.selected.borrow_mut().clear();
+            c.granted.borrow_mut().clear();
+            c.joined.borrow_mut().clear();
+            c.items.borrow_mut().clear();
+            c.waiting.borrow_mut().clear();
+            c.created.borrow_mut().clear();
+            c.preemptions.borrow_mut().clear();
+            c.data.borrow_mut().clear();
+            c.fired.borrow_mut().clear();
+        }
+        self.future_events.clear();
+        self.processed_events.clear();
+        self.dropped_events = 0;
+        self.timestamp_pending = false;
+        if let Some(log) = self.per_process_log.as_mut() {
+            log.clear();
+        }
+        if let Some(times) = self.wall_times.as_mut() {
+            times.clear();
+        }
+        if let Some(stats) = self.process_stats.as_mut() {
+            stats.clear();
+        }
+        self.context.series.borrow_mut().clear();
+        for res in self.resources.iter_mut() {
+            res.available = res.allocated;
+            res.shared_holders = 0;
+            res.has_exclusive = false;
+            res.excess = 0;
+            res.holders.clear();
+            res.preempted.clear();
+            res.acquired.clear();
+            res.queue.clear();
+            res.wait_stats = WaitStats::default();
+        }
+        for pool in self.pools.iter_mut() {
+            pool.available = pool.capacity;
+            pool.queue.clear();
+        }
+        for store in self.stores.iter_mut() {
+            store.queue.clear();
+        }
+        self.selects.clear();
+        self.message_waiters.clear();
+        self.gathers.clear();
+        self.signal_waiters.clear();
+        self.joiners.clear();
+        self.wakeups.clear();
+        self.woken.clear();
+        self.last_fires.clear();
+        self.deferred.clear();
+        self.in_transit.clear();
+        self.auto_releases.clear();
+        self.pending_holds.clear();
+        self.context.population.set(Population::default());
+        self.results.clear();
+        self.completion_times.clear();
+        self.round.clear();
+        if let Some(holdings) = self.holdings.as_mut() {
+            holdings.clear();
+        }
+        self.busy.clear();
+    }
+
+    /// Take a snapshot of the current state of the simulation, that can be
+    /// restored later on to run several continuations from a common point.
+    ///
+    /// See `SimulationSnapshot` for what is captured.
+    pub fn snapshot(&self) -> SimulationSnapshot<T, Time, R>
+    where
+        T: Clone,
+        R: Clone,
+    {
+        SimulationSnapshot {
+            context: (*self.context).clone(),
+            future_events: self.future_events.clone(),
+            processed_events: self.processed_events.clone(),
+            dropped_events: self.dropped_events,
+            per_process_log: self.per_process_log.clone(),
+            resources: self.resources.clone(),
+            pools: self.pools.clone(),
+            stores: self.stores.clone(),
+            selects: self.selects.clone(),
+            message_waiters: self.message_waiters.clone(),
+            gathers: self.gathers.clone(),
+            signal_waiters: self.signal_waiters.clone(),
+            joiners: self.joiners.clone(),
+            groups: self.groups.clone(),
+            results: self.results.clone(),
+            completion_times: self.completion_times.clone(),
+            round: self.round.clone(),
+            wakeups: self.wakeups.clone(),
+            woken: self.woken.clone(),
+            last_fires: self.last_fires.clone(),
+            deferred: self.deferred.clone(),
+            in_transit: self.in_transit.clone(),
+            auto_releases: self.auto_releases.clone(),
+            pending_holds: self.pending_holds.clone(),
+            holdings: self.holdings.clone(),
+            busy: self.busy.clone(),
+        }
+    }
+
+    /// Bring the simulation back to the state captured in the snapshot.
+    ///
+    /// The processes are not touched: their generators keep the state they
+    /// have now, so the ones whose behavior depends on their past must be
+    /// supplied again by the user with matching `ProcessId`s.
+    pub fn restore(&mut self, snapshot: &SimulationSnapshot<T, Time, R>)
+    where
+        T: Clone,
+        R: Clone,
+    {
+        self.context.restore_from(&snapshot.context);
+        self.future_events = snapshot.future_events.clone();
+        self.processed_events = snapshot.processed_events.clone();
+        self.dropped_events = snapshot.dropped_events;
+        self.per_process_log = snapshot.per_process_log.clone();
+        self.resources = snapshot.resources.clone();
+        self.pools = snapshot.pools.clone();
+        self.stores = snapshot.stores.clone();
+        self.selects = snapshot.selects.clone();
+        self.message_waiters = snapshot.message_waiters.clone();
+        self.gathers = snapshot.gathers.clone();
+        self.signal_waiters = snapshot.signal_waiters.clone();
+        self.joiners = snapshot.joiners.clone();
+        self.groups = snapshot.groups.clone();
+        self.results = snapshot.results.clone();
+        self.completion_times = snapshot.completion_times.clone();
+        self.round = snapshot.round.clone();
+        self.wakeups = snapshot.wakeups.clone();
+        self.woken = snapshot.woken.clone();
+        self.last_fires = snapshot.last_fires.clone();
+        self.deferred = snapshot.deferred.clone();
+        self.in_transit = snapshot.in_transit.clone();
+        self.auto_releases = snapshot.auto_releases.clone();
+        self.pending_holds = snapshot.pending_holds.clone();
+        self.holdings = snapshot.holdings.clone();
+        self.busy = snapshot.busy.clone();
+    }
+
+    /// Save the state of the simulation, except the processes, in a
+    /// checkpoint that can be serialized with the `serde` feature.
+    ///
+    /// See `SimulationCheckpoint` for what is saved.
+    pub fn checkpoint(&self) -> SimulationCheckpoint<T, Time, R>
+    where
+        T: Clone,
+        R: Clone,
+    {
+        SimulationCheckpoint {
+            time: self.context.time(),
+            messages: self.context.messages.borrow().clone(),
+            interrupted: self.context.interrupted.borrow().clone(),
+            rng: self.context.rng.get(),
+            next_pid: self.context.next_pid.get(),
+            selected: self.context.selected.borrow().clone(),
+            granted: self.context.granted.borrow().clone(),
+            joined: self.context.joined.borrow().clone(),
+            items: self.context.items.borrow().clone(),
+            waiting: self.context.waiting.borrow().clone(),
+            created: self.context.created.borrow().clone(),
+            preemptions: self.context.preemptions.borrow().clone(),
+            signals: self.context.signals.borrow().clone(),
+            series: self.context.series.borrow().clone(),
+            population: self.context.population.get(),
+            future_events: self.future_events.entries(),
+            next_event_id: self.future_events.next_id,
+            processed_events: self.processed_events.clone(),
+            dropped_events: self.dropped_events,
+            resources: self.resources.clone(),
+            pools: self.pools.clone(),
+            stores: self.stores.clone(),
+            selects: self.selects.clone(),
+            message_waiters: self.message_waiters.clone(),
+            gathers: self.gathers.clone(),
+            signal_waiters: self.signal_waiters.clone(),
+            joiners: self.joiners.clone(),
+            groups: self.groups.clone(),
+            wakeups: self.wakeups.clone(),
+            woken: self.woken.clone(),
+            last_fires: self.last_fires.clone(),
+            deferred: self.deferred.clone(),
+            in_transit: self.in_transit.clone(),
+            auto_releases: self.auto_releases.clone(),
+            pending_holds: self.pending_holds.clone(),
+            completed: self
+                .processes
+                .iter()
+                .filter(|(_, p)| p.is_none())
+                .map(|(pid, _)| *pid)
+                .collect(),
+            results: self.results.clone(),
+            completion_times: self.completion_times.clone(),
+            holdings: self.holdings.clone(),
+            busy: self.busy.clone(),
+        }
+    }
+
+    /// Create a simulation, with a new `Context`, from a checkpoint.
+    ///
+    /// The processes that had completed remain completed, while the ones
+    /// that were still alive must be created again, with the same
+    /// `ProcessId`, before the events targeting them are processed. Use
+    /// `shared_context` to obtain the `Context` for the new generators.
+    pub fn from_checkpoint(checkpoint: SimulationCheckpoint<T, Time, R>) -> Simulation<T, Time, R> {
+        let ctx = Context::new();
+        ctx.time.set(checkpoint.time);
+        *ctx.messages.borrow_mut() = checkpoint.messages;
+        *ctx.interrupted.borrow_mut() = checkpoint.interrupted;
+        ctx.rng.set(checkpoint.rng);
+        ctx.next_pid.set(checkpoint.next_pid);
+        *ctx.selected.borrow_mut() = checkpoint.selected;
+        *ctx.granted.borrow_mut() = checkpoint.granted;
+        *ctx.joined.borrow_mut() = checkpoint.joined;
+        *ctx.items.borrow_mut() = checkpoint.items;
+        *ctx.waiting.borrow_mut() = checkpoint.waiting;
+        *ctx.created.borrow_mut() = checkpoint.created;
+        *ctx.preemptions.borrow_mut() = checkpoint.preemptions;
+        *ctx.signals.borrow_mut() = checkpoint.signals;
+        *ctx.series.borrow_mut() = checkpoint.series;
+        ctx.population.set(checkpoint.population);
+        let mut s = Simulation::new(Rc::new(ctx));
+        for (id, event) in checkpoint.future_events {
+            s.future_events.insert(id, event);
+        }
+        s.future_events.next_id = checkpoint.next_event_id;
+        s.processed_events = checkpoint.processed_events;
+        s.dropped_events = checkpoint.dropped_events;
+        s.resources = checkpoint.resources;
+        s.pools = checkpoint.pools;
+        s.stores = checkpoint.stores;
+        s.selects = checkpoint.selects;
+        s.message_waiters = checkpoint.message_waiters;
+        s.gathers = checkpoint.gathers;
+        s.signal_waiters = checkpoint.signal_waiters;
+        s.joiners = checkpoint.joiners;
+        s.groups = checkpoint.groups;
+        s.wakeups = checkpoint.wakeups;
+        s.woken = checkpoint.woken;
+        s.last_fires = checkpoint.last_fires;
+        s.deferred = checkpoint.deferred;
+        s.in_transit = checkpoint.in_transit;
+        s.auto_releases = checkpoint.auto_releases;
+        s.pending_holds = checkpoint.pending_holds;
+        s.processes = checkpoint.completed.into_iter().map(|pid| (pid, None)).collect();
+        s.results = checkpoint.results;
+        s.completion_times = checkpoint.completion_times;
+        s.holdings = checkpoint.holdings;
+        s.busy = checkpoint.busy;
+        s
+    }
+
+    /// Fork the simulation at the current time.
+    ///
+    /// The forked simulation starts with the same clock, future events,
+    /// resources and event log, but has its own copy of the `Context`, so the
+    /// two simulations can proceed independently (e.g. to explore different
+    /// branches in a Monte Carlo study).
+    ///
+    /// See the `Clone` implementation for the limitations about processes.
+    pub fn fork_at_current_time(&self) -> Simulation<T, Time, R>
+    where
+        T: Clone,
+        R: Clone,
+    {
+        self.clone()
+    }
+
+    /// Returns `true` if the ending condition was met after `steps` steps of
+    /// the current run, `false` otherwise.
+    fn check_ending_condition(&self, ending_condition: &EndCondition<T, Time, R>, steps: usize) -> bool {
+        match &ending_condition {
+            EndCondition::Time(t) => if self.context.time() >= *t {
+                return true
+            },
+            EndCondition::NoEvents => if self.future_events.is_empty() {
+                return true
+            },
+            EndCondition::NSteps(n) => if steps == *n {
+                return true
+            },
+            EndCondition::ProcessDone(pid) => if let Some(None) = self.processes.get(pid) {
+                return true
+            },
+            EndCondition::Custom(f) => if f(self) {
+                return true
+            },
+            EndCondition::SteadyState { metric, window, tolerance } => {
+                assert!(*window > 0, "ERROR: the window of the steady state must be positive");
+                let mut samples = self.steady_samples.borrow_mut();
+                if steps == 0 {
+                    // a new run
+                    samples.clear();
+                }
+                samples.push_back(metric(self));
+                if samples.len() > *window {
+                    samples.pop_front();
+                }
+                if samples.len() == *window {
+                    let mean = samples.iter().sum::<f64>() / *window as f64;
+                    if samples.iter().all(|x| (x - mean).abs() <= *tolerance) {
+                        return true
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Returns an error if the time, or delay, of an event for the process is NaN
+/// or infinite.
+fn check_time<Time: SimTime>(pid: ProcessId, t: Time, now: Time) -> Result<(), SimulationError<Time>> {
+    // NaN is the only value not comparable with itself
+    if t.partial_cmp(&t).is_none() {
+        return Err(SimulationError::NaNEventTime {
+            process: pid,
+            scheduled_at: now,
+        });
+    }
+    if t.to_f64().is_infinite() {
+        return Err(SimulationError::InfiniteEventTime {
+            process: pid,
+            scheduled_at: now,
+        });
+    }
+    Ok(())
+}
+
+impl<T, R> Simulation<T, f64, R> {
+    /// Add a source of processes arriving as a Poisson process with the given
+    /// rate, i.e. with exponentially distributed interarrival times drawn
+    /// from the random number generator of the `Context`.
+    ///
+    /// At every arrival, `factory` is called with a new, unique `ProcessId`
+    /// and the process it returns is created and started.
+    /// The source itself is a process, whose id is returned, that is
+    /// scheduled starting from the current time and never completes: use an
+    /// ending condition other than `NoEvents` to stop the simulation.
+    pub fn add_poisson_source(
+        &mut self,
+        rate: f64,
+        mut factory: Box<dyn FnMut(ProcessId) -> Box<dyn Generator<Yield = Effect<T, f64, R>, Return = R> + Unpin>>,
+    ) -> ProcessId
+    where
+        T: 'static,
+        R: 'static,
+    {
+        let ctx = self.context.clone();
+        let source = self.context.new_pid();
+        self.create_process(source, Box::new(move || {
+            loop {
+                yield Effect::TimeOut(ctx.rand_exponential(rate));
+                let pid = ctx.new_pid();
+                ctx.spawn(pid, factory(pid));
+            }
+        }));
+        self.future_events.push(Event {
+            time: self.context.time(),
+            process: source,
+            label: None,
+        });
+        source
+    }
+
+    /// Create a token bucket, i.e. a pool of capacity `burst`, initially
+    /// full, refilled with one unit every `1 / rate` up to its capacity.
+    ///
+    /// A process takes a token yielding `Reserve(bucket, 1.0)` and never
+    /// gives it back: the requests up to `burst` are served immediately, then
+    /// at the rate of the refills. The refills are made by a process that
+    /// never completes: use an ending condition other than `NoEvents` to
+    /// stop the simulation.
+    pub fn create_token_bucket(&mut self, rate: f64, burst: f64) -> PoolId
+    where
+        T: 'static,
+        R: 'static,
+    {
+        assert!(rate > 0.0, "ERROR: the rate of a token bucket must be positive");
+        let bucket = self.create_pool(burst);
+        self.pools[bucket].bucket = true;
+        let refill = self.context.new_pid();
+        self.create_process(refill, Box::new(move || {
+            loop {
+                yield Effect::TimeOut(1.0 / rate);
+                yield Effect::Unreserve(bucket, 1.0);
+            }
+        }));
+        self.future_events.push(Event {
+            time: self.context.time(),
+            process: refill,
+            label: None,
+        });
+        bucket
+    }
+}
+
+/// Returns a generator that tries to acquire the resource for the process
+/// `pid`, without waiting in its queue: if it is busy, it sleeps for
+/// `base_delay`, then tries again, multiplying the delay by `factor` at every
+/// retry. It returns `true` once the resource is acquired, or `false` after
+/// `max_retries` retries failed.
+///
+/// Each attempt is a `Select` of the request and a `TimeOut(0.0)`. The
+/// generator can be used as a process when its return type matches, or
+/// driven by the generator of another process, yielding the effects it
+/// yields until it completes.
+pub fn retry_request<T: 'static, R: 'static>(
+    ctx: Rc<Context<T, f64, R>>,
+    pid: ProcessId,
+    resource: ResourceId,
+    base_delay: f64,
+    factor: f64,
+    max_retries: usize,
+) -> Box<dyn Generator<Yield = Effect<T, f64, R>, Return = bool> + Unpin> {
+    Box::new(move || {
+        let mut delay = base_delay;
+        let mut retries = 0;
+        loop {
+            yield Effect::Select(vec![Effect::Request(resource), Effect::TimeOut(0.0)]);
+            if ctx.selected_branch(pid) == Some(0) {
+                return true;
+            }
+            if retries == max_retries {
+                return false;
+            }
+            retries += 1;
+            yield Effect::TimeOut(delay);
+            delay *= factor;
+        }
+    })
+}
+
+/// The effects yielded by the processes during a run, in order, with the
+/// process that yielded each of them. See `Simulation::record`.
+#[derive(Debug, Clone)]
+pub struct Recording<T, Time = f64, R = ()> {
+    effects: Vec<(ProcessId, Effect<T, Time, R>)>,
+}
+
+impl<T, Time, R> Recording<T, Time, R> {
+    /// Returns the recorded effects.
+    pub fn effects(&self) -> &[(ProcessId, Effect<T, Time, R>)] {
+        self.effects.as_slice()
+    }
+}
+
+/// A `Simulation` that records the effects yielded by its processes,
+/// created by `Simulation::record`.
+pub struct RecordingSimulation<T, Time = f64, R = ()> {
+    simulation: Simulation<T, Time, R>,
+    effects: Rc<RefCell<Vec<(ProcessId, Effect<T, Time, R>)>>>,
+}
+
+impl<T, Time: SimTime, R> RecordingSimulation<T, Time, R> {
+    /// Proceed in the simulation by 1 step, see `Simulation::step`.
+    pub fn step(&mut self) -> Result<Option<Event<Time>>, SimulationError<Time>> {
+        self.simulation.step()
+    }
+
+    /// Run the simulation until an ending condition is met, see
+    /// `Simulation::run`.
+    pub fn run(self, until: EndCondition<T, Time, R>) -> RecordingSimulation<T, Time, R> {
+        RecordingSimulation {
+            simulation: self.simulation.run(until),
+            effects: self.effects,
+        }
+    }
+
+    /// Returns the simulation being recorded.
+    pub fn simulation(&self) -> &Simulation<T, Time, R> {
+        &self.simulation
+    }
+
+    /// Returns the simulation being recorded, e.g. to schedule events.
+    pub fn simulation_mut(&mut self) -> &mut Simulation<T, Time, R> {
+        &mut self.simulation
+    }
+
+    /// Stop recording and return the simulation and the recording.
+    pub fn finish(mut self) -> (Simulation<T, Time, R>, Recording<T, Time, R>) {
+        self.simulation.effect_hook = None;
+        self.simulation.replay_progress = None;
+        let effects = self.effects.replace(Vec::new());
+        (self.simulation, Recording { effects })
+    }
+}
+
+/// A read-only simulation that replays the events of a log, e.g. the
+/// processed events of a previous run, without processes. See
+/// `ReplaySimulation::from_log`.
+#[derive(Debug, Clone)]
+pub struct ReplaySimulation<Time = f64> {
+    events: Vec<Event<Time>>,
+    /// Number of events replayed so far.
+    next: usize,
+    time: Time,
+}
+
+impl<Time: SimTime> ReplaySimulation<Time> {
+    /// Create a `ReplaySimulation` that steps through the given log, e.g. the
+    /// `processed_events` of a previous run, saved and loaded with the
+    /// `serde` feature, to analyze or animate the timeline without running
+    /// the processes again.
+    ///
+    /// Panics if the events are not sorted by time.
+    pub fn from_log(events: &[Event<Time>]) -> ReplaySimulation<Time> {
+        if events.windows(2).any(|w| w[1].time < w[0].time) {
+            panic!("ERROR: the events of the log are not sorted by time");
+        }
+        ReplaySimulation {
+            events: events.to_vec(),
+            next: 0,
+            time: Time::default(),
+        }
+    }
+
+    /// Replay the next event of the log, moving the clock to its time.
+    ///
+    /// Returns `None` once all the events have been replayed.
+    pub fn step(&mut self) -> Option<Event<Time>> {
+        let event = *self.events.get(self.next)?;
+        self.next += 1;
+        self.time = event.time;
+        Some(event)
+    }
+
+    /// Replay the events until the clock reaches the given time, as
+    /// `EndCondition::Time` does, or the log is over.
+    pub fn run_until(&mut self, time: Time) {
+        while self.time < time && self.step().is_some() {}
+    }
+
+    /// Returns the time of the last event replayed.
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Returns the next event to replay, if any.
+    pub fn peek_next_event(&self) -> Option<&Event<Time>> {
+        self.events.get(self.next)
+    }
+
+    /// Returns the events replayed so far, in order.
+    pub fn processed_events(&self) -> &[Event<Time>] {
+        &self.events[..self.next]
+    }
+}
+
+/// Builder to set up a simulation with chained calls.
+///
+/// The builder creates the `Context`: use `context` to get a handle to it for
+/// the processes that need one. Resources get consecutive identifiers,
+/// starting from 0, in the order they are added.
+///
+/// ```
+/// #![feature(generators, generator_trait)]
+/// # extern crate desim;
+/// use desim::{Effect, EndCondition, ProcessId, ResourceId, SimulationBuilder};
+///
+/// # fn main() {
+/// // instead of creating the context, wrapping it in an Rc, creating the
+/// // simulation and then calling create_process, create_resource and
+/// // schedule_event on it
+/// let builder = SimulationBuilder::<()>::new().seed(42);
+/// let ctx = builder.context();
+/// let (s, _ctx) = builder
+///     .resource(1)
+///     .process(ProcessId(0), Box::new(move || {
+///         yield Effect::Request(ResourceId(0));
+///         yield Effect::TimeOut(ctx.rand_uniform());
+///         yield Effect::Release(ResourceId(0));
+///     }))
+///     .schedule(1.0, ProcessId(0))
+///     .build();
+/// let s = s.run(EndCondition::NoEvents);
+/// assert!(s.now() >= 1.0);
+/// # }
+/// ```
+pub struct SimulationBuilder<T, Time = f64, R = ()> {
+    simulation: Simulation<T, Time, R>,
+}
+
+impl<T, Time: SimTime, R> SimulationBuilder<T, Time, R> {
+    /// Start building a simulation with a new `Context`.
+    pub fn new() -> SimulationBuilder<T, Time, R> {
+        SimulationBuilder::from_context(Rc::new(Context::new()))
+    }
+
+    /// Start building a simulation that uses the given `Context`.
+    pub fn from_context(ctx: Rc<Context<T, Time, R>>) -> SimulationBuilder<T, Time, R> {
+        SimulationBuilder {
+            simulation: Simulation {
+                context: ctx,
+                subcontexts: Vec::new(),
+                #[cfg(feature = "sync")]
+                sync_contexts: Vec::new(),
+                processes: HashMap::default(),
+                future_events: EventQueue::new(),
+                processed_events: Vec::default(),
+                log_policy: LogPolicy::All,
+                dropped_events: 0,
+                per_process_log: None,
+                wall_times: None,
+                process_stats: None,
+                holdings: None,
+                busy: HashMap::default(),
+                resources: Vec::default(),
+                pools: Vec::default(),
+                stores: Vec::default(),
+                selects: HashMap::default(),
+                message_waiters: HashSet::default(),
+                gathers: HashMap::default(),
+                signal_waiters: HashMap::default(),
+                joiners: HashMap::default(),
+                groups: Vec::new(),
+                deadlock_handler: None,
+                effect_hook: None,
+                replay_progress: None,
+                monitors: Vec::new(),
+                resource_hooks: Vec::new(),
+                timestamp_monitor: None,
+                timestamp_pending: false,
+                steady_samples: RefCell::new(VecDeque::new()),
+                results: HashMap::default(),
+                completion_times: HashMap::default(),
+                policy: SchedulingPolicy::Fifo,
+                round: HashSet::default(),
+                wakeups: HashMap::default(),
+                woken: HashSet::default(),
+                last_fires: HashMap::default(),
+                deferred: HashMap::default(),
+                in_transit: HashMap::default(),
+                auto_releases: HashMap::default(),
+                pending_holds: HashMap::default(),
+                missing_policy: MissingPolicy::Panic,
+                nan_policy: NanPolicy::Reject,
+                time_unit: None,
+                warmup: None,
+                unique_scheduling: false,
+                event_budget: None,
+                strict_wakeups: false,
+            },
+        }
+    }
+
+    /// Reserve space for `n` future events, to avoid reallocations in large
+    /// simulations.
+    pub fn with_event_capacity(mut self, n: usize) -> Self {
+        self.simulation.future_events.reserve(n);
+        self
+    }
+
+    /// Reserve space for `n` processes.
+    pub fn with_process_capacity(mut self, n: usize) -> Self {
+        self.simulation.processes.reserve(n);
+        self
+    }
+
+    /// Discard the statistics collected before the given time, i.e. the
+    /// resource wait statistics, the series recorded with `Context::record`
+    /// and the samples of the monitors, so that they describe the steady
+    /// state of the model.
+    pub fn with_warmup(mut self, t: Time) -> Self {
+        self.simulation.warmup = Some(t);
+        self
+    }
+
+    /// Measure the wall-clock time of each step, see
+    /// `Simulation::enable_wall_clock_tracking`.
+    pub fn with_wall_clock_tracking(mut self) -> Self {
+        self.simulation.enable_wall_clock_tracking();
+        self
+    }
+
+    /// Track the units of each resource held by each process, so that a
+    /// process releasing a resource it does not hold makes the simulation
+    /// fail with `SimulationError::ReleaseWithoutHold`, see
+    /// `Simulation::enable_hold_tracking`.
+    pub fn with_hold_tracking(mut self) -> Self {
+        self.simulation.enable_hold_tracking();
+        self
+    }
+
+    /// Make `schedule_event`, and the processes yielding `Effect::Event`,
+    /// fail with `SimulationError::DuplicateScheduling` when the process
+    /// already has a pending event, e.g. because of a bug scheduling it
+    /// twice. The events the simulation schedules by itself, e.g. when a
+    /// resource is granted, are not checked.
+    pub fn with_unique_scheduling(mut self) -> Self {
+        self.simulation.unique_scheduling = true;
+        self
+    }
+
+    /// Make `schedule_event`, and the processes yielding `Effect::Event`,
+    /// fail with `SimulationError::DuplicateScheduling` when they wake up
+    /// again a process blocked by `Effect::Wait` before it is resumed,
+    /// instead of just printing a warning.
+    pub fn with_strict_wakeups(mut self) -> Self {
+        self.simulation.strict_wakeups = true;
+        self
+    }
+
+    /// Keep in the log only the `n` most recent processed events, see
+    /// `Simulation::set_log_capacity`.
+    pub fn with_log_capacity(mut self, n: usize) -> Self {
+        self.simulation.set_log_capacity(n);
+        self
+    }
+
+    /// Returns a handle to the `Context` of the simulation being built.
+    pub fn context(&self) -> Rc<Context<T, Time, R>> {
+        self.simulation.shared_context()
+    }
+
+    /// Add a process, see `Simulation::create_process`.
+    pub fn process(mut self, pid: ProcessId, process: ProcessBox<T, Time, R>) -> Self {
+        self.simulation.create_process(pid, process);
+        self
+    }
+
+    /// Add a resource with `n` units and a FIFO queue.
+    pub fn resource(self, n: usize) -> Self {
+        self.resource_with_discipline(n, QueueDiscipline::Fifo)
+    }
+
+    /// Add a resource with `n` units and the given queue discipline.
+    pub fn resource_with_discipline(mut self, n: usize, discipline: QueueDiscipline) -> Self {
+        self.simulation.create_resource(n, discipline);
+        self
+    }
+
+    /// Add a pool with the given capacity.
+    pub fn pool(mut self, capacity: f64) -> Self {
+        self.simulation.create_pool(capacity);
+        self
+    }
+
+    /// Schedule the process to be resumed at the given time.
+    ///
+    /// Panics if the time is NaN.
+    pub fn schedule(mut self, time: Time, process: ProcessId) -> Self {
+        if let Err(e) = self.simulation.schedule_event(Event { time, process, label: None }) {
+            panic!("ERROR: {}", e);
+        }
+        self
+    }
+
+    /// Seed the random number generator of the `Context`.
+    pub fn seed(self, seed: u64) -> Self {
+        self.simulation.context.seed(seed);
+        self
+    }
+
+    /// Returns the simulation, ready to run, and a handle to its `Context`.
+    pub fn build(self) -> (Simulation<T, Time, R>, Rc<Context<T, Time, R>>) {
+        let ctx = self.simulation.shared_context();
+        (self.simulation, ctx)
+    }
+}
+
+impl<T, Time: SimTime, R> Default for SimulationBuilder<T, Time, R> {
+    fn default() -> Self {
+        SimulationBuilder::new()
+    }
+}
+
+impl<Time: Debug> Display for Event<Time> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Event {{ time: {:?}, process: #{}", self.time, self.process)?;
+        if let Some(label) = self.label {
+            write!(f, ", label: {}", label)?;
+        }
+        write!(f, " }}")
+    }
+}
+
+impl<Time: SimTime> PartialEq for Event<Time> {
+    fn eq(&self, other: &Event<Time>) -> bool {
+        self.time == other.time
+    }
+}
+
+impl<Time: SimTime> Eq for Event<Time> {}
+
+impl<Time: SimTime> PartialOrd for Event<Time> {
+    fn partial_cmp(&self, other: &Event<Time>) -> Option<Ordering> {
+        self.time.partial_cmp(&other.time)
+    }
+}
+
+impl<Time: SimTime> Ord for Event<Time> {
+    fn cmp(&self, other: &Event<Time>) -> Ordering {
+        match self.time.partial_cmp(&other.time) {
+            Some(o) => o,
+            None => panic!("Event time was uncomparable. Maybe a NaN"),
+        }
+    }
+}
+
+/// A comparator of events, see `Simulation::with_ordering`.
+type EventOrdering<Time> = Rc<dyn Fn(&Event<Time>, &Event<Time>) -> Ordering>;
+
+/// A binary min-heap of events, ordered by time, or by a custom comparator,
+/// and then by insertion order, that also supports the removal of any event
+/// given its id.
+#[derive(Clone)]
+struct EventQueue<Time> {
+    heap: Vec<(EventId, Event<Time>)>,
+    /// Position in `heap` of each event.
+    positions: HashMap<EventId, usize>,
+    /// Number of events of each process with at least one.
+    pending: HashMap<ProcessId, usize>,
+    next_id: EventId,
+    /// The times of the events pushed are rounded to multiples of it.
+    resolution: Option<Time>,
+    ordering: Option<EventOrdering<Time>>,
+}
+
+impl<Time: SimTime> EventQueue<Time> {
+    fn new() -> EventQueue<Time> {
+        EventQueue {
+            heap: Vec::new(),
+            positions: HashMap::default(),
+            pending: HashMap::default(),
+            next_id: 0,
+            resolution: None,
+            ordering: None,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    fn reserve(&mut self, n: usize) {
+        self.heap.reserve(n);
+        self.positions.reserve(n);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Iterate over the events in no particular order.
+    fn iter(&self) -> impl Iterator<Item = &Event<Time>> {
+        self.heap.iter().map(|e| &e.1)
+    }
+
+    fn peek(&self) -> Option<&Event<Time>> {
+        self.heap.first().map(|e| &e.1)
+    }
+
+    /// Returns a new id that refers to no event, for an event dropped.
+    fn skip_id(&mut self) -> EventId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn push(&mut self, mut event: Event<Time>) -> EventId {
+        if let Some(resolution) = self.resolution {
+            event.time = event.time.round_to(resolution);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.insert(id, event);
+        id
+    }
+
+    /// Add an event with the given id, e.g. taken from `entries`.
+    fn insert(&mut self, id: EventId, event: Event<Time>) {
+        *self.pending.entry(event.process).or_insert(0) += 1;
+        self.heap.push((id, event));
+        self.positions.insert(id, self.heap.len() - 1);
+        self.sift_up(self.heap.len() - 1);
+    }
+
+    /// Returns the events with their ids, in the order they will be popped.
+    fn entries(&self) -> Vec<(EventId, Event<Time>)> {
+        let mut entries = self.heap.clone();
+        entries.sort_by(|a, b| self.compare(a, b));
+        entries
+    }
+
+    fn pop(&mut self) -> Option<(EventId, Event<Time>)> {
+        if self.heap.is_empty() {
+            return None;
+        }
+        let id = self.heap[0].0;
+        self.remove(id).map(|e| (id, e))
+    }
+
+    fn remove(&mut self, id: EventId) -> Option<Event<Time>> {
+        let i = self.positions.remove(&id)?;
+        let last = self.heap.len() - 1;
+        self.heap.swap(i, last);
+        let (_, event) = self.heap.pop().unwrap();
+        match self.pending.get_mut(&event.process) {
+            Some(n) if *n > 1 => *n -= 1,
+            _ => {
+                self.pending.remove(&event.process);
+            }
+        }
+        if i < last {
+            self.positions.insert(self.heap[i].0, i);
+            self.sift_down(i);
+            self.sift_up(i);
+        }
+        Some(event)
+    }
+
+    /// Returns the first scheduled event for the given time whose process is
+    /// not in `skip`.
+    fn first_at(&self, time: Time, skip: &HashSet<ProcessId>) -> Option<EventId> {
+        self.heap
+            .iter()
+            .filter(|e| e.1.time == time && !skip.contains(&e.1.process))
+            .min_by(|a, b| self.compare(a, b))
+            .map(|e| e.0)
+    }
+
+    fn clear(&mut self) {
+        self.heap.clear();
+        self.positions.clear();
+        self.pending.clear();
+    }
+
+    fn compare(&self, a: &(EventId, Event<Time>), b: &(EventId, Event<Time>)) -> Ordering {
+        let o = match self.ordering {
+            Some(ref ordering) => ordering(&a.1, &b.1),
+            None => a.1.cmp(&b.1),
+        };
+        o.then(a.0.cmp(&b.0))
+    }
+
+    fn less(&self, a: usize, b: usize) -> bool {
+        self.compare(&self.heap[a], &self.heap[b]) == Ordering::Less
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.positions.insert(self.heap[a].0, a);
+        self.positions.insert(self.heap[b].0, b);
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !self.less(i, parent) {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let mut smallest = i;
+            for child in [2 * i + 1, 2 * i + 2].iter() {
+                if *child < self.heap.len() && self.less(*child, smallest) {
+                    smallest = *child;
+                }
+            }
+            if smallest == i {
+                break;
+            }
+            self.swap(i, smallest);
+            i = smallest;
+        }
+    }
+}
+
+/// The future event list of the simulation, exposed only to compare it with
+/// other queues in `benches/event_queue.rs`. Not part of the public API.
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct RawEventQueue<Time = f64>(EventQueue<Time>);
+
+impl<Time: SimTime> Default for RawEventQueue<Time> {
+    fn default() -> Self {
+        RawEventQueue::new()
+    }
+}
+
+impl<Time: SimTime> RawEventQueue<Time> {
+    pub fn new() -> RawEventQueue<Time> {
+        RawEventQueue(EventQueue::new())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn push(&mut self, event: Event<Time>) -> EventId {
+        self.0.push(event)
+    }
+
+    pub fn pop(&mut self) -> Option<(EventId, Event<Time>)> {
+        self.0.pop()
+    }
+
+    pub fn remove(&mut self, id: EventId) -> Option<Event<Time>> {
+        self.0.remove(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use Context;
+    use ProcessId;
+
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    enum TestMessage {
+        MessageType1,
+        MessageType2(&'static str)
+    }
+
+    #[test]
+    fn display() {
+        use Effect;
+        use Event;
+        use ResourceId;
+
+        let e = Event{time: 12.0, process: ProcessId(3), label: None};
+        assert_eq!(e.to_string(), "Event { time: 12.0, process: #3 }");
+        assert_eq!(Effect::<&str>::TimeOut(5.3).to_string(), "TimeOut(5.3)");
+        assert_eq!(Effect::<&str>::SendMessage(ProcessId(2), "hi", 0.5).to_string(), "SendMessage(#2, hi, 0.5)");
+        let select = Effect::<&str>::Select(vec![Effect::Request(ResourceId(0)), Effect::TimeOut(3.0)]);
+        assert_eq!(select.to_string(), "Select([Request(0), TimeOut(3.0)])");
+        assert_eq!(Effect::<&str>::Event(e).to_string(), "Event(Event { time: 12.0, process: #3 })");
+        let e = Event{time: 1.0, process: ProcessId(3), label: Some("arrival")};
+        assert_eq!(e.to_string(), "Event { time: 1.0, process: #3, label: arrival }");
+    }
+
+    #[test]
+    fn debug() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        s.schedule_event(Event{time: 0.5, process: ProcessId(1), label: None}).unwrap();
+        s.step().unwrap();
+        assert_eq!(
+            format!("{:?}", s),
+            "Simulation { time: 0.5, processes: 1, future_events: 1, processed_events: 1 }"
+        );
+    }
+
+    #[test]
+    fn process_age() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::TimeOut(5.0);
+            ctx.record("age", ctx.process_age(ProcessId(1)).unwrap());
+        }));
+        s.schedule_event(Event{time: 2.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("age"), vec![(7.0, 7.0)]);
+        assert_eq!(s.context().process_age(ProcessId(2)), None);
+    }
+
+    #[test]
+    fn channel() {
+        use Channel;
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let readings = Channel::<(u32, f64)>::new();
+        let sender = readings.sender();
+        let receiver = readings.receiver(ProcessId(2));
+        s.create_process(ProcessId(1), Box::new(move || {
+            for i in 0..3 {
+                yield Effect::TimeOut(1.0);
+                yield sender.send(ProcessId(2), (i, i as f64 * 0.5));
+            }
+        }));
+        let c = ctx.clone();
+        s.create_process(ProcessId(2), Box::new(move || {
+            loop {
+                yield Effect::Wait;
+                while let Some((i, value)) = receiver.pop_message() {
+                    c.record("reading", value + i as f64);
+                }
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("reading"), vec![(1.0, 0.0), (2.0, 1.5), (3.0, 3.0)]);
+        assert_eq!(readings.receiver(ProcessId(2)).message_count(), 0);
+        // the message is queued when the effect is handled, not when built
+        let effect: Effect<&str> = readings.sender().send(ProcessId(2), (9, 0.0));
+        assert_eq!(readings.receiver(ProcessId(2)).message_count(), 0);
+        assert_eq!(format!("{}", effect), "ChannelSend(#2)");
+    }
+
+    #[test]
+    fn replay_from_log() {
+        use ReplaySimulation;
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        for p in 0..2 {
+            s.create_process(ProcessId(p), Box::new(|| {
+                for _ in 0..3 {
+                    yield Effect::TimeOut(1.5);
+                }
+            }));
+            s.schedule_event(Event{time: p as f64, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        let mut replay = ReplaySimulation::from_log(s.processed_events());
+        assert_eq!(replay.step().unwrap().process, ProcessId(0));
+        replay.run_until(2.0);
+        assert_eq!(replay.time(), 2.5);
+        assert_eq!(replay.processed_events().len(), 4);
+        assert_eq!(replay.peek_next_event().unwrap().time, 3.0);
+        while replay.step().is_some() {}
+        assert_eq!(replay.processed_events().len(), s.processed_events().len());
+        assert_eq!(replay.time(), s.context().time());
+    }
+
+    #[test]
+    fn replace_process() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::{NSteps, Time};
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let c = ctx.clone();
+        s.create_process(ProcessId(1), Box::new(move || {
+            loop {
+                yield Effect::TimeOut(1.0);
+                c.record("mode", 1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let mut s = s.run(Time(2.0));
+        s.replace_process(ProcessId(1), Box::new(move || {
+            loop {
+                yield Effect::TimeOut(5.0);
+                ctx.record("mode", 2.0);
+            }
+        }));
+        // the wakeup at 3.0 starts the new generator
+        let s = s.run(NSteps(2));
+        assert_eq!(s.series("mode"), vec![(1.0, 1.0), (2.0, 1.0), (8.0, 2.0)]);
+    }
+
+    #[test]
+    fn typed_time() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use SimDuration;
+        use SimInstant;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(move || {
+            let start = ctx.now();
+            yield Effect::timeout(SimDuration(2.5));
+            yield Effect::sleep_until(start + SimDuration(4.0));
+            ctx.record("elapsed", (ctx.now() - start).0);
+        }));
+        s.schedule_event(Event::at(SimInstant(1.0), ProcessId(1))).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("elapsed"), vec![(5.0, 4.0)]);
+        let (a, b) = (0.1, 0.2);
+        assert_eq!(SimInstant(a) + SimDuration(b), SimInstant(a + b));
+        assert_eq!(SimInstant(b) - SimInstant(a), SimDuration(b - a));
+        assert_eq!(SimDuration(a) + SimDuration(b) - SimDuration(b), SimDuration(a + b - b));
+        assert_eq!(Event::after(SimDuration(a), ProcessId(1)).time, a);
+    }
+
+    #[test]
+    fn store() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use ItemFilter;
+        use WaitReason;
+        use EndCondition::{NoEvents, Time};
+        use self::TestMessage::MessageType2;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let store = s.create_store(vec![MessageType2("red"), MessageType2("blue")]);
+        for (p, color) in [(1, "blue"), (2, "green"), (3, "")].iter().cloned() {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Get(store, ItemFilter::new(move |item| color == "" || *item == MessageType2(color)));
+                c.record("served", p as f64);
+                assert!(c.take_item(ProcessId(p)).is_some());
+                assert!(c.take_item(ProcessId(p)).is_none());
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        s.create_process(ProcessId(4), Box::new(move || {
+            yield Effect::TimeOut(2.0);
+            yield Effect::Put(store, MessageType2("white"));
+            yield Effect::Put(store, MessageType2("green"));
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(4), label: None}).unwrap();
+        let mut s = s.run(Time(1.0));
+        assert_eq!(s.stuck_processes(), vec![(ProcessId(2), WaitReason::Store(store))]);
+        s = s.run(NoEvents);
+        assert_eq!(s.series("served"), vec![(0.0, 1.0), (0.0, 3.0), (2.0, 2.0)]);
+        assert_eq!(s.store_items(store).collect::<Vec<_>>(), vec![&MessageType2("white")]);
+    }
+
+    #[test]
+    fn store_select() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use ItemFilter;
+        use EndCondition::NoEvents;
+        use self::TestMessage::MessageType2;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let store = s.create_store(Vec::new());
+        for (p, color, patience) in [(1, "green", 3.0), (2, "", 10.0)].iter().cloned() {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                let filter = ItemFilter::new(move |item| color == "" || *item == MessageType2(color));
+                yield Effect::Select(vec![Effect::Get(store, filter), Effect::TimeOut(patience)]);
+                let branch = c.selected_branch(ProcessId(p)).unwrap();
+                c.record("branch", branch as f64);
+                assert_eq!(c.take_item(ProcessId(p)).is_some(), branch == 0);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        s.create_process(ProcessId(3), Box::new(move || {
+            yield Effect::TimeOut(5.0);
+            yield Effect::Put(store, MessageType2("red"));
+            yield Effect::TimeOut(1.0);
+            yield Effect::Put(store, MessageType2("green"));
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(3), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        // process 1 gave up at 3.0 and left the queue of the store, process 2
+        // took the first item and its timeout was cancelled
+        assert_eq!(s.series("branch"), vec![(3.0, 1.0), (5.0, 0.0)]);
+        assert_eq!(s.store_items(store).collect::<Vec<_>>(), vec![&MessageType2("green")]);
+        assert_eq!(s.now(), 6.0);
+    }
+
+    #[test]
+    fn gather() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let c = ctx.clone();
+        s.create_process(ProcessId(0), Box::new(move || {
+            yield Effect::Gather(3);
+            let reports: Vec<_> = c.drain_messages(ProcessId(0)).collect();
+            c.record("reports", reports.len() as f64);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        for p in 1..4 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::TimeOut(p as f64);
+                yield Effect::SendMessage(ProcessId(0), TestMessage::MessageType1, 0.5);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        // started, then resumed only by the report of the last worker
+        assert_eq!(s.series("reports"), vec![(3.5, 3.0)]);
+        // the deliveries that did not resume it are processed events too
+        let processed: Vec<f64> = s.processed_events().iter().filter(|e| e.process == ProcessId(0)).map(|e| e.time).collect();
+        assert_eq!(processed, vec![0.0, 1.5, 2.5, 3.5]);
+    }
+
+    #[test]
+    fn snapshot_message_waiters() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        for p in 0..2 {
+            s.create_process(ProcessId(p), Box::new(|| {
+                yield Effect::WaitForMessage;
+            }));
+        }
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::SendMessage(ProcessId(0), TestMessage::MessageType1, 0.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.step().unwrap();
+        let snapshot = s.snapshot();
+        assert!(s.is_deadlocked());
+
+        // #0 gets a message and #1 starts waiting after the snapshot
+        s.schedule_event(Event{time: 1.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(2), label: None}).unwrap();
+        s.run_mut(NoEvents);
+        assert_eq!(s.message_waiters.iter().cloned().collect::<Vec<_>>(), vec![ProcessId(1)]);
+
+        s.restore(&snapshot);
+        assert_eq!(s.message_waiters.iter().cloned().collect::<Vec<_>>(), vec![ProcessId(0)]);
+    }
+
+    #[test]
+    fn snapshot_gather() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::Gather(2);
+        }));
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::SendMessage(ProcessId(0), TestMessage::MessageType1, 0.0);
+            yield Effect::SendMessage(ProcessId(0), TestMessage::MessageType1, 0.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::Gather(5);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.step().unwrap();
+        let snapshot = s.snapshot();
+
+        // #0 gets its messages and #2 starts gathering after the snapshot
+        s.schedule_event(Event{time: 1.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(2), label: None}).unwrap();
+        s.run_mut(NoEvents);
+        assert_eq!(s.completed_process_count(), 2);
+        assert!(!s.gathers.contains_key(&ProcessId(0)));
+        assert_eq!(s.gathers.get(&ProcessId(2)), Some(&5));
+
+        s.restore(&snapshot);
+        assert_eq!(s.gathers.get(&ProcessId(0)), Some(&2));
+        assert!(!s.gathers.contains_key(&ProcessId(2)));
+    }
+
+    #[test]
+    fn resource_holders() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NSteps;
+
+        for &tracking in [false, true].iter() {
+            let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+            if tracking {
+                s.enable_hold_tracking();
+            }
+            let r = s.create_resource(2, QueueDiscipline::Fifo);
+            for p in 0..3 {
+                s.create_process(ProcessId(p), Box::new(move || {
+                    yield Effect::Request(r);
+                    yield Effect::TimeOut(2.0);
+                    yield Effect::Release(r);
+                }));
+                s.schedule_event(Event{time: p as f64 * 0.5, process: ProcessId(p), label: None}).unwrap();
+            }
+            let mut s = s.run(NSteps(5));
+            assert_eq!(s.resource_holders(r), vec![ProcessId(0), ProcessId(1)]);
+            assert_eq!(s.resource_queue_snapshot(r), vec![ProcessId(2)]);
+            // process 0 releases, process 2 takes over its unit
+            while s.resource_queue_snapshot(r).len() == 1 {
+                s.step().unwrap();
+            }
+            assert_eq!(s.resource_holders(r), vec![ProcessId(1), ProcessId(2)]);
+        }
+    }
+
+    #[test]
+    fn event_rate() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            for _ in 0..9 {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        // the events at 6, 7, 8 and 9
+        assert_eq!(s.event_rate(4.0), 1.0);
+        assert_eq!(s.event_rate(20.0), 0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "the window of the event rate must be positive")]
+    fn event_rate_zero_window() {
+        use Simulation;
+
+        let s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.event_rate(0.0);
+    }
+
+    #[test]
+    fn process_panicked() {
+        use Simulation;
+        use SimulationError;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+            let v: Vec<u32> = Vec::new();
+            if v.is_empty() {
+                panic!("no value at {}", 1.0);
+            }
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::TimeOut(2.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        for _ in 0..2 {
+            s.step().unwrap();
+        }
+        assert_eq!(
+            s.step(),
+            Err(SimulationError::ProcessPanicked { process: ProcessId(1), message: "no value at 1".to_string() })
+        );
+        // the other process goes on
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 2.0);
+        assert_eq!(s.completed_process_count(), 2);
+    }
+
+    #[test]
+    fn collect() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use Action;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(0), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::Collect(vec![
+                Effect::Release(r),
+                Effect::SendMessage(ProcessId(2), TestMessage::MessageType1, 0.0),
+                Effect::Event(Event{time: 1.0, process: ProcessId(3), label: None}),
+                Effect::Now(Action::new(|c| c.record("now", c.message_count(ProcessId(2)) as f64))),
+            ]);
+        }));
+        for p in 1..4 {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                if p == 1 {
+                    yield Effect::Request(r);
+                }
+                c.record("resumed", p as f64);
+            }));
+        }
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        // process 0 is resumed once, after all the effects are applied
+        let resumed: Vec<f64> = s.processed_events().iter().filter(|e| e.process == ProcessId(0)).map(|e| e.time).collect();
+        assert_eq!(resumed, vec![0.0, 0.0, 0.0]);
+        assert_eq!(s.series("resumed"), vec![(0.0, 1.0), (0.0, 2.0), (1.0, 3.0)]);
+        assert_eq!(s.completed_process_count(), 4);
+        // the action ran after the previous effects, before the delivery
+        assert_eq!(s.series("now"), vec![(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn run_mut() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::Time;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.run_mut(Time(5.0));
+        assert_eq!(s.now(), 5.0);
+        assert_eq!(s.processed_events().len(), 6);
+        s.run_mut(Time(10.0));
+        assert_eq!(s.now(), 10.0);
+        assert_eq!(s.processed_events().len(), 11);
+    }
+
+    #[test]
+    fn now() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use Action;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(2.0);
+            yield Effect::Now(Action::new(|ctx| ctx.record("metric", ctx.time() * 10.0)));
+            yield Effect::Now(Action::new(|ctx| ctx.record("resumed", ctx.time())));
+            yield Effect::TimeOut(1.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("metric"), vec![(2.0, 20.0)]);
+        assert_eq!(s.series("resumed"), vec![(2.0, 2.0)]);
+        assert_eq!(s.now(), 3.0);
+        assert_eq!(s.completed_process_count(), 1);
+    }
+
+    #[test]
+    fn it_works() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(move || {
+            let mut a = 0.0;
+            loop {
+                a += 1.0;
+                println!("time {}", ctx.time());
+                
+                yield Effect::TimeOut(a);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.step().unwrap();
+        s.step().unwrap();
+        assert_eq!(s.now(), 1.0);
+        s.step().unwrap();
+        assert_eq!(s.now(), 3.0);
+        s.step().unwrap();
+        assert_eq!(s.now(), 6.0);
+    }
+
+    #[test]
+    fn run() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1),  Box::new(|| {
+            let tik = 0.7;
+            loop{
+                println!("tik");
+                yield Effect::TimeOut(tik);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(EndCondition::Time(10.0));
+        println!("{}", s.now());
+        assert!(s.now() >= 10.0);
+    }
+
+    #[test]
+    fn events_between() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(move || {
+            for _ in 0..10 {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        let times: Vec<f64> = s.events_between(2.5, 5.0).map(|e| e.time).collect();
+        assert_eq!(times, vec![3.0, 4.0]);
+        assert_eq!(s.events_between(0.0, 100.0).count(), 11);
+        assert_eq!(s.events_between(5.0, 2.0).count(), 0);
+    }
+
+    #[test]
+    fn labeled_events() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(move || {
+            loop {
+                yield Effect::Wait;
+            }
+        }));
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Event(Event{time: 1.5, process: ProcessId(0), label: Some("departure")});
+        }));
+        s.schedule_event(Event{time: 1.0, process: ProcessId(0), label: Some("arrival")}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(0), label: Some("arrival")}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        let arrivals: Vec<f64> = s.events_with_label("arrival").map(|e| e.time).collect();
+        assert_eq!(arrivals, vec![1.0, 2.0]);
+        let departures: Vec<f64> = s.events_with_label("departure").map(|e| e.time).collect();
+        assert_eq!(departures, vec![1.5]);
+        assert_eq!(s.processed_events().len(), 4);
+    }
+
+    #[test]
+    fn run_out_of_events() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::{Time, NSteps};
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(5.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        // stops when the events run out, before the ending condition is met
+        let mut s = s.run(Time(100.0));
+        assert_eq!(s.now(), 5.0);
+        assert_eq!(s.step().unwrap(), None);
+        let s = s.run(NSteps(3));
+        assert_eq!(s.processed_events().len(), 2);
+    }
+
+    #[test]
+    fn process_done() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::ProcessDone;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(2.5);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(ProcessDone(ProcessId(1)));
+        assert_eq!(s.now(), 2.5);
+        assert_eq!(s.live_process_count(), 1);
+    }
+
+    #[test]
+    fn custom_end_condition() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::Custom;
+        use QueueDiscipline;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        for i in 0..5 {
+            s.create_process(ProcessId(i), Box::new(move || {
+                yield Effect::Request(r);
+                yield Effect::TimeOut(1.0);
+                yield Effect::Release(r);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(i), label: None}).unwrap();
+        }
+        // stop as soon as 3 processes are waiting for the resource
+        let s = s.run(Custom(Box::new(move |s| s.resource_state(r).queue_len >= 3)));
+        assert_eq!(s.now(), 0.0);
+        assert_eq!(s.resource_state(r).queue_len, 3);
+
+        // or when process 3 has been resumed twice
+        let s = s.run(Custom(Box::new(|s| {
+            s.processed_events().iter().filter(|e| e.process == ProcessId(3)).count() >= 2
+        })));
+        assert_eq!(s.now(), 3.0);
+    }
+
+    #[test]
+    fn monitor() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::Time;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(0.75);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let m = s.add_monitor("processed", 1.0, Box::new(|s| {
+            s.processed_events().len() as f64
+        }));
+        let s = s.run(Time(3.0));
+        assert_eq!(s.monitor_name(m), "processed");
+        // events at 0.0, 0.75, 1.5, 2.25, 3.0
+        assert_eq!(s.monitor_data(m), &[(0.0, 0.0), (1.0, 2.0), (2.0, 3.0), (3.0, 4.0)]);
+    }
+
+    #[test]
+    fn process_counts() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        for p in 1..4 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::TimeOut(p as f64);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        assert_eq!((s.live_process_count(), s.completed_process_count()), (3, 0));
+        for _ in 0..3 {
+            s.step().unwrap();
+        }
+        assert_eq!((s.live_process_count(), s.completed_process_count()), (3, 0));
+        s.step().unwrap();
+        assert_eq!((s.live_process_count(), s.completed_process_count()), (2, 1));
+        s.step().unwrap();
+        s.step().unwrap();
+        assert_eq!((s.live_process_count(), s.completed_process_count()), (0, 3));
+    }
+
+    #[test]
+    fn series() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let c = ctx.clone();
+        s.create_process(ProcessId(1), Box::new(move || {
+            for i in 0..5 {
+                yield Effect::TimeOut(1.0);
+                c.record("latency", i as f64);
+                if i % 2 == 0 {
+                    c.record("even", 1.0);
+                }
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("latency"), vec![(1.0, 0.0), (2.0, 1.0), (3.0, 2.0), (4.0, 3.0), (5.0, 4.0)]);
+        assert_eq!(s.series("even").len(), 3);
+        assert!(s.series("missing").is_empty());
+        assert_eq!(s.histogram("latency", 2), vec![(0.0, 2), (2.0, 3)]);
+        assert_eq!(s.histogram("even", 3), vec![(1.0, 3), (1.0, 0), (1.0, 0)]);
+    }
+
+    #[test]
+    fn builder_options() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let (mut s, ctx) = Simulation::<TestMessage>::builder()
+            .with_event_capacity(100)
+            .with_process_capacity(10)
+            .with_warmup(10.0)
+            .with_wall_clock_tracking()
+            .with_hold_tracking()
+            .build();
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        for p in 0..2 {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                loop {
+                    yield Effect::Request(r);
+                    c.record("served", 1.0);
+                    yield Effect::TimeOut(4.0);
+                    yield Effect::Release(r);
+                    if c.time() > 20.0 {
+                        break;
+                    }
+                }
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        assert!(!s.wall_time_per_step().is_empty());
+        assert!(s.process_busy_time(ProcessId(0)) > 0.0);
+        // the two processes alternate, each waiting 4.0 for the resource:
+        // only the requests granted from time 10.0 on are counted
+        let stats = s.resource_wait_stats(r);
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.max, 4.0);
+        let served = s.series("served");
+        assert!(!served.is_empty());
+        assert!(served.iter().all(|&(t, _)| t >= 10.0));
+    }
+
+    #[test]
+    fn unique_scheduling() {
+        use Simulation;
+        use SimulationError;
+        use Effect;
+        use Event;
+
+        let (mut s, _) = Simulation::<TestMessage>::builder()
+            .with_unique_scheduling()
+            .build();
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::Event(Event{time: 0.5, process: ProcessId(1), label: None});
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        assert_eq!(
+            s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}),
+            Err(SimulationError::DuplicateScheduling { process: ProcessId(1), time: 0.0 })
+        );
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        // process 1 waits for the end of its timeout
+        s.step().unwrap();
+        assert_eq!(
+            s.step(),
+            Err(SimulationError::DuplicateScheduling { process: ProcessId(1), time: 0.5 })
+        );
+        assert_eq!(s.future_events().count(), 1);
+    }
+
+    #[test]
+    fn strict_wakeups() {
+        use Simulation;
+        use SimulationError;
+        use Effect;
+        use Event;
+
+        let (mut s, ctx) = Simulation::<TestMessage>::builder()
+            .with_strict_wakeups()
+            .build();
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::Wait;
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::Event(Event{time: 2.0, process: ProcessId(1), label: None});
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.step().unwrap();
+        assert!(ctx.is_waiting(ProcessId(1)));
+        // the owner wakes up the process, then process 2 does it again
+        s.schedule_event(Event{time: 1.0, process: ProcessId(1), label: None}).unwrap();
+        assert!(!ctx.is_waiting(ProcessId(1)));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        assert_eq!(
+            s.step(),
+            Err(SimulationError::DuplicateScheduling { process: ProcessId(1), time: 2.0 })
+        );
+        // the second wake-up was not scheduled
+        assert_eq!(s.step().unwrap().map(|e| e.process), Some(ProcessId(1)));
+        assert_eq!(s.future_events().count(), 0);
+    }
+
+    #[test]
+    fn timeout_throttled() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(0), Box::new(move || {
+            for _ in 0..3 {
+                yield Effect::TimeOutThrottled(0.5, 2.0);
+                ctx.record("fired", 1.0);
+            }
+            // longer delays than the spacing are not affected
+            yield Effect::TimeOutThrottled(3.0, 2.0);
+            ctx.record("fired", 1.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        let times: Vec<f64> = s.series("fired").iter().map(|&(t, _)| t).collect();
+        assert_eq!(times, vec![0.5, 2.5, 4.5, 7.5]);
+    }
+
+    #[test]
+    fn yield_to() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let c = ctx.clone();
+        s.create_process(ProcessId(1), Box::new(move || {
+            for i in 0..2 {
+                c.record("ping", i as f64);
+                yield Effect::YieldTo(ProcessId(2));
+            }
+        }));
+        let c = ctx.clone();
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Wait;
+            for i in 0..2 {
+                c.record("pong", i as f64);
+                yield Effect::Wait;
+            }
+        }));
+        s.create_process(ProcessId(3), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        for p in &[2, 1, 3] {
+            s.schedule_event(Event{time: 0.0, process: ProcessId(*p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        let order: Vec<_> = s.processed_events().iter().map(|e| e.process.0).collect();
+        // process 3 was already queued, then 2 runs before 1 each time
+        assert_eq!(order, vec![2, 1, 3, 2, 1, 2, 1, 3]);
+        assert_eq!(s.series("pong"), vec![(0.0, 0.0), (0.0, 1.0)]);
+    }
+
+    #[test]
+    fn completion_time() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        for p in 0..2 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::TimeOut(2.5 * (p + 1) as f64);
+            }));
+        }
+        s.schedule_event(Event{time: 1.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.completion_time(ProcessId(0)), Some(3.5));
+        // never started
+        assert_eq!(s.completion_time(ProcessId(1)), None);
+    }
+
+    #[test]
+    fn wake_process() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use WakeError;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+            yield Effect::Suspend;
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        assert_eq!(s.wake_process(ProcessId(0)), Err(WakeError::NotSuspended(ProcessId(0))));
+        assert_eq!(s.wake_process(ProcessId(1)), Err(WakeError::UnknownProcess(ProcessId(1))));
+        let mut s = s.run(NoEvents);
+        assert_eq!(s.now(), 1.0);
+        s.wake_process(ProcessId(0)).unwrap();
+        // already scheduled
+        assert_eq!(s.wake_process(ProcessId(0)), Err(WakeError::NotSuspended(ProcessId(0))));
+        let mut s = s.run(NoEvents);
+        assert_eq!(s.completion_time(ProcessId(0)), Some(1.0));
+        assert_eq!(s.wake_process(ProcessId(0)), Err(WakeError::Completed(ProcessId(0))));
+    }
+
+    #[test]
+    fn absolute_event() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        // at time 2.0, one event at time 3.0 and one 3.0 time units later
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(2.0);
+            yield Effect::AbsoluteEvent(Event{time: 3.0, process: ProcessId(1), label: None});
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::TimeOut(2.0);
+            yield Effect::Event(Event{time: 3.0, process: ProcessId(1), label: None});
+        }));
+        s.create_process(ProcessId(1), Box::new(|| {
+            loop {
+                yield Effect::Wait;
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        let times: Vec<f64> = s.processed_events().iter().filter(|e| e.process == ProcessId(1)).map(|e| e.time).collect();
+        assert_eq!(times, vec![3.0, 5.0]);
+    }
+
+    #[test]
+    fn schedule_events() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        for p in 0..2 {
+            s.create_process(ProcessId(p), Box::new(|| {
+                loop {
+                    yield Effect::Wait;
+                }
+            }));
+        }
+        // scrambled times, 0 to 998 for process 0
+        let times: Vec<f64> = (0..999).map(|i| ((i * 7) % 999) as f64).collect();
+        assert_eq!(s.schedule_arrivals(&times, ProcessId(0)).unwrap().len(), 999);
+        let ids = s.schedule_events(vec![Event{time: 500.5, process: ProcessId(1), label: None}]).unwrap();
+        assert_eq!(ids.len(), 1);
+        let s = s.run(NoEvents);
+        let log = s.processed_events();
+        assert_eq!(log.len(), 1000);
+        assert!(log.windows(2).all(|w| w[0].time <= w[1].time));
+        assert_eq!(log[501].process, ProcessId(1));
+    }
+
+    #[test]
+    fn token_bucket() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::Time;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let bucket = s.create_token_bucket(2.0, 3.0);
+        // 7 requests at time 0 and 4 at time 10.25, when the bucket is full again
+        for p in 1..12 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Reserve(bucket, 1.0);
+            }));
+            let time = if p <= 7 { 0.0 } else { 10.25 };
+            s.schedule_event(Event{time, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(Time(12.0));
+        let served: Vec<f64> = (1..12).map(|p| s.completion_time(ProcessId(p)).unwrap()).collect();
+        assert_eq!(served, vec![0.0, 0.0, 0.0, 0.5, 1.0, 1.5, 2.0, 10.25, 10.25, 10.25, 10.5]);
+    }
+
+    #[test]
+    fn fork_process_at() {
+        use Simulation;
+        use DuplicatePidError;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.step().unwrap();
+        // arrivals injected between the steps
+        for &(p, t) in [(1, 0.5), (2, 3.0)].iter() {
+            s.fork_process_at(ProcessId(p), Box::new(|| {
+                yield Effect::TimeOut(1.0);
+            }), t).unwrap();
+        }
+        let r = s.fork_process_at(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }), 2.0);
+        assert_eq!(r, Err(DuplicatePidError(ProcessId(0))));
+        let s = s.run(NoEvents);
+        let times: Vec<f64> = s.processed_events().iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![0.0, 0.5, 1.0, 1.5, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn with_context() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let (mut s, ctx) = Simulation::<TestMessage>::with_context();
+        s.create_process(ProcessId(0), Box::new(move || {
+            yield Effect::TimeOut(2.0);
+            assert_eq!(ctx.time(), 2.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.completed_process_count(), 1);
+    }
+
+    #[test]
+    fn future_events() {
+        use Simulation;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.schedule_event(Event{time: 3.0, process: ProcessId(0), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 3.0, process: ProcessId(2), label: None}).unwrap();
+        assert_eq!(s.future_events().count(), 3);
+        let sorted: Vec<_> = s.future_events_sorted().iter().map(|e| (e.time, e.process.0)).collect();
+        assert_eq!(sorted, vec![(1.0, 1), (3.0, 0), (3.0, 2)]);
+    }
+
+    #[test]
+    fn future_events_order() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        for p in 0..4 {
+            s.create_process(ProcessId(p), Box::new(|| {
+                yield Effect::Wait;
+            }));
+            s.schedule_event(Event{time: (p % 2) as f64, process: ProcessId(p), label: None}).unwrap();
+        }
+        let expected: Vec<_> = s.future_events_sorted().iter().map(|e| (e.time, e.process)).collect();
+        let mut processed = Vec::new();
+        while let Some(e) = s.step().unwrap() {
+            processed.push((e.time, e.process));
+        }
+        assert_eq!(processed, expected);
+        assert!(s.future_events_sorted().is_empty());
+    }
+
+    #[test]
+    fn mean_number_in_system() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        // entities in the system during [0, 4) and [2, 6)
+        for (p, stay) in vec![(0, 4.0), (1, 4.0)] {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                c.entity_enter();
+                yield Effect::TimeOut(stay);
+                c.entity_exit();
+            }));
+        }
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::TimeOut(8.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        assert_eq!(s.mean_number_in_system(), 0.0);
+        let s = s.run(NoEvents);
+        // 1 * 2 + 2 * 2 + 1 * 2 over 8 time units
+        assert_eq!(s.now(), 8.0);
+        assert_eq!(s.mean_number_in_system(), 1.0);
+    }
+
+    #[test]
+    fn peek_next_event() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(10.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        assert_eq!(s.future_event_count(), 0);
+        assert!(s.peek_next_event().is_none());
+        s.schedule_event(Event{time: 2.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(2), label: None}).unwrap();
+        assert_eq!(s.future_event_count(), 2);
+        assert_eq!(s.peek_next_event().unwrap().process, ProcessId(2));
+        s.step().unwrap();
+        s.step().unwrap();
+        assert_eq!(s.future_event_count(), 2);
+        let next = s.peek_next_event().unwrap();
+        assert_eq!((next.time, next.process.0), (2.0, 2));
+        // peeking does not consume the event
+        assert_eq!(s.future_event_count(), 2);
+    }
+
+    #[test]
+    fn step_n() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            for _ in 0..5 {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        assert_eq!(s.step_n(2).unwrap(), 2);
+        assert_eq!(s.now(), 1.0);
+        while s.step().unwrap().is_some() {}
+        assert_eq!(s.now(), 5.0);
+        assert_eq!(s.completed_process_count(), 1);
+        assert_eq!(s.step_n(3).unwrap(), 0);
+    }
+
+    #[test]
+    fn timestamp_monitor() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+        use std::cell::RefCell;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        for i in 0..3 {
+            let c = ctx.clone();
+            s.create_process(ProcessId(i), Box::new(move || {
+                c.record("done", i as f64);
+                yield Effect::TimeOut(1.0);
+            }));
+            s.schedule_event(Event{time: 1.0, process: ProcessId(i), label: None}).unwrap();
+        }
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let c = calls.clone();
+        s.set_timestamp_monitor(Box::new(move |t, ctx| {
+            c.borrow_mut().push((t, ctx.series.borrow()["done"].len()));
+        }));
+        let s = s.run(NoEvents);
+        // once per time, after all the events at that time
+        assert_eq!(*calls.borrow(), vec![(1.0, 3), (2.0, 3)]);
+        assert_eq!(s.now(), 2.0);
+    }
+
+    #[test]
+    fn timestamp_monitor_end_time() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::{NoEvents, Time};
+        use std::cell::RefCell;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        for i in 0..3 {
+            s.create_process(ProcessId(i), Box::new(move || {
+                yield Effect::TimeOut(i as f64 + 1.0);
+            }));
+            s.schedule_event(Event{time: 1.0, process: ProcessId(i), label: None}).unwrap();
+        }
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let c = calls.clone();
+        s.set_timestamp_monitor(Box::new(move |t, _| c.borrow_mut().push(t)));
+        // the run stops at 2.0, with no other event due then
+        let s = s.run(Time(2.0));
+        assert_eq!(*calls.borrow(), vec![1.0, 2.0]);
+        let s = s.run(NoEvents);
+        assert_eq!(*calls.borrow(), vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(s.now(), 4.0);
+    }
+
+    #[test]
+    fn settle() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        // each process wakes the next one with no delay
+        for p in 1..4 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Event(Event{time: 0.0, process: ProcessId(p + 1), label: None});
+            }));
+        }
+        s.create_process(ProcessId(4), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        s.create_process(ProcessId(5), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        s.schedule_event(Event{time: 2.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 3.0, process: ProcessId(5), label: None}).unwrap();
+        s.step().unwrap();
+        s.settle().unwrap();
+        assert_eq!(s.now(), 2.0);
+        let processes: Vec<_> = s.processed_events().iter().map(|e| e.process.0).collect();
+        assert_eq!(processes, vec![1, 2, 3, 4]);
+        // the timeout of process 4 and the event of process 5 are left
+        assert_eq!(s.future_event_count(), 2);
+        // nothing left to do at this time
+        s.settle().unwrap();
+        assert_eq!(s.processed_events().len(), 4);
+    }
+
+    #[test]
+    fn cancel_event() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            loop {
+                yield Effect::Wait;
+            }
+        }));
+        let first = s.schedule_event(Event{time: 1.0, process: ProcessId(1), label: None}).unwrap();
+        let second = s.schedule_event(Event{time: 2.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 3.0, process: ProcessId(1), label: None}).unwrap();
+        assert_eq!(s.cancel_event(second).unwrap().time, 2.0);
+        assert!(s.cancel_event(second).is_none());
+        assert_eq!(s.future_event_count(), 2);
+        let mut s = s.run(NoEvents);
+        let times: Vec<f64> = s.processed_events().iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![1.0, 3.0]);
+        // already processed
+        assert!(s.cancel_event(first).is_none());
+    }
+
+    #[test]
+    fn event_queue() {
+        use EventQueue;
+        use Event;
+
+        let mut q = EventQueue::new();
+        let times = [5.0, 1.0, 4.0, 1.0, 3.0, 9.0, 2.0, 6.0];
+        let ids: Vec<_> = times.iter().enumerate()
+            .map(|(p, &time)| q.push(Event{time, process: ProcessId(p), label: None}))
+            .collect();
+        q.remove(ids[4]);
+        q.remove(ids[0]);
+        assert!(q.remove(ids[0]).is_none());
+        let mut popped = Vec::new();
+        while let Some((_, e)) = q.pop() {
+            popped.push((e.time, e.process.0));
+        }
+        // same time events come out in the order they were pushed
+        assert_eq!(popped, vec![(1.0, 1), (1.0, 3), (2.0, 6), (4.0, 2), (6.0, 7), (9.0, 5)]);
+    }
+
+    #[test]
+    fn round_robin() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NSteps;
+        use SchedulingPolicy;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        for p in 0..3 {
+            s.create_process(ProcessId(p), Box::new(|| {
+                loop {
+                    yield Effect::TimeOut(0.0);
+                }
+            }));
+        }
+        // process 0 starts with a burst of events at the same time
+        for _ in 0..10 {
+            s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        }
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        s.set_scheduling_policy(SchedulingPolicy::RoundRobin);
+        let s = s.run(NSteps(30));
+        let mut counts = [0; 3];
+        for e in s.processed_events() {
+            counts[e.process.0] += 1;
+        }
+        assert_eq!(counts, [10, 10, 10]);
+        assert!(s.processed_events()[..3].iter().any(|e| e.process == ProcessId(2)));
+    }
+
+    #[test]
+    fn signal() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+        use WaitReason;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let go = ctx.create_signal("go");
+        assert_eq!(ctx.signal_name(go), "go");
+        let mut s = Simulation::new(ctx.clone());
+        for p in 0..3 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::TimeOut(p as f64);
+                yield Effect::WaitForSignal(go);
+                yield Effect::TimeOut(1.0);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let c = ctx.clone();
+        s.create_process(ProcessId(3), Box::new(move || {
+            yield Effect::TimeOut(5.0);
+            c.fire_signal(go);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(3), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        // all the waiters were resumed together
+        assert_eq!(s.now(), 6.0);
+        for p in 0..3 {
+            assert_eq!(s.processed_events().iter().filter(|e| e.process == ProcessId(p) && e.time == 5.0).count(), 1);
+        }
+
+        // the signal is not remembered once delivered
+        let mut s = s;
+        s.create_process(ProcessId(4), Box::new(move || {
+            yield Effect::WaitForSignal(go);
+        }));
+        s.schedule_event(Event{time: 6.0, process: ProcessId(4), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.stuck_processes(), vec![(ProcessId(4), WaitReason::Signal(go))]);
+        // the owner can fire it between steps
+        ctx.fire_signal(go);
+        let s = s.run(NoEvents);
+        assert!(!s.is_deadlocked());
+    }
+
+    #[test]
+    fn format_time() {
+        use Simulation;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        ctx.time.set(0.7 * 14.0);
+        assert_eq!(ctx.format_time(2), "9.80");
+        assert_eq!(ctx.format_time(0), "10");
+        let mut s = Simulation::new(ctx);
+        assert_eq!(s.time_unit(), None);
+        s.set_time_unit("ms");
+        assert_eq!(s.time_unit(), Some("ms"));
+    }
+
+    #[test]
+    fn advance_time() {
+        use TimeError;
+
+        let ctx = Context::<TestMessage>::new();
+        assert_eq!(ctx.advance_time(2.0), Ok(()));
+        assert_eq!(ctx.advance_time(2.0), Ok(()));
+        assert_eq!(ctx.advance_time(1.0), Err(TimeError::TimeTravelAttempted { from: 2.0, to: 1.0 }));
+        assert!(ctx.advance_time(std::f64::NAN).is_err());
+        assert_eq!(ctx.time(), 2.0);
+    }
+
+    #[test]
+    fn advance_to() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use SimulationError;
+        use TimeError;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(2.0);
+            }
+        }));
+        s.schedule_event(Event{time: 1.0, process: ProcessId(1), label: None}).unwrap();
+        s.advance_to(5.0).unwrap();
+        let times: Vec<f64> = s.processed_events().iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![1.0, 3.0, 5.0]);
+        assert_eq!(s.now(), 5.0);
+        // no event at 6.5: the clock moves anyway
+        s.advance_to(6.5).unwrap();
+        assert_eq!(s.processed_events().len(), 3);
+        assert_eq!(s.now(), 6.5);
+        assert_eq!(s.peek_next_event().unwrap().time, 7.0);
+        assert_eq!(
+            s.advance_to(2.0),
+            Err(SimulationError::Time(TimeError::TimeTravelAttempted { from: 6.5, to: 2.0 }))
+        );
+    }
+
+    #[test]
+    fn integer_time() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage, u64>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(3);
+            }
+        }));
+        s.schedule_event(Event{time: 1, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(EndCondition::Time(10));
+        assert_eq!(s.now(), 10);
+        assert_eq!(s.processed_events().len(), 4);
+    }
+
+    #[test]
+    fn time_resolution() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        for &resolution in &[None, Some(0.1)] {
+            let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+            if let Some(resolution) = resolution {
+                s.set_time_resolution(resolution);
+            }
+            s.create_process(ProcessId(0), Box::new(|| {
+                for _ in 0..10 {
+                    yield Effect::TimeOut(0.1);
+                }
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+            let s = s.run(NoEvents);
+            if resolution.is_some() {
+                assert_eq!(s.now(), 1.0);
+            } else {
+                assert!(s.now() != 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn delay() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        for p in 0..2 {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                for _ in 0..3 {
+                    c.record("order", p as f64);
+                    yield Effect::Delay;
+                }
+                c.record("order", p as f64);
+            }));
+            s.schedule_event(Event{time: 1.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 1.0);
+        let order: Vec<f64> = s.series("order").iter().map(|&(_, p)| p).collect();
+        // the two processes take turns
+        assert_eq!(order, vec![0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn sleep_until() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let ctx1 = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::TimeOut(10.0);
+            yield Effect::SleepUntil(50.0);
+            assert_eq!(ctx1.time(), 50.0);
+            // in the past: resumed immediately
+            yield Effect::SleepUntil(10.0);
+            assert_eq!(ctx1.time(), 50.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 50.0);
+        assert_eq!(s.processed_events().len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "negative TimeOut")]
+    fn negative_timeout() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(-1.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.step().unwrap();
+    }
+
+    #[test]
+    fn nan_timeout() {
+        use Simulation;
+        use SimulationError;
+        use Effect;
+        use Event;
+        use std::f64::NAN;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+            yield Effect::TimeOut(NAN);
+        }));
+        assert_eq!(
+            s.schedule_event(Event{time: NAN, process: ProcessId(1), label: None}),
+            Err(SimulationError::NaNEventTime { process: ProcessId(1), scheduled_at: 0.0 })
+        );
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        assert_eq!(s.step().unwrap().unwrap().time, 0.0);
+        assert_eq!(s.step(), Err(SimulationError::NaNEventTime { process: ProcessId(1), scheduled_at: 1.0 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "NaN time")]
+    fn nan_timeout_run() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+        use std::f64::NAN;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(NAN);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.run(NoEvents);
+    }
+
+    #[test]
+    fn infinite_timeout() {
+        use Simulation;
+        use SimulationError;
+        use Effect;
+        use Event;
+        use std::f64::INFINITY;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(INFINITY);
+        }));
+        assert_eq!(
+            s.schedule_event(Event{time: INFINITY, process: ProcessId(1), label: None}),
+            Err(SimulationError::InfiniteEventTime { process: ProcessId(1), scheduled_at: 0.0 })
+        );
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        assert_eq!(s.step(), Err(SimulationError::InfiniteEventTime { process: ProcessId(1), scheduled_at: 0.0 }));
+    }
+
+    #[test]
+    fn nan_policy() {
+        use Simulation;
+        use NanPolicy;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+        use std::f64::{INFINITY, NAN};
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.set_nan_policy(NanPolicy::Skip);
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::Event(Event{time: NAN, process: ProcessId(2), label: None});
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::TimeOut(INFINITY);
+        }));
+        let id = s.schedule_event(Event{time: NAN, process: ProcessId(1), label: None}).unwrap();
+        assert_eq!(s.cancel_event(id), None);
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.5, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        // the events at NaN and infinite times are dropped
+        assert_eq!(s.processed_events().len(), 2);
+        assert_eq!(s.context().time(), 0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "infinite time")]
+    fn nan_policy_panic() {
+        use Simulation;
+        use NanPolicy;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.set_nan_policy(NanPolicy::Panic);
+        let _ = s.schedule_event(Event{time: ::std::f64::INFINITY, process: ProcessId(1), label: None});
+    }
+
+    #[test]
+    fn resource() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
 
         // simple process that lock the resource for 7 time units
-        s.create_process(1, Box::new(move || {
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(7.0);
+            yield Effect::Release(r);
+        }));
+        // simple process that holds the resource for 3 time units
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(3.0);
+            yield Effect::Release(r);
+        }));
+
+        // let p1 start immediately...
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        // let p2 start after 2 t.u., when r is not available
+        s.schedule_event(Event{time: 2.0, process: ProcessId(2), label: None}).unwrap();
+        // p2 will wait r to be free (time 7.0) and its timeout
+        // of 3.0 t.u. The simulation will end at time 10.0
+        
+        let s = s.run(NoEvents);
+        s.print_trace();
+        assert_eq!(s.now(), 10.0);
+        // p2 waited from 2.0 to 7.0
+        let stats = s.resource_wait_stats(r);
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.max, 5.0);
+        assert_eq!(stats.mean, 2.5);
+        assert_eq!(stats.variance, 6.25);
+        assert_eq!(stats.mean_wait(), 2.5);
+        assert_eq!(stats.max_wait(), 5.0);
+        assert_eq!(s.wait_stats(), stats);
+    }
+
+    #[test]
+    fn resource_hold() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+
+        // the same processes as in `resource`, with a single effect each
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Hold(r, 7.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Hold(r, 3.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(2), label: None}).unwrap();
+
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 10.0);
+        assert_eq!(s.completion_time(ProcessId(1)), Some(7.0));
+        assert_eq!(s.completion_time(ProcessId(2)), Some(10.0));
+        let stats = s.resource_wait_stats(r);
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.max, 5.0);
+        assert_eq!(s.resource_state(r).available, 1);
+    }
+
+    #[test]
+    fn resource_hold_message() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Hold(r, 7.0);
+        }));
+        let c = ctx.clone();
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Hold(r, 3.0);
+            c.record("inbox", c.message_count(ProcessId(2)) as f64);
+        }));
+        // the message reaches process 2 while it waits for the resource
+        s.create_process(ProcessId(3), Box::new(move || {
+            yield Effect::SendMessage(ProcessId(2), TestMessage::MessageType1, 1.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(2), label: None}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(3), label: None}).unwrap();
+        s.enable_per_process_log();
+
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("inbox"), vec![(10.0, 1.0)]);
+        // the delivery, the grant and the release are logged, although they
+        // do not resume the process
+        assert_eq!(s.process_event_times(ProcessId(2)), Some(&[2.0, 3.0, 7.0, 10.0, 10.0][..]));
+        assert_eq!(s.completion_time(ProcessId(2)), Some(10.0));
+        assert_eq!(s.resource_state(r).available, 1);
+    }
+
+    #[test]
+    fn context_resource_state() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        for p in 0..3 {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                // admission control: balk if the resource is busy
+                if c.resource_available(r) == 0 {
+                    c.record("balked", c.resource_queue_len(r) as f64);
+                    return;
+                }
+                yield Effect::Request(r);
+                yield Effect::TimeOut(5.0);
+                yield Effect::Release(r);
+            }));
+            s.schedule_event(Event{time: p as f64, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("balked"), vec![(1.0, 0.0), (2.0, 0.0)]);
+        assert_eq!(s.now(), 5.0);
+    }
+
+    #[test]
+    fn resource_state() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use ResourceState;
+        use EndCondition::{Time, NoEvents};
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        assert_eq!(s.resource_count(), 1);
+        assert_eq!(s.resource_ids().collect::<Vec<_>>(), vec![r]);
+        for p in 1..3 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Request(r);
+                yield Effect::TimeOut(5.0);
+                yield Effect::Release(r);
+            }));
+        }
+        let mut pids: Vec<_> = s.process_ids().map(|p| p.0).collect();
+        pids.sort();
+        assert_eq!(pids, vec![1, 2]);
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(2), label: None}).unwrap();
+
+        // p1 holds r, p2 is waiting for it
+        let s = s.run(Time(2.0));
+        let state = ResourceState{allocated: 1, available: 0, in_use: 1, queue_len: 1};
+        assert_eq!(s.resource_state(r), state);
+        assert_eq!(s.resource_queue_snapshot(r), vec![ProcessId(2)]);
+
+        let s = s.run(NoEvents);
+        let state = ResourceState{allocated: 1, available: 1, in_use: 0, queue_len: 0};
+        assert_eq!(s.resource_state(r), state);
+        assert!(s.resource_queue_snapshot(r).is_empty());
+    }
+
+    #[test]
+    fn retry_request() {
+        use std::pin::Pin;
+        use std::ops::{Generator, GeneratorState};
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+        use retry_request;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(0), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(3.0);
+            yield Effect::Release(r);
+        }));
+        for (p, max_retries) in vec![(1, 2), (2, 1)] {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                let mut retry = retry_request(c.clone(), ProcessId(p), r, 1.0, 2.0, max_retries);
+                let acquired = loop {
+                    match Pin::new(&mut retry).resume() {
+                        GeneratorState::Yielded(e) => yield e,
+                        GeneratorState::Complete(acquired) => break acquired,
+                    }
+                };
+                c.record("acquired", if acquired { 1.0 } else { 0.0 });
+                if acquired {
+                    yield Effect::Release(r);
+                }
+            }));
+            s.schedule_event(Event{time: 1.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        // both fail at 1.0 and 2.0, then #2 gives up while #1 retries at 4.0
+        assert_eq!(s.series("acquired"), vec![(2.0, 0.0), (4.0, 1.0)]);
+    }
+
+    #[test]
+    fn preempt_request() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let c = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Priority);
+        s.enable_hold_tracking();
+        // low priority job, that needs 5 time units of work on r
+        s.create_process(ProcessId(1), Box::new(move || {
+            let mut work = 5.0;
+            while work > 0.0 {
+                yield Effect::PreemptRequest(r, 10);
+                let start = c.time();
+                yield Effect::TimeOut(work);
+                work -= c.time() - start;
+                if c.check_interrupted(ProcessId(1)).is_some() {
+                    c.record("evicted", c.time());
+                }
+                yield Effect::Release(r);
+            }
+        }));
+        // high priority job, arriving at 2
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::PreemptRequest(r, 1);
+            yield Effect::TimeOut(3.0);
+            yield Effect::Release(r);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        // #1 is evicted at 2, #2 uses r until 5, then #1 works for 3 more
+        assert_eq!(s.series("evicted"), vec![(2.0, 2.0)]);
+        assert_eq!(s.now(), 8.0);
+        assert_eq!(s.completed_process_count(), 2);
+        assert_eq!(s.resource_state(r).available, 1);
+    }
+
+    #[test]
+    fn preempt_rerequest() {
+        use Simulation;
+        use SimulationError;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Priority);
+        s.enable_hold_tracking();
+        // evicted at 2, requests the resource again without releasing it
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::PreemptRequest(r, 10);
+            yield Effect::TimeOut(5.0);
+            yield Effect::PreemptRequest(r, 10);
+            yield Effect::TimeOut(1.0);
+            yield Effect::Release(r);
+            // one release too many
+            yield Effect::Release(r);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::PreemptRequest(r, 1);
+            yield Effect::TimeOut(3.0);
+            yield Effect::Release(r);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(2), label: None}).unwrap();
+        let err = loop {
+            match s.step() {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("the second release was accepted"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(err, SimulationError::ReleaseWithoutHold { process: ProcessId(1), resource: r });
+        assert_eq!(s.now(), 6.0);
+        assert!(s.resources[r.0].preempted.is_empty());
+        assert_eq!(s.resource_state(r).available, 1);
+    }
+
+    #[test]
+    fn preemption_elapsed() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let c = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Priority);
+        // low priority job, that needs 5 time units of work on r
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::PreemptRequest(r, 10);
+            yield Effect::TimeOut(5.0);
+            if c.check_interrupted(ProcessId(1)).is_some() {
+                let elapsed = c.preemption_elapsed(ProcessId(1)).unwrap();
+                c.record("elapsed", elapsed);
+                yield Effect::Release(r);
+                yield Effect::PreemptRequest(r, 10);
+                yield Effect::TimeOut(5.0 - elapsed);
+            }
+            yield Effect::Release(r);
+            c.record("done", c.time());
+        }));
+        // high priority job, arriving at 3
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::PreemptRequest(r, 1);
+            yield Effect::TimeOut(3.0);
+            yield Effect::Release(r);
+        }));
+        assert_eq!(ctx.preemption_elapsed(ProcessId(1)), None);
+        s.schedule_event(Event{time: 1.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 3.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        // #1 works from 1 to 3, #2 until 6, then #1 for the remaining 3
+        assert_eq!(s.series("elapsed"), vec![(3.0, 2.0)]);
+        assert_eq!(s.series("done"), vec![(9.0, 9.0)]);
+        assert_eq!(s.completed_process_count(), 2);
+    }
+
+    #[test]
+    fn grow_capacity() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(10.0);
+            yield Effect::Release(r);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(1.0);
+            yield Effect::Release(r);
+        }));
+        // a manager adds a second unit at time 3.0
+        s.create_process(ProcessId(3), Box::new(move || {
+            yield Effect::TimeOut(3.0);
+            yield Effect::SetCapacity(r, 2);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(2), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(3), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.resource_wait_stats(r).max, 2.0);
+        assert_eq!(s.now(), 10.0);
+    }
+
+    #[test]
+    fn shrink_capacity() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(2, QueueDiscipline::Fifo);
+        for &(pid, hold) in [(1, 2.0), (2, 5.0), (3, 1.0)].iter() {
+            s.create_process(ProcessId(pid), Box::new(move || {
+                yield Effect::Request(r);
+                yield Effect::TimeOut(hold);
+                yield Effect::Release(r);
+            }));
+        }
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(3), label: None}).unwrap();
+        s.step().unwrap();
+        s.step().unwrap();
+        // both units are in use, the resource shrinks to one unit
+        s.set_resource_capacity(r, 1);
+        // process 1 releases at 2.0, but its unit is gone: process 3 must
+        // wait for process 2 to release at 5.0
+        let s = s.run(NoEvents);
+        assert_eq!(s.resource_wait_stats(r).max, 4.0);
+        assert_eq!(s.now(), 6.0);
+    }
+
+    #[test]
+    fn select() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let ctx2 = ctx.clone();
+        let ctx3 = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+
+        // holds the resource for 10 time units
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(10.0);
+            yield Effect::Release(r);
+        }));
+        // gives up after 3 time units
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Select(vec![Effect::Request(r), Effect::TimeOut(3.0)]);
+            assert_eq!(ctx2.selected_branch(ProcessId(2)), Some(1));
+            assert_eq!(ctx2.time(), 4.0);
+        }));
+        // gets the resource when process 1 releases it
+        s.create_process(ProcessId(3), Box::new(move || {
+            yield Effect::Select(vec![Effect::TimeOut(20.0), Effect::Request(r)]);
+            assert_eq!(ctx3.selected_branch(ProcessId(3)), Some(1));
+            assert_eq!(ctx3.time(), 10.0);
+            yield Effect::Release(r);
+        }));
+
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(2), label: None}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(3), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        // the cancelled timeout of process 3 does not resume it
+        assert_eq!(s.now(), 10.0);
+        assert_eq!(s.resource_wait_stats(r).count, 2);
+    }
+
+    #[test]
+    fn request_any() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let lanes = vec![
+            s.create_resource(1, QueueDiscipline::Fifo),
+            s.create_resource(1, QueueDiscipline::Fifo),
+        ];
+        // hold both lanes, the first one longer
+        for (p, &hold) in [3.0, 1.0].iter().enumerate() {
+            let lane = lanes[p];
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Request(lane);
+                yield Effect::TimeOut(hold);
+                yield Effect::Release(lane);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let c = ctx.clone();
+        let l = lanes.clone();
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::RequestAny(l.clone());
+            let lane = c.granted_resource(ProcessId(2)).unwrap();
+            assert_eq!(lane, l[1]);
+            assert_eq!(c.time(), 1.0);
+            yield Effect::TimeOut(1.0);
+            yield Effect::Release(lane);
+            yield Effect::TimeOut(2.0);
+            // both lanes are free now: the first one is taken immediately
+            yield Effect::RequestAny(l.clone());
+            assert_eq!(c.granted_resource(ProcessId(2)), Some(l[0]));
+            yield Effect::Release(l[0]);
+        }));
+        s.schedule_event(Event{time: 0.5, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 4.0);
+        assert_eq!(s.resource_wait_stats(lanes[0]).count, 2);
+        assert_eq!(s.resource_wait_stats(lanes[1]).count, 2);
+    }
+
+    #[test]
+    fn request_all() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        // dining philosophers: each one needs the fork on its left and the
+        // one on its right, requested in opposite orders
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let forks: Vec<_> = (0..3).map(|_| s.create_resource(1, QueueDiscipline::Fifo)).collect();
+        for p in 0..3 {
+            let (left, right) = (forks[p], forks[(p + 1) % 3]);
+            s.create_process(ProcessId(p), Box::new(move || {
+                for _ in 0..2 {
+                    yield Effect::RequestAll(vec![right, left]);
+                    yield Effect::TimeOut(1.0);
+                    yield Effect::Release(left);
+                    yield Effect::Release(right);
+                }
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        // someone holding a single fork delays the ones that need it
+        let f = forks[1];
+        s.create_process(ProcessId(3), Box::new(move || {
+            yield Effect::Request(f);
+            yield Effect::TimeOut(0.5);
+            yield Effect::Release(f);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(3), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert!(!s.is_deadlocked());
+        assert_eq!(s.completed_process_count(), 4);
+        // only one philosopher can eat at a time, 6 meals in total
+        assert_eq!(s.now(), 6.0);
+    }
+
+    #[test]
+    fn release_after() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let ctx1 = ctx.clone();
+        let ctx2 = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        s.enable_hold_tracking();
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::ReleaseAfter(r, 5.0);
+            // goes on while still holding the resource
+            assert_eq!(ctx1.time(), 0.0);
+            yield Effect::TimeOut(1.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Request(r);
+            assert_eq!(ctx2.time(), 5.0);
+            yield Effect::Release(r);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.completed_process_count(), 2);
+        assert_eq!(s.process_busy_time(ProcessId(1)), 5.0);
+        assert_eq!(s.resource_wait_stats(r).max, 5.0);
+    }
+
+    #[test]
+    fn release_after_cancel() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::{NoEvents, NSteps};
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let ctx2 = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::ReleaseAfter(r, 5.0);
+            yield Effect::TimeOut(10.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Request(r);
+            ctx2.record("acquired", 2.0);
+            yield Effect::Release(r);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let mut s = s.run(NSteps(4));
+        // the process is gone, but the resource is still released at 5.0
+        s.cancel_process(ProcessId(1));
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("acquired"), vec![(5.0, 2.0)]);
+        assert_eq!(s.resource_state(r).available, 1);
+    }
+
+    #[test]
+    fn resource_callbacks() {
+        use std::cell::RefCell;
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(2, QueueDiscipline::Fifo);
+        let idle = Rc::new(RefCell::new(Vec::new()));
+        let saturated = Rc::new(RefCell::new(Vec::new()));
+        let i = idle.clone();
+        s.on_resource_idle(r, Box::new(move |t| i.borrow_mut().push(t)));
+        let sat = saturated.clone();
+        s.on_resource_saturated(r, Box::new(move |t| sat.borrow_mut().push(t)));
+        for p in 0..3 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Request(r);
+                yield Effect::TimeOut(2.0);
+                yield Effect::Release(r);
+            }));
+        }
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 5.0, process: ProcessId(2), label: None}).unwrap();
+        let _ = s.run(NoEvents);
+        // both units held from 1.0 to 2.0, free from 3.0 to 5.0 and after 7.0
+        assert_eq!(*saturated.borrow(), vec![1.0]);
+        assert_eq!(*idle.borrow(), vec![3.0, 7.0]);
+    }
+
+    #[test]
+    fn reorder_resource_queue() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::{NSteps, NoEvents};
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        for p in 0..3 {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Request(r);
+                c.record("served", p as f64);
+                yield Effect::TimeOut(1.0);
+                yield Effect::Release(r);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let mut s = s.run(NSteps(3));
+        assert_eq!(s.resource_queue_snapshot(r), vec![ProcessId(1), ProcessId(2)]);
+        s.reorder_resource_queue(r, &[ProcessId(2), ProcessId(1)]);
+        assert_eq!(s.resource_queue_snapshot(r), vec![ProcessId(2), ProcessId(1)]);
+        let s = s.run(NoEvents);
+        let served: Vec<f64> = s.series("served").iter().map(|&(_, p)| p).collect();
+        assert_eq!(served, vec![0.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a permutation of the queue")]
+    fn reorder_resource_queue_not_permutation() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NSteps;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        for p in 0..2 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Request(r);
+                yield Effect::TimeOut(1.0);
+                yield Effect::Release(r);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let mut s = s.run(NSteps(2));
+        s.reorder_resource_queue(r, &[ProcessId(0)]);
+    }
+
+    #[test]
+    fn request_all_opposite_orders() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let printer = s.create_resource(1, QueueDiscipline::Fifo);
+        let network = s.create_resource(1, QueueDiscipline::Fifo);
+        for (p, wanted) in vec![(0, vec![printer, network]), (1, vec![network, printer])] {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::RequestAll(wanted.clone());
+                c.record("granted", p as f64);
+                yield Effect::TimeOut(2.0);
+                for i in 0..wanted.len() {
+                    yield Effect::Release(wanted[i]);
+                }
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        assert!(!s.is_deadlocked());
+        assert_eq!(s.series("granted"), vec![(0.0, 0.0), (2.0, 1.0)]);
+        assert_eq!(s.now(), 4.0);
+    }
+
+    #[test]
+    fn missing_process() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use MissingPolicy;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.on_missing_process(MissingPolicy::Drop);
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(2.0);
+        }));
+        s.schedule_event(Event{time: 1.0, process: ProcessId(7), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 2.0);
+        assert_eq!(s.completed_process_count(), 1);
+        assert!(s.processed_events().iter().all(|e| e.process == ProcessId(0)));
+
+        // the process is created by the time the event is deferred
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.on_missing_process(MissingPolicy::Defer(1));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.step().unwrap();
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 1.0);
+        assert_eq!(s.completed_process_count(), 1);
+    }
+
+    #[test]
+    fn cancel_process() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(5.0);
+            yield Effect::Release(r);
+        }));
+        // waits for the resource and is cancelled while in the queue
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Request(r);
+            panic!("process 2 should have been cancelled");
+        }));
+        s.create_process(ProcessId(3), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(3), label: None}).unwrap();
+        s.schedule_event(Event{time: 3.0, process: ProcessId(3), label: None}).unwrap();
+        for _ in 0..3 {
+            s.step().unwrap();
+        }
+        s.cancel_process(ProcessId(2));
+        s.cancel_process(ProcessId(3));
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 5.0);
+        assert!(s.processed_events().iter().all(|e| e.process == ProcessId(1) || e.time == 0.0));
+    }
+
+    #[test]
+    fn cancel_process_messages() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::SendMessage(ProcessId(2), TestMessage::MessageType1, 0.0);
+            yield Effect::SendMessage(ProcessId(2), TestMessage::MessageType1, 5.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            loop {
+                yield Effect::Wait;
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        while ctx.message_count(ProcessId(2)) == 0 {
+            s.step().unwrap();
+        }
+        ctx.interrupt(ProcessId(2));
+        s.cancel_process(ProcessId(2));
+        // the message delivered, the interruption and the one in transit are dropped
+        assert_eq!(ctx.message_count(ProcessId(2)), 0);
+        assert!(ctx.check_interrupted(ProcessId(2)).is_none());
+        assert!(s.peek_next_event().map_or(true, |e| e.process != ProcessId(2)));
+    }
+
+    #[test]
+    fn join() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use JoinOutcome;
+        use WaitReason;
+        use EndCondition::{NoEvents, NSteps};
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        for p in 1..3 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::TimeOut(p as f64 * 2.0);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        for (p, target) in [(3, 1), (4, 2), (5, 9)].iter().cloned() {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Join(ProcessId(target));
+                let completed = c.join_outcome(ProcessId(p)) == Some(JoinOutcome::Completed);
+                c.record(if completed { "completed" } else { "cancelled" }, target as f64);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        // the five processes started and process 5 was resumed
+        let mut s = s.run(NSteps(6));
+        // process 9 does not exist
+        assert_eq!(s.series("cancelled"), vec![(0.0, 9.0)]);
+        assert_eq!(s.stuck_processes(), vec![(ProcessId(3), WaitReason::Join(ProcessId(1))), (ProcessId(4), WaitReason::Join(ProcessId(2)))]);
+        // the joiner of process 2 is resumed because it was cancelled
+        s.cancel_process(ProcessId(2));
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("completed"), vec![(2.0, 1.0)]);
+        assert_eq!(s.series("cancelled"), vec![(0.0, 9.0), (0.0, 2.0)]);
+        assert!(s.stuck_processes().is_empty());
+    }
+
+    #[test]
+    fn cancel_process_events() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::ReleaseAfter(r, 2.0);
+            yield Effect::TimeOut(5.0);
+            panic!("process 1 should not be resumed");
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        for _ in 0..3 {
+            s.step().unwrap();
+        }
+        for &t in &[1.0, 3.0, 4.0] {
+            s.schedule_event(Event{time: t, process: ProcessId(1), label: None}).unwrap();
+        }
+        // the end of the timeout and the three wakeups
+        assert_eq!(s.cancel_process_events(ProcessId(1)), 4);
+        assert_eq!(s.cancel_process_events(ProcessId(1)), 0);
+        let s = s.run(NoEvents);
+        // the resource is released anyway
+        assert_eq!(s.now(), 2.0);
+        assert_eq!(s.resource_state(r).available, 1);
+    }
+
+    #[test]
+    fn groups() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let workers = s.create_group();
+        for p in 0..3 {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                loop {
+                    yield Effect::Wait;
+                    if c.check_interrupted(ProcessId(p)).is_some() {
+                        c.record("interrupted", p as f64);
+                    }
+                }
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+            s.add_to_group(ProcessId(p), workers);
+        }
+        s.add_to_group(ProcessId(0), workers);
+        assert_eq!(s.group_members(workers), &[ProcessId(0), ProcessId(1), ProcessId(2)]);
+
+        // the interruptions are processed after the events starting them
+        s.broadcast_interrupt(workers);
+        let mut s = s.run(NoEvents);
+        assert_eq!(s.series("interrupted").len(), 3);
+        assert_eq!(s.live_process_count(), 3);
+
+        s.cancel_group(workers);
+        assert!(s.group_members(workers).is_empty());
+        assert_eq!(s.live_process_count(), 0);
+    }
+
+    #[test]
+    fn interrupt_group() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let children = s.create_group();
+        for p in 1..5 {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::TimeOut(if p == 4 { 0.5 } else { 100.0 });
+                if c.check_interrupted(ProcessId(p)).is_some() {
+                    c.record("interrupted", p as f64);
+                }
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+            s.add_to_group(ProcessId(p), children);
+        }
+        // the supervisor, in the group too, is not interrupted
+        s.add_to_group(ProcessId(0), children);
+        s.create_process(ProcessId(0), Box::new(move || {
+            yield Effect::TimeOut(1.0);
+            yield Effect::InterruptGroup(children);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("interrupted"), vec![(1.0, 1.0), (1.0, 2.0), (1.0, 3.0)]);
+        assert_eq!(s.now(), 1.0);
+        // completed processes leave the group
+        assert!(s.group_members(children).is_empty());
+    }
+
+    #[test]
+    fn request_with_patience() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use Outcome;
+        use EndCondition::{NoEvents, Time};
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(0), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(10.0);
+            yield Effect::Release(r);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        for &(p, patience) in &[(1, 3.0), (2, 20.0)] {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::RequestWithPatience(r, patience);
+                let outcome = c.acquisition_outcome(ProcessId(p)).unwrap();
+                c.record(if outcome == Outcome::Served { "served" } else { "reneged" }, p as f64);
+                if outcome == Outcome::Served {
+                    yield Effect::Release(r);
+                }
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        // process 1 gave up while the resource is still busy
+        let s = s.run(Time(3.0));
+        assert_eq!(s.series("reneged"), vec![(3.0, 1.0)]);
+        assert_eq!(s.resource_queue_snapshot(r), vec![ProcessId(2)]);
+        assert_eq!(s.resource_state(r).available, 0);
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("served"), vec![(10.0, 2.0)]);
+        assert_eq!(s.resource_state(r).available, 1);
+        assert!(s.resource_queue_snapshot(r).is_empty());
+    }
+
+    #[test]
+    fn request_with_timeout() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use RequestOutcome;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(0), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(5.0);
+            yield Effect::Release(r);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        for &(p, timeout) in &[(1, 2.0), (2, 8.0)] {
+            let c = ctx.clone();
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::RequestWithTimeout(r, timeout);
+                match c.last_request_outcome(ProcessId(p)).unwrap() {
+                    RequestOutcome::Acquired => {
+                        c.record("acquired", p as f64);
+                        yield Effect::Release(r);
+                    }
+                    RequestOutcome::TimedOut => c.record("timed out", p as f64),
+                }
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("timed out"), vec![(2.0, 1.0)]);
+        assert_eq!(s.series("acquired"), vec![(5.0, 2.0)]);
+        // the timeout of process 2 was cancelled when it got the resource
+        assert_eq!(s.now(), 5.0);
+        assert_eq!(s.resource_state(r).available, 1);
+    }
+
+    #[test]
+    fn waiting_processes() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::{NoEvents, NSteps};
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        for p in 1..3 {
+            s.create_process(ProcessId(p), Box::new(|| {
+                yield Effect::Wait;
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+            yield Effect::Event(Event{time: 2.0, process: ProcessId(1), label: None});
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NSteps(3));
+        assert_eq!(s.waiting_processes(), vec![ProcessId(1), ProcessId(2)]);
+        assert_eq!(ctx.waiting_processes(), vec![ProcessId(1), ProcessId(2)]);
+        // process 1 is woken up, process 2 never is
+        let mut s = s.run(NoEvents);
+        assert_eq!(s.waiting_processes(), vec![ProcessId(2)]);
+        s.schedule_event(Event{time: 3.0, process: ProcessId(2), label: None}).unwrap();
+        assert!(s.waiting_processes().is_empty());
+    }
+
+    #[test]
+    fn custom_ordering() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+        use std::cmp::Ordering;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        // same-time events by descending pid
+        let mut s = Simulation::with_ordering(ctx, |a: &Event<f64>, b: &Event<f64>| {
+            a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal).then(b.process.cmp(&a.process))
+        });
+        for p in 1..4 {
+            s.create_process(ProcessId(p), Box::new(|| {
+                yield Effect::TimeOut(1.0);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        let order: Vec<_> = s.processed_events().iter().map(|e| (e.time, e.process.0)).collect();
+        assert_eq!(order, vec![(0.0, 3), (0.0, 2), (0.0, 1), (1.0, 3), (1.0, 2), (1.0, 1)]);
+    }
+
+    #[test]
+    fn subcontext() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let sub = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        s.attach_subcontext(sub.clone(), 100);
+        let worker = sub.new_pid();
+        assert_eq!(worker, ProcessId(100));
+        let c = sub.clone();
+        s.create_process(worker, Box::new(move || {
+            yield Effect::WaitForMessage;
+            c.record("received", c.message_count(worker) as f64);
+            // pushed to the attached context, routed to the primary one
+            c.push_message(ProcessId(1), TestMessage::MessageType2("done"));
+            c.interrupt(ProcessId(1));
+        }));
+        s.schedule_event(Event{time: 0.0, process: worker, label: None}).unwrap();
+        let c = ctx.clone();
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::SendMessage(worker, TestMessage::MessageType1, 2.0);
+            yield Effect::TimeOut(5.0);
+            if c.check_interrupted(ProcessId(1)).is_some() {
+                c.record("replied", c.message_count(ProcessId(1)) as f64);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(sub.series.borrow()["received"], vec![(2.0, 1.0)]);
+        assert_eq!(s.series("replied"), vec![(5.0, 1.0)]);
+        assert_eq!(sub.time(), 5.0);
+    }
+
+    #[test]
+    fn reset() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::{NoEvents, Time};
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            loop {
+                yield Effect::TimeOut(2.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        // the process keeps the resource when the simulation is stopped
+        let mut s = s.run(Time(4.0));
+        assert_eq!(s.now(), 4.0);
+        s.context().push_message(ProcessId(1), TestMessage::MessageType1);
+        s.context().interrupt(ProcessId(1));
+
+        s.reset();
+        assert_eq!(s.now(), 0.0);
+        assert!(s.processed_events().is_empty());
+        assert_eq!(s.future_event_count(), 0);
+        assert_eq!(s.context().message_count(ProcessId(1)), 0);
+        assert!(s.context().check_interrupted(ProcessId(1)).is_none());
+        // the resource is available again and the process is still alive
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(1.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 1.0);
+    }
+
+    #[test]
+    fn snapshot() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let go = ctx.create_signal("go");
+        s.create_process(ProcessId(1), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(1.5);
+            }
+        }));
+        for p in 2..4 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::WaitForSignal(go);
+            }));
+        }
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let g = s.create_group();
+        s.add_to_group(ProcessId(1), g);
+        for _ in 0..4 {
+            s.step().unwrap();
+        }
+        let snapshot = s.snapshot();
+        assert_eq!(snapshot.time(), 3.0);
+        let events = s.processed_events().to_vec();
+
+        // #2 is woken up and #3 starts waiting after the snapshot
+        ctx.fire_signal(go);
+        s.schedule_event(Event{time: 4.0, process: ProcessId(3), label: None}).unwrap();
+        s.add_to_group(ProcessId(3), g);
+        s.create_group();
+        for _ in 0..5 {
+            s.step().unwrap();
+        }
+        assert_eq!(s.now(), 7.5);
+        assert_eq!(s.signal_waiters[&go], vec![ProcessId(3)]);
+
+        s.restore(&snapshot);
+        assert_eq!(s.now(), 3.0);
+        assert_eq!(s.signal_waiters[&go], vec![ProcessId(2)]);
+        assert_eq!(s.group_members(g), &[ProcessId(1)]);
+        assert_eq!(s.groups.len(), 1);
+        assert_eq!(s.processed_events().len(), events.len());
+        for (a, b) in s.processed_events().iter().zip(events.iter()) {
+            assert_eq!(a.time, b.time);
+            assert_eq!(a.process, b.process);
+        }
+        s.step().unwrap();
+        assert_eq!(s.now(), 4.5);
+    }
+
+    #[test]
+    fn per_process_log() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::Time;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(2.0);
+            }
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(3.5);
+            }
+        }));
+        s.enable_per_process_log();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(Time(7.0));
+        assert_eq!(s.process_event_times(ProcessId(1)).unwrap(), &[0.0, 2.0, 4.0, 6.0]);
+        assert_eq!(s.process_event_times(ProcessId(2)).unwrap(), &[0.0, 3.5, 7.0]);
+        assert!(s.process_event_times(ProcessId(3)).is_none());
+    }
+
+    #[test]
+    fn process_stats() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.enable_process_stats();
+        s.create_process(ProcessId(0), Box::new(move || {
+            yield Effect::TimeOut(2.0);
+            yield Effect::TimeOut(3.0);
+        }));
+        s.schedule_event(Event{time: 1.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        let stats = s.process_stats(ProcessId(0)).unwrap();
+        // resumed at 1.0, 3.0 and 6.0
+        assert_eq!(stats.resume_count, 3);
+        assert_eq!(stats.total_wait_time, 6.0);
+        assert!(stats.total_active_time >= 0.0);
+        assert!(s.process_stats(ProcessId(1)).is_none());
+    }
+
+    #[test]
+    fn wall_clock_tracking() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NSteps;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.step().unwrap();
+        assert!(s.wall_time_per_step().is_empty());
+        s.enable_wall_clock_tracking();
+        // the steps are counted from the start of the run
+        let s = s.run(NSteps(5));
+        assert_eq!(s.wall_time_per_step().len(), 5);
+    }
+
+    #[test]
+    fn poisson_source() {
+        use Simulation;
+        use Effect;
+        use EndCondition::Time;
+        use std::cell::Cell;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        ctx.seed(42);
+        let mut s = Simulation::new(ctx.clone());
+        let arrivals = Rc::new(Cell::new(0));
+        let counter = arrivals.clone();
+        s.add_poisson_source(2.0, Box::new(move |_pid| {
+            counter.set(counter.get() + 1);
+            Box::new(|| {
+                yield Effect::TimeOut(1.0);
+            })
+        }));
+        s.run(Time(1000.0));
+        // about rate * duration = 2000 arrivals
+        let n = arrivals.get();
+        assert!(n > 1800 && n < 2200, "{} arrivals", n);
+    }
+
+    #[test]
+    fn choose_weighted() {
+        let draws = |seed| {
+            let ctx = Context::<TestMessage>::new();
+            ctx.seed(seed);
+            (0..10000).map(|_| ctx.choose_weighted(&[0.7, 0.0, 0.3])).collect::<Vec<_>>()
+        };
+        let a = draws(42);
+        assert_eq!(a, draws(42));
+        assert_ne!(a, draws(7));
+        assert!(a.iter().all(|&i| i != 1));
+        let n = a.iter().filter(|&&i| i == 0).count();
+        assert!(n > 6800 && n < 7200, "{} draws of the first index", n);
+    }
+
+    /// Run a resource held until 10.0 and three waiters enqueued at 1.0,
+    /// 2.0 and 3.0 with the given priorities. Returns the service order.
+    fn service_order(discipline: ::QueueDiscipline, priorities: [u32; 3]) -> Vec<usize> {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+        use std::cell::RefCell;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, discipline);
+        let order = Rc::new(RefCell::new(Vec::new()));
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(10.0);
+            yield Effect::Release(r);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        for i in 0..3 {
+            let pid = i + 2;
+            let priority = priorities[i];
+            let order = order.clone();
+            s.create_process(ProcessId(pid), Box::new(move || {
+                yield Effect::PriorityRequest(r, priority);
+                order.borrow_mut().push(pid);
+                yield Effect::TimeOut(1.0);
+                yield Effect::Release(r);
+            }));
+            s.schedule_event(Event{time: i as f64 + 1.0, process: ProcessId(pid), label: None}).unwrap();
+        }
+        s.run(NoEvents);
+        let order = order.borrow().clone();
+        order
+    }
+
+    #[test]
+    fn queue_disciplines() {
+        use QueueDiscipline::*;
+
+        assert_eq!(service_order(Fifo, [5, 1, 3]), vec![2, 3, 4]);
+        assert_eq!(service_order(Lifo, [5, 1, 3]), vec![4, 3, 2]);
+        assert_eq!(service_order(Priority, [5, 1, 3]), vec![3, 4, 2]);
+        // FIFO among equal priorities
+        assert_eq!(service_order(Priority, [1, 1, 0]), vec![4, 2, 3]);
+    }
+
+    #[test]
+    fn set_queue_discipline() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::{NoEvents, NSteps};
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(0), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(10.0);
+            yield Effect::Release(r);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        for i in 1..4 {
+            let c = ctx.clone();
+            s.create_process(ProcessId(i), Box::new(move || {
+                yield Effect::PriorityRequest(r, 3 - i as u32);
+                c.record("served", i as f64);
+                yield Effect::Release(r);
+            }));
+            s.schedule_event(Event{time: i as f64, process: ProcessId(i), label: None}).unwrap();
+        }
+        s.run_mut(NSteps(5));
+        assert_eq!(s.resource_queue_snapshot(r), vec![ProcessId(1), ProcessId(2), ProcessId(3)]);
+        s.set_queue_discipline(r, QueueDiscipline::Lifo);
+        assert_eq!(s.resource_discipline(r), QueueDiscipline::Lifo);
+        assert_eq!(s.resource_queue_snapshot(r), vec![ProcessId(3), ProcessId(2), ProcessId(1)]);
+        // back to the arrival order, then by priority
+        s.set_queue_discipline(r, QueueDiscipline::Fifo);
+        assert_eq!(s.resource_queue_snapshot(r), vec![ProcessId(1), ProcessId(2), ProcessId(3)]);
+        s.set_queue_discipline(r, QueueDiscipline::Priority);
+        assert_eq!(s.resource_queue_snapshot(r), vec![ProcessId(3), ProcessId(2), ProcessId(1)]);
+        s.set_queue_discipline(r, QueueDiscipline::Lifo);
+        let s = s.run(NoEvents);
+        let served: Vec<f64> = s.series("served").iter().map(|&(_, i)| i).collect();
+        assert_eq!(served, vec![3.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn clone_config() {
+        use Simulation;
+        use SimulationConfig;
+        use QueueDiscipline;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        ctx.seed(7);
+        let mut s = Simulation::new(ctx.clone());
+        s.create_resource(2, QueueDiscipline::Fifo);
+        let r = s.create_resource(1, QueueDiscipline::Priority);
+        let config = s.clone_config();
+        let expected = ctx.rand_uniform();
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let s2 = Simulation::from_config(ctx.clone(), &config);
+        assert_eq!(s2.clone_config(), config);
+        assert_eq!(s2.resource_count(), 2);
+        assert_eq!(s2.resource_state(r).allocated, 1);
+        assert_eq!(s2.resource_discipline(r), QueueDiscipline::Priority);
+        assert_eq!(ctx.rand_uniform(), expected);
+
+        // a zero seed would stop the generator
+        let config = SimulationConfig { resources: Vec::new(), seed: 0 };
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        Simulation::from_config(ctx.clone(), &config);
+        assert_ne!(ctx.rand_uniform(), ctx.rand_uniform());
+    }
+
+    #[test]
+    fn release_without_hold() {
+        use Simulation;
+        use SimulationError;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.enable_hold_tracking();
+        let r = s.create_resource(2, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(1.0);
+            yield Effect::Release(r);
+        }));
+        // releases a unit that process 1 is using
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Release(r);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.5, process: ProcessId(2), label: None}).unwrap();
+        s.step().unwrap();
+        s.step().unwrap();
+        assert_eq!(s.step(), Err(SimulationError::ReleaseWithoutHold { process: ProcessId(2), resource: r }));
+    }
+
+    #[test]
+    fn shrink_log() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            for _ in 0..9 {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let mut s = s.run(NoEvents);
+        assert_eq!(s.processed_events().len(), 10);
+        s.shrink_log(4.0);
+        assert_eq!(s.processed_events()[0].time, 4.0);
+        s.set_log_capacity(3);
+        let times: Vec<f64> = s.processed_events().iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![7.0, 8.0, 9.0]);
+        assert_eq!(s.events_between(0.0, 8.0).count(), 1);
+        // the events removed are still counted
+        assert_eq!(s.summary().events_processed, 10);
+    }
+
+    #[test]
+    fn log_capacity() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.set_log_capacity(2);
+        s.create_process(ProcessId(0), Box::new(|| {
+            for _ in 0..9 {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        let times: Vec<f64> = s.processed_events().iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![8.0, 9.0]);
+        assert_eq!(s.summary().events_processed, 10);
+    }
+
+    #[test]
+    fn builder_log_capacity() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let (mut s, _) = Simulation::<TestMessage>::builder()
+            .with_log_capacity(2)
+            .build();
+        s.create_process(ProcessId(0), Box::new(|| {
+            for _ in 0..9 {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        let times: Vec<f64> = s.processed_events().iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![8.0, 9.0]);
+        assert_eq!(s.processed_event_count(), 10);
+        assert_eq!(s.summary().events_processed, 10);
+    }
+
+    #[test]
+    fn log_policy() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use LogPolicy;
+        use EndCondition::NoEvents;
+
+        let run = |policy| {
+            let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+            s.set_log_policy(policy);
+            s.create_process(ProcessId(0), Box::new(|| {
+                for _ in 0..99 {
+                    yield Effect::TimeOut(1.0);
+                }
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+            let s = s.run(NoEvents);
+            assert_eq!(s.processed_event_count(), 100);
+            s.processed_events().iter().map(|e| e.time).collect::<Vec<f64>>()
+        };
+        let times = run(LogPolicy::LastN(10));
+        assert_eq!(times, (90..100).map(|t| t as f64).collect::<Vec<f64>>());
+        assert_eq!(run(LogPolicy::None), vec![]);
+        assert_eq!(run(LogPolicy::Sampled(25)), vec![0.0, 25.0, 50.0, 75.0]);
+        assert_eq!(run(LogPolicy::All).len(), 100);
+    }
+
+    #[test]
+    fn throughput() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        assert_eq!(s.throughput(), 0.0);
+        // a job arrives every time unit and departs 2 time units later
+        for i in 0..4 {
+            s.create_process(ProcessId(i), Box::new(move || {
+                yield Effect::Event(Event{time: 2.0, process: ProcessId(i), label: Some("departure")});
+            }));
+            s.schedule_event(Event{time: i as f64, process: ProcessId(i), label: Some("arrival")}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        assert_eq!(s.arrival_count(), 4);
+        assert_eq!(s.departure_count(), 4);
+        assert_eq!(s.now(), 5.0);
+        assert_eq!(s.throughput(), 0.8);
+    }
+
+    #[test]
+    fn steady_state() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::SteadyState;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(1.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        // converges to 0, but never stops changing
+        let metric = |s: &Simulation<TestMessage>| 1.0 / (1.0 + s.now());
+        let s = s.run(SteadyState { metric: Box::new(metric), window: 5, tolerance: 0.01 });
+        // the last 5 samples, from 12 to 16, are within 0.01 of their mean
+        assert_eq!(s.now(), 16.0);
+        let s = s.run(SteadyState { metric: Box::new(metric), window: 5, tolerance: 0.001 });
+        assert!(s.now() > 16.0);
+        assert!(metric(&s) < 0.05);
+    }
+
+    #[test]
+    fn count_events_to() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::{NoEvents, NSteps, Time};
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+            panic!("process 0 should not be resumed");
+        }));
+        for &t in &[0.0, 2.0, 4.0, 6.0] {
+            s.schedule_event(Event{time: t, process: ProcessId(0), label: None}).unwrap();
+        }
+        assert_eq!(s.count_events_to(NoEvents), 4);
+        assert_eq!(s.count_events_to(NSteps(3)), 3);
+        // as `run`, up to the first event at or after the given time
+        assert_eq!(s.count_events_to(Time(3.0)), 3);
+        assert_eq!(s.count_events_to(Time(4.0)), 3);
+        assert_eq!(s.now(), 0.0);
+    }
+
+    #[test]
+    fn summary() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use RunSummary;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        assert_eq!(s.summary().events_per_time_unit, 0.0);
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        for p in 0..2 {
+            s.create_process(ProcessId(p), Box::new(move || {
+                yield Effect::Request(r);
+                yield Effect::TimeOut(5.0);
+                yield Effect::Release(r);
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        // each process: request, timeout, release and completion
+        let summary = RunSummary {
+            end_time: 10.0,
+            events_processed: 8,
+            events_per_time_unit: 0.8,
+            live_processes: 0,
+            completed_processes: 2,
+            resources: 1,
+        };
+        assert_eq!(s.summary(), summary);
+        assert_eq!(
+            summary.to_string(),
+            "end time: 10.0, events processed: 8 (0.800 per time unit), processes: 0 live, 2 completed, resources: 1"
+        );
+    }
+
+    #[test]
+    fn process_busy_time() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.enable_hold_tracking();
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::TimeOut(2.0);
+            yield Effect::Request(r);
+            yield Effect::TimeOut(7.0);
+            yield Effect::Release(r);
+            yield Effect::TimeOut(3.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 12.0);
+        assert_eq!(s.process_busy_time(ProcessId(1)), 7.0);
+        assert_eq!(s.process_busy_time(ProcessId(2)), 0.0);
+    }
+
+    #[test]
+    fn event_budget() {
+        use Simulation;
+        use SimulationError;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.set_event_budget(Some(1000));
+        s.create_process(ProcessId(0), Box::new(|| {
+            loop {
+                yield Effect::TimeOut(0.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        match s.run_strict(NoEvents) {
+            Err((s, e)) => {
+                assert_eq!(e, SimulationError::EventBudgetExceeded { budget: 1000 });
+                assert_eq!(s.processed_events().len(), 1000);
+            }
+            Ok(_) => panic!("the run should have exceeded the budget"),
+        }
+    }
+
+    #[test]
+    fn run_strict() {
+        use Simulation;
+        use SimulationError;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.enable_hold_tracking();
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(1.0);
+            yield Effect::Release(r);
+            // released twice
+            yield Effect::Release(r);
+            yield Effect::TimeOut(1.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let (s, e) = match s.run_strict(NoEvents) {
+            Ok(_) => panic!("the over-release was not reported"),
+            Err(failure) => failure,
+        };
+        assert_eq!(e, SimulationError::ReleaseWithoutHold { process: ProcessId(1), resource: r });
+        assert_eq!(s.now(), 1.0);
+        // the event whose resumption yielded the release is not logged
+        let times: Vec<f64> = s.processed_events().iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn run_realtime() {
+        use std::time::{Duration, Instant};
+        use std::rc::Rc;
+        use std::cell::Cell;
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.create_process(ProcessId(1), Box::new(|| {
+            for _ in 0..5 {
+                yield Effect::TimeOut(10.0);
+            }
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        // never resumed
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::WaitForMessage;
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let stuck = Rc::new(Cell::new(0));
+        let st = stuck.clone();
+        s.on_deadlock(Box::new(move |processes| st.set(processes.len())));
+        let last = Rc::new(Cell::new(0.0));
+        let l = last.clone();
+        s.set_timestamp_monitor(Box::new(move |t, _| l.set(t)));
+        let started = Instant::now();
+        // 1000 time units per second: 50 units take 50 ms
+        let s = s.run_realtime(1000.0, NoEvents);
+        assert_eq!(s.now(), 50.0);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(stuck.get(), 1);
+        assert_eq!(last.get(), 50.0);
+    }
+
+    #[test]
+    fn shared_resource() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+
+        // two readers that share the resource for 4 time units
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::RequestShared(r);
+            yield Effect::TimeOut(4.0);
+            yield Effect::ReleaseShared(r);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::RequestShared(r);
+            yield Effect::TimeOut(4.0);
+            yield Effect::ReleaseShared(r);
+        }));
+        // a writer that must wait for both the readers
+        s.create_process(ProcessId(3), Box::new(move || {
             yield Effect::Request(r);
-            yield Effect::TimeOut(7.0);
+            yield Effect::TimeOut(2.0);
             yield Effect::Release(r);
         }));
-        // simple process that holds the resource for 3 time units
-        s.create_process(2, Box::new(move || {
-            yield Effect::Request(r);
+
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(2), label: None}).unwrap();
+        s.schedule_event(Event{time: 2.0, process: ProcessId(3), label: None}).unwrap();
+        // the readers overlap and release at 4.0 and 5.0,
+        // then the writer holds the resource until 7.0
+        let s = s.run(NoEvents);
+        s.print_trace();
+        assert_eq!(s.now(), 7.0);
+    }
+
+    #[test]
+    fn pool() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let bw = s.create_pool(1.0);
+
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Reserve(bw, 0.5);
             yield Effect::TimeOut(3.0);
-            yield Effect::Release(r);
+            yield Effect::Unreserve(bw, 0.5);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Reserve(bw, 0.25);
+            yield Effect::TimeOut(5.0);
+            yield Effect::Unreserve(bw, 0.25);
+        }));
+        // does not fit until process 1 gives back its share
+        s.create_process(ProcessId(3), Box::new(move || {
+            yield Effect::Reserve(bw, 0.8);
+            yield Effect::TimeOut(2.0);
+            yield Effect::Unreserve(bw, 0.8);
         }));
 
-        // let p1 start immediately...
-        s.schedule_event(Event{time: 0.0, process: 1});
-        // let p2 start after 2 t.u., when r is not available
-        s.schedule_event(Event{time: 2.0, process: 2});
-        // p2 will wait r to be free (time 7.0) and its timeout
-        // of 3.0 t.u. The simulation will end at time 10.0
-        
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(3), label: None}).unwrap();
+        // process 3 must wait for both 1 (t=3) and 2 (t=5)
         let s = s.run(NoEvents);
-        println!("{:?}", s.processed_events());
-        assert_eq!(ctx.time(), 10.0);
+        s.print_trace();
+        assert_eq!(s.now(), 7.0);
     }
 
     #[test]
     fn interruption() {
+        use Interrupted;
         use Simulation;
         use Effect;
         use Event;
+        use EndCondition::NoEvents;
 
         let ctx = Rc::new(Context::<TestMessage>::new());
         let ctx2 = ctx.clone();
         let mut s = Simulation::new(ctx.clone());
-        s.create_process(1, Box::new(move || {
+        s.create_process(ProcessId(1), Box::new(move || {
             yield Effect::TimeOut(1.0);
             println!("process #1: time {}", ctx.time());
-            assert!(!ctx.check_interrupted(1));
+            assert!(ctx.check_interrupted(ProcessId(1)).is_none());
             assert_eq!(ctx.time(), 1.0);
 
             yield Effect::TimeOut(1.0);
             println!("process #1: time {}", ctx.time());
-            assert!(ctx.check_interrupted(1));
+            assert_eq!(ctx.check_interrupted(ProcessId(1)), Some(Interrupted::Plain));
             assert_eq!(ctx.time(), 1.1);
 
+            yield Effect::TimeOut(1.0);
+            assert_eq!(
+                ctx.check_interrupted(ProcessId(1)),
+                Some(Interrupted::With(TestMessage::MessageType2("stop")))
+            );
+            assert!(ctx.check_interrupted(ProcessId(1)).is_none());
+            assert_eq!(ctx.time(), 1.6);
+
         }));
 
-        s.create_process(2, Box::new(move || {
+        s.create_process(ProcessId(2), Box::new(move || {
             yield Effect::TimeOut(1.1);
             println!("{}: interrupting process #1", ctx2.time());
-            yield Effect::Interrupt(1);
+            yield Effect::Interrupt(ProcessId(1));
+            yield Effect::TimeOut(0.5);
+            yield Effect::InterruptWith(ProcessId(1), TestMessage::MessageType2("stop"));
+        }));
+
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        // the timeouts of process #1 are cancelled by the interruptions
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 1.6);
+        assert_eq!(s.completed_process_count(), 2);
+    }
+
+    #[test]
+    fn record_and_replay() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use SimulationError;
+        use EndCondition::NoEvents;
+
+        fn model(delay: f64) -> Simulation<TestMessage> {
+            let mut s = Simulation::new(Rc::new(Context::new()));
+            s.create_process(ProcessId(0), Box::new(move || {
+                yield Effect::TimeOut(1.0);
+                yield Effect::SendMessage(ProcessId(1), TestMessage::MessageType1, delay);
+            }));
+            s.create_process(ProcessId(1), Box::new(move || {
+                yield Effect::WaitForMessage;
+            }));
+            s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+            s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+            s
+        }
+
+        let (_, recording) = model(2.0).record().run(NoEvents).finish();
+        assert_eq!(recording.effects().len(), 3);
+        assert_eq!(recording.effects()[0].0, ProcessId(0));
+
+        let mut s = model(2.0);
+        s.replay(&recording);
+        while s.step().unwrap().is_some() {}
+
+        // the message is sent with a different delay
+        let mut s = model(3.0);
+        s.replay(&recording);
+        let mut result = Ok(None);
+        for _ in 0..4 {
+            result = s.step();
+            if result.is_err() {
+                break;
+            }
+        }
+        assert_eq!(result, Err(SimulationError::ReplayDivergence{process: ProcessId(0), index: 2}));
+
+        // the run ends before the last effect is yielded
+        let mut s = model(2.0);
+        s.replay(&recording);
+        s.step_n(2).unwrap();
+        s.cancel_process(ProcessId(0));
+        assert_eq!(s.step(), Err(SimulationError::ReplayDivergence{process: ProcessId(0), index: 2}));
+    }
+
+    #[test]
+    fn interrupt_cancels_timeout() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::{NoEvents, Time};
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let c = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(move || {
+            loop {
+                yield Effect::TimeOut(100.0);
+                c.record("resumed", if c.check_interrupted(ProcessId(1)).is_some() { 1.0 } else { 0.0 });
+            }
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::TimeOut(1.0);
+            yield Effect::Interrupt(ProcessId(1));
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let mut s = s.run(Time(101.0));
+        // resumed once at 1.0 by the interruption, not also at 100.0
+        assert_eq!(s.series("resumed"), vec![(1.0, 1.0), (101.0, 0.0)]);
+        s.cancel_process(ProcessId(1));
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 101.0);
+    }
+
+    #[test]
+    fn interrupt_queued_process() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let c = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(5.0);
+            yield Effect::Release(r);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Request(r);
+            // interrupted while waiting: the resource was not granted
+            assert!(c.check_interrupted(ProcessId(2)).is_some());
+            yield Effect::Wait;
+        }));
+        s.create_process(ProcessId(3), Box::new(move || {
+            yield Effect::TimeOut(1.0);
+            yield Effect::Interrupt(ProcessId(2));
+        }));
+        for p in 1..4 {
+            s.schedule_event(Event{time: 0.0, process: ProcessId(p), label: None}).unwrap();
+        }
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 5.0);
+        assert!(s.resource_queue_snapshot(r).is_empty());
+        assert_eq!(s.resource_state(r).available, 1);
+        // process 2 is waiting, not resumed again by a phantom grant
+        assert_eq!(s.live_process_count(), 1);
+        assert_eq!(s.processed_events().iter().filter(|e| e.process == ProcessId(2)).count(), 2);
+    }
+
+    #[test]
+    fn parallel_simulations() {
+        use std::thread;
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let handles: Vec<_> = vec![1.0, 2.0].into_iter().map(|delay| {
+            thread::spawn(move || {
+                let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+                s.create_process(ProcessId(0), Box::new(move || {
+                    for _ in 0..3 {
+                        yield Effect::TimeOut(delay);
+                    }
+                }));
+                s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+                s.run(NoEvents).now()
+            })
+        }).collect();
+        let ends: Vec<f64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(ends, vec![3.0, 6.0]);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn sync_context() {
+        use std::thread;
+        use Simulation;
+        use SyncContext;
+        use Interrupted;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let contexts = vec![SyncContext::<TestMessage>::new(), SyncContext::new()];
+        let handles: Vec<_> = contexts.iter().cloned().zip(vec![1.0, 2.0]).map(|(ctx, delay)| {
+            thread::spawn(move || {
+                let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+                s.attach_sync_context(ctx.clone());
+                s.create_process(ProcessId(0), Box::new(move || {
+                    for i in 0..3 {
+                        yield Effect::TimeOut(delay);
+                        ctx.record("tick", i as f64);
+                    }
+                    ctx.push_message(ProcessId(1), TestMessage::MessageType1);
+                }));
+                s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+                s.run(NoEvents);
+            })
+        }).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(contexts[0].time(), 3.0);
+        assert_eq!(contexts[1].time(), 6.0);
+        assert_eq!(contexts[1].series("tick"), vec![(2.0, 0.0), (4.0, 1.0), (6.0, 2.0)]);
+        assert_eq!(contexts[0].message_count(ProcessId(1)), 1);
+        contexts[0].interrupt(ProcessId(1));
+        assert_eq!(contexts[0].check_interrupted(ProcessId(1)), Some(Interrupted::Plain));
+        contexts[1].interrupt_with(ProcessId(1), TestMessage::MessageType2("stop"));
+        assert_eq!(
+            contexts[1].check_interrupted(ProcessId(1)),
+            Some(Interrupted::With(TestMessage::MessageType2("stop")))
+        );
+        assert_eq!(contexts[1].check_interrupted(ProcessId(1)), None);
+    }
+
+    #[test]
+    fn checkpoint() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use ResourceId;
+        use EndCondition::{NoEvents, Time};
+        use std::ops::Generator;
+
+        fn user(r: ResourceId) -> Box<dyn Generator<Yield = Effect<TestMessage>, Return = ()> + Unpin> {
+            Box::new(move || {
+                yield Effect::Request(r);
+                yield Effect::TimeOut(2.0);
+                yield Effect::Release(r);
+            })
+        }
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::TimeOut(0.5);
+        }));
+        s.create_process(ProcessId(1), user(r));
+        s.create_process(ProcessId(2), user(r));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 1.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(Time(1.0));
+        let checkpoint = s.checkpoint();
+        assert_eq!(checkpoint.time(), 1.0);
+        let expected = s.run(NoEvents);
+
+        let mut restored = Simulation::from_checkpoint(checkpoint);
+        // process 0 completed, the others must be registered again, in the
+        // state they had
+        restored.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Release(r);
+        }));
+        // process 2 is waiting for the resource
+        restored.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::TimeOut(2.0);
+            yield Effect::Release(r);
+        }));
+        let restored = restored.run(NoEvents);
+        assert_eq!(restored.now(), expected.now());
+        assert_eq!(restored.processed_events().len(), expected.processed_events().len());
+        assert_eq!(restored.resource_wait_stats(r), expected.resource_wait_stats(r));
+    }
+
+    #[test]
+    fn fork() {
+        use Simulation;
+        use Effect;
+        use Event;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::TimeOut(1.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            loop {
+                yield Effect::TimeOut(2.0);
+            }
         }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        s.step().unwrap();
+        s.step().unwrap();
+        s.step().unwrap();
+        assert_eq!(ctx.time(), 1.0);
 
-        s.schedule_event(Event{time: 0.0, process: 1});
-        s.schedule_event(Event{time: 0.0, process: 2});
-        s.step();
-        s.step();
-        s.step();
-        s.step();
-        s.step();
-        s.step();
+        let mut f = s.fork_at_current_time();
+        let fctx = f.shared_context();
+        assert!(!Rc::ptr_eq(&ctx, &fctx));
+        assert_eq!(fctx.time(), 1.0);
+        // process 1 completed, process 2 must be supplied again
+        f.create_process(ProcessId(2), Box::new(move || {
+            loop {
+                yield Effect::TimeOut(5.0);
+            }
+        }));
+        f.step().unwrap();
+        f.step().unwrap();
+        assert_eq!(fctx.time(), 7.0);
+        s.step().unwrap();
+        s.step().unwrap();
+        assert_eq!(ctx.time(), 4.0);
     }
 
     #[test]
@@ -553,30 +10120,353 @@ mod tests {
         let ctx = Rc::new(Context::<TestMessage>::new());
         let ctx2 = ctx.clone();
         let mut s = Simulation::new(ctx.clone());
-        s.create_process(1, Box::new(move || {
+        s.create_process(ProcessId(1), Box::new(move || {
             yield Effect::Wait;
             println!("process #1: time {}", ctx.time());
 
             assert_eq!(ctx.time(), 1.2);
 
-            let m1 = ctx.pop_message(1);
+            let m1 = ctx.pop_message(ProcessId(1));
             assert_eq!(m1.expect("message expected"), TestMessage::MessageType2("hello there"));
-            let m2 = ctx.pop_message(1);
+            let m2 = ctx.pop_message(ProcessId(1));
             assert!(m2.is_none());
         }));
 
-        s.create_process(2, Box::new(move || {
+        s.create_process(ProcessId(2), Box::new(move || {
             yield Effect::TimeOut(1.0);
             println!("{}: sending message to process #1", ctx2.time());
-            yield Effect::SendMessage(1, TestMessage::MessageType2("hello there"), 0.2);
+            yield Effect::SendMessage(ProcessId(1), TestMessage::MessageType2("hello there"), 0.2);
+        }));
+
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        s.step().unwrap();
+        s.step().unwrap();
+        s.step().unwrap();
+        s.step().unwrap();
+        s.step().unwrap();
+    }
+
+    #[test]
+    fn messages_delivered_in_time_order() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let ctx1 = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::SendMessage(ProcessId(2), TestMessage::MessageType2("slow"), 3.0);
+            yield Effect::SendMessage(ProcessId(2), TestMessage::MessageType2("fast"), 1.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::Wait;
+            assert_eq!(ctx1.time(), 1.0);
+            assert_eq!(ctx1.pop_message(ProcessId(2)), Some(TestMessage::MessageType2("fast")));
+            assert!(ctx1.pop_message(ProcessId(2)).is_none());
+            yield Effect::Wait;
+            assert_eq!(ctx1.time(), 3.0);
+            assert_eq!(ctx1.pop_message(ProcessId(2)), Some(TestMessage::MessageType2("slow")));
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.completed_process_count(), 2);
+    }
+
+    #[test]
+    fn owned_message_payload() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<String>::new());
+        let ctx1 = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(|| {
+            let text = vec!["owned", "payload"].join(" ");
+            yield Effect::SendMessage(ProcessId(2), text, 1.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::WaitForMessage;
+            assert_eq!(ctx1.pop_message(ProcessId(2)), Some("owned payload".to_string()));
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.completed_process_count(), 2);
+    }
+
+    #[test]
+    fn drain_messages() {
+        let ctx = Context::<TestMessage>::new();
+        ctx.push_message(ProcessId(1), TestMessage::MessageType2("first"));
+        ctx.push_message(ProcessId(1), TestMessage::MessageType1);
+        ctx.push_message(ProcessId(1), TestMessage::MessageType2("third"));
+        assert_eq!(ctx.message_count(ProcessId(1)), 3);
+        assert_eq!(ctx.inbox_len(ProcessId(1)), 3);
+        assert_eq!(ctx.drain_messages(ProcessId(1)).collect::<Vec<_>>(), vec![
+            TestMessage::MessageType2("first"),
+            TestMessage::MessageType1,
+            TestMessage::MessageType2("third"),
+        ]);
+        assert_eq!(ctx.message_count(ProcessId(1)), 0);
+        assert_eq!(ctx.inbox_len(ProcessId(1)), 0);
+        assert!(ctx.pop_message(ProcessId(1)).is_none());
+        assert_eq!(ctx.drain_messages(ProcessId(2)).count(), 0);
+    }
+
+    #[test]
+    fn message_priority() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let ctx1 = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::SendMessagePriority(ProcessId(2), TestMessage::MessageType2("low"), 0.0, 5);
+            yield Effect::SendMessagePriority(ProcessId(2), TestMessage::MessageType2("high"), 0.0, 1);
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            // resumed by each message
+            while ctx1.message_count(ProcessId(2)) < 2 {
+                yield Effect::Wait;
+            }
+            assert_eq!(ctx1.pop_message(ProcessId(2)), Some(TestMessage::MessageType2("high")));
+            assert_eq!(ctx1.pop_message(ProcessId(2)), Some(TestMessage::MessageType2("low")));
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.completed_process_count(), 2);
+
+        // FIFO among messages with the same priority
+        ctx.push_message_with_priority(ProcessId(3), TestMessage::MessageType2("first"), 1);
+        ctx.push_message(ProcessId(3), TestMessage::MessageType1);
+        ctx.push_message_with_priority(ProcessId(3), TestMessage::MessageType2("second"), 1);
+        assert_eq!(ctx.drain_messages(ProcessId(3)).collect::<Vec<_>>(), vec![
+            TestMessage::MessageType1,
+            TestMessage::MessageType2("first"),
+            TestMessage::MessageType2("second"),
+        ]);
+    }
+
+    #[test]
+    fn send_message_now() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use SchedulingPolicy;
+        use EndCondition::NoEvents;
+
+        let order = |now, policy| {
+            let ctx = Rc::new(Context::<TestMessage>::new());
+            let mut s = Simulation::new(ctx.clone());
+            s.set_scheduling_policy(policy);
+            let c = ctx.clone();
+            s.create_process(ProcessId(1), Box::new(move || {
+                yield Effect::WaitForMessage;
+                assert_eq!(c.pop_message(ProcessId(1)), Some(TestMessage::MessageType2("now")));
+                c.record("order", 1.0);
+            }));
+            let c = ctx.clone();
+            s.create_process(ProcessId(0), Box::new(move || {
+                let message = TestMessage::MessageType2("now");
+                if now {
+                    yield Effect::SendMessageNow(ProcessId(1), message);
+                } else {
+                    yield Effect::SendMessage(ProcessId(1), message, 0.0);
+                }
+                c.record("order", 0.0);
+            }));
+            // the recipient has not run yet in the round of the sender
+            s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+            s.schedule_event(Event{time: 1.0, process: ProcessId(0), label: None}).unwrap();
+            let s = s.run(NoEvents);
+            assert_eq!(s.now(), 1.0);
+            assert_eq!(s.completed_process_count(), 2);
+            s.series("order").iter().map(|&(_, v)| v).collect::<Vec<f64>>()
+        };
+        assert_eq!(order(true, SchedulingPolicy::Fifo), vec![0.0, 1.0]);
+        assert_eq!(order(false, SchedulingPolicy::Fifo), vec![1.0, 0.0]);
+        assert_eq!(order(true, SchedulingPolicy::RoundRobin), vec![0.0, 1.0]);
+        assert_eq!(order(false, SchedulingPolicy::RoundRobin), vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn wait_for_message() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let ctx1 = ctx.clone();
+        let mut s = Simulation::new(ctx.clone());
+        s.create_process(ProcessId(1), Box::new(move || {
+            // the first message is already in the inbox
+            yield Effect::WaitForMessage;
+            assert_eq!(ctx1.time(), 0.0);
+            assert_eq!(ctx1.pop_message(ProcessId(1)), Some(TestMessage::MessageType1));
+            // the second one is sent later by process 2
+            yield Effect::WaitForMessage;
+            assert_eq!(ctx1.time(), 3.0);
+            assert_eq!(ctx1.pop_message(ProcessId(1)), Some(TestMessage::MessageType2("late")));
+        }));
+        s.create_process(ProcessId(2), Box::new(move || {
+            yield Effect::TimeOut(3.0);
+            yield Effect::SendMessage(ProcessId(1), TestMessage::MessageType2("late"), 0.0);
+        }));
+
+        ctx.push_message(ProcessId(1), TestMessage::MessageType1);
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.now(), 3.0);
+        assert_eq!(ctx.message_count(ProcessId(1)), 0);
+    }
+
+    #[test]
+    fn is_idle() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        assert!(s.is_idle());
+        s.create_process(ProcessId(0), Box::new(|| {
+            yield Effect::Wait;
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(0), label: None}).unwrap();
+        assert!(!s.is_idle());
+        let mut s = s.run(NoEvents);
+        // waiting to be woken up: idle but not finished
+        assert!(s.is_idle());
+        assert!(s.is_deadlocked());
+        assert_eq!(s.step(), Ok(None));
+        s.wake_process(ProcessId(0)).unwrap();
+        assert!(!s.is_idle());
+        let s = s.run(NoEvents);
+        // finished
+        assert!(s.is_idle());
+        assert!(!s.is_deadlocked());
+        assert_eq!(s.completed_process_count(), 1);
+    }
+
+    #[test]
+    fn process_data() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use EndCondition::NoEvents;
+
+        #[derive(Debug, PartialEq)]
+        struct Customer {
+            class: usize,
+        }
+
+        let ctx = Rc::new(Context::<TestMessage>::new());
+        let mut s = Simulation::new(ctx.clone());
+        for i in 1..4 {
+            s.create_process(ProcessId(i), Box::new(|| {
+                yield Effect::TimeOut(1.0);
+            }));
+            s.set_process_data(ProcessId(i), Customer { class: i % 2 });
+            s.schedule_event(Event{time: i as f64, process: ProcessId(i), label: None}).unwrap();
+        }
+        // routes the customers by class
+        let c = ctx.clone();
+        s.create_process(ProcessId(0), Box::new(move || {
+            for i in 1..4 {
+                yield Effect::TimeOut(1.0);
+                let customer = c.process_data::<Customer>(ProcessId(i)).unwrap();
+                c.record("class", customer.class as f64);
+            }
+        }));
+        s.schedule_event(Event{time: 0.5, process: ProcessId(0), label: None}).unwrap();
+        let s = s.run(NoEvents);
+        assert_eq!(s.series("class"), vec![(1.5, 1.0), (2.5, 0.0), (3.5, 1.0)]);
+        assert_eq!(*s.process_data::<Customer>(ProcessId(2)).unwrap(), Customer { class: 0 });
+        assert!(s.process_data::<usize>(ProcessId(2)).is_none());
+        assert!(s.process_data::<Customer>(ProcessId(0)).is_none());
+    }
+
+    #[test]
+    fn deadlock() {
+        use Simulation;
+        use Effect;
+        use Event;
+        use WaitReason;
+        use EndCondition::NoEvents;
+        use std::cell::Cell;
+
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        // each process waits for a message the other never sends
+        s.create_process(ProcessId(1), Box::new(|| {
+            yield Effect::WaitForMessage;
+            yield Effect::SendMessage(ProcessId(2), TestMessage::MessageType1, 0.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::WaitForMessage;
+            yield Effect::SendMessage(ProcessId(1), TestMessage::MessageType1, 0.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 0.0, process: ProcessId(2), label: None}).unwrap();
+        let detected = Rc::new(Cell::new(false));
+        let d = detected.clone();
+        s.on_deadlock(Box::new(move |stuck| {
+            assert_eq!(stuck, &[(ProcessId(1), WaitReason::Message), (ProcessId(2), WaitReason::Message)]);
+            d.set(true);
         }));
+        let s = s.run(NoEvents);
+        assert!(s.is_deadlocked());
+        assert!(detected.get());
+    }
+
+    #[test]
+    fn validate() {
+        use Simulation;
+        use QueueDiscipline;
+        use Effect;
+        use Event;
+        use ValidationKind;
+        use EndCondition::NoEvents;
 
-        s.schedule_event(Event{time: 0.0, process: 1});
-        s.schedule_event(Event{time: 0.0, process: 2});
-        s.step();
-        s.step();
-        s.step();
-        s.step();
-        s.step();
+        let mut s = Simulation::new(Rc::new(Context::<TestMessage>::new()));
+        s.enable_hold_tracking();
+        let r = s.create_resource(1, QueueDiscipline::Fifo);
+        let empty = s.create_resource(0, QueueDiscipline::Fifo);
+        s.create_process(ProcessId(1), Box::new(move || {
+            yield Effect::Request(r);
+            yield Effect::TimeOut(1.0);
+        }));
+        s.create_process(ProcessId(2), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        s.schedule_event(Event{time: 0.0, process: ProcessId(1), label: None}).unwrap();
+        s.schedule_event(Event{time: 5.0, process: ProcessId(3), label: None}).unwrap();
+        let kinds = |s: &Simulation<TestMessage>| {
+            s.validate().iter().map(|w| (w.kind, w.pid_or_rid)).collect::<Vec<_>>()
+        };
+        assert_eq!(kinds(&s), vec![
+            (ValidationKind::UnknownProcess, 3),
+            (ValidationKind::NeverScheduled, 2),
+            (ValidationKind::ZeroCapacity, empty.0),
+        ]);
+        s.create_process(ProcessId(3), Box::new(|| {
+            yield Effect::TimeOut(1.0);
+        }));
+        let s = s.run(NoEvents);
+        assert_eq!(kinds(&s), vec![
+            (ValidationKind::NeverScheduled, 2),
+            (ValidationKind::UnreleasedResource, r.0),
+            (ValidationKind::ZeroCapacity, empty.0),
+        ]);
     }
 }